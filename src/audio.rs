@@ -0,0 +1,311 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Turns the state of the [CPU]'s sound timer into PCM samples
+//!
+//! The [Synth] renders one or more 60Hz frames of audio at a time,
+//! and hands the samples off to a [Sink]: a [WavSink] records them to a file,
+//! and a [PcmSink] streams them out as they're made, to be played live.
+//!
+//! If an XO-Chip program has loaded an audio pattern with `F002`, the pattern
+//! is played instead of the beep, at the [CPU::playback_rate] set with `Fx3A`.
+//! The CPU keeps track of whether the last frame made a sound, and how far the pattern
+//! had played by then ([CPU::pattern_phase]), so save states and rewinding pick it up
+//! where it was. Render each frame after running it, as its sound timer ticks at the end.
+
+use crate::{
+    cpu::CPU,
+    error::{Error, Result},
+};
+use std::{f64::consts::TAU, io::Write, path::Path, str::FromStr};
+
+/// The rate at which the sound timer counts down, in Hz
+pub const FRAME_RATE: f64 = 60.0;
+
+/// Selects the shape of the tone played while the sound timer is active
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Waveform {
+    /// A square wave with 50% duty cycle
+    #[default]
+    Square,
+    /// A triangle wave
+    Triangle,
+    /// A rising sawtooth wave
+    Sawtooth,
+    /// A pure sine wave
+    Sine,
+}
+
+impl Waveform {
+    /// Gets the value of the waveform at `phase`, in the range `-1.0..=1.0`
+    ///
+    /// `phase` is measured in cycles, and must be in the range `0.0..1.0`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::audio::Waveform;
+    /// assert_eq!(1.0, Waveform::Square.sample(0.25));
+    /// assert_eq!(-1.0, Waveform::Square.sample(0.75));
+    /// ```
+    pub fn sample(&self, phase: f64) -> f64 {
+        match self {
+            Waveform::Square if phase < 0.5 => 1.0,
+            Waveform::Square => -1.0,
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sawtooth => 2.0 * phase - 1.0,
+            Waveform::Sine => (TAU * phase).sin(),
+        }
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" | "saw" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(Error::InvalidWaveform {
+                waveform: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// Accepts blocks of mono samples, in the range `-1.0..=1.0`
+pub trait Sink {
+    /// Accepts a block of mono samples, in the range `-1.0..=1.0`
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()>;
+}
+
+impl Sink for Vec<f32> {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        self.extend_from_slice(samples);
+        Ok(())
+    }
+}
+
+/// Collects samples in memory, to be written out as a 16-bit PCM WAV file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct WavSink {
+    sample_rate: u32,
+    samples: Vec<i16>,
+}
+
+impl WavSink {
+    /// Constructs a new, empty [WavSink]
+    pub fn new(sample_rate: u32) -> Self {
+        WavSink {
+            sample_rate,
+            samples: vec![],
+        }
+    }
+
+    /// Gets the samples collected so far
+    pub fn samples(&self) -> &[i16] {
+        &self.samples
+    }
+
+    /// Encodes the collected samples as a mono, 16-bit PCM WAV file
+    /// # Examples
+    /// ```rust
+    /// # use chirp::audio::WavSink;
+    /// let wav = WavSink::new(48000).to_bytes();
+    /// assert_eq!(b"RIFF", &wav[0..4]);
+    /// assert_eq!(44, wav.len());
+    /// ```
+    pub fn to_bytes(&self) -> Vec<u8> {
+        const CHANNELS: u16 = 1;
+        const BITS: u16 = 16;
+        let block_align = CHANNELS * BITS / 8;
+        let data_len = (self.samples.len() * block_align as usize) as u32;
+        let mut wav = Vec::with_capacity(44 + data_len as usize);
+        wav.extend_from_slice(b"RIFF");
+        wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+        wav.extend_from_slice(b"WAVE");
+        // Format chunk
+        wav.extend_from_slice(b"fmt ");
+        wav.extend_from_slice(&16u32.to_le_bytes());
+        wav.extend_from_slice(&1u16.to_le_bytes()); // PCM
+        wav.extend_from_slice(&CHANNELS.to_le_bytes());
+        wav.extend_from_slice(&self.sample_rate.to_le_bytes());
+        wav.extend_from_slice(&(self.sample_rate * block_align as u32).to_le_bytes());
+        wav.extend_from_slice(&block_align.to_le_bytes());
+        wav.extend_from_slice(&BITS.to_le_bytes());
+        // Data chunk
+        wav.extend_from_slice(b"data");
+        wav.extend_from_slice(&data_len.to_le_bytes());
+        for sample in &self.samples {
+            wav.extend_from_slice(&sample.to_le_bytes());
+        }
+        wav
+    }
+
+    /// Writes the collected samples to a WAV file at `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, self.to_bytes())?;
+        Ok(())
+    }
+}
+
+impl Sink for WavSink {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        self.samples.extend(samples.iter().copied().map(pcm));
+        Ok(())
+    }
+}
+
+/// Streams samples as raw, mono, 16-bit little-endian PCM, as soon as they're rendered
+///
+/// Writing into a pipe to a player (like `aplay -f S16_LE -r 48000`) plays the sound live.
+#[derive(Debug)]
+pub struct PcmSink<W: Write> {
+    out: W,
+}
+
+impl<W: Write> PcmSink<W> {
+    /// Constructs a new [PcmSink], writing to `out`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::audio::{PcmSink, Sink};
+    /// # fn main() -> chirp::error::Result<()> {
+    /// let mut sink = PcmSink::new(vec![]);
+    /// sink.write_samples(&[1.0, -1.0])?;
+    /// assert_eq!([0xff, 0x7f, 0x01, 0x80], sink.into_inner()[..]);
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn new(out: W) -> Self {
+        PcmSink { out }
+    }
+
+    /// Gets back the writer
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+}
+
+impl<W: Write> Sink for PcmSink<W> {
+    fn write_samples(&mut self, samples: &[f32]) -> Result<()> {
+        let bytes: Vec<u8> = samples
+            .iter()
+            .flat_map(|&sample| pcm(sample).to_le_bytes())
+            .collect();
+        self.out.write_all(&bytes)?;
+        self.out.flush()?;
+        Ok(())
+    }
+}
+
+/// Converts a sample in the range `-1.0..=1.0` to 16-bit PCM
+fn pcm(sample: f32) -> i16 {
+    (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16
+}
+
+/// The number of bits in an XO-Chip audio pattern
+pub(crate) const PATTERN_BITS: f64 = 128.0;
+
+/// Renders the [CPU]'s sound timer as a tone, or as an XO-Chip audio pattern
+#[derive(Clone, Debug, PartialEq)]
+pub struct Synth {
+    /// Number of samples per second
+    pub sample_rate: u32,
+    /// Frequency of the beep, in Hz
+    pub frequency: f64,
+    /// Shape of the beep
+    pub waveform: Waveform,
+    /// Amplitude of the beep, from 0.0 to 1.0
+    pub volume: f32,
+    // Position within the current cycle of the beep's waveform
+    phase: f64,
+    // Fraction of a sample left over from the last frame
+    remainder: f64,
+}
+
+impl Synth {
+    /// Constructs a new [Synth] with a 440Hz square wave
+    pub fn new(sample_rate: u32) -> Self {
+        Synth {
+            sample_rate,
+            ..Default::default()
+        }
+    }
+
+    /// Renders `frames` 60Hz frames of audio into `sink`, starting with the frame the [CPU] just ran
+    ///
+    /// The tone, or audio pattern, plays if the sound timer was running during the last
+    /// frame the CPU ran. The position within the beep's waveform is kept between calls,
+    /// and the pattern starts from [CPU::pattern_phase], so consecutive frames join up seamlessly.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, audio::Synth};
+    /// # fn main() -> Result<()> {
+    /// let (mut synth, mut samples) = (Synth::new(48000), vec![]);
+    /// synth.render(&CPU::default(), 1, &mut samples)?;
+    /// assert_eq!(800, samples.len());
+    /// assert!(samples.iter().all(|&sample| sample == 0.0));
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn render(&mut self, cpu: &CPU, frames: usize, sink: &mut impl Sink) -> Result<()> {
+        // Carry partial samples over to the next frame, so the rate doesn't drift
+        self.remainder += self.sample_rate as f64 * frames as f64 / FRAME_RATE;
+        let len = self.remainder as usize;
+        self.remainder -= len as f64;
+
+        let samples = match (cpu.pattern_phase(), cpu.pattern()) {
+            // Patterns loop once every 128 bits, starting where the CPU had got to
+            (Some(mut phase), Some(pattern)) => {
+                let step = cpu.playback_rate() / PATTERN_BITS / self.sample_rate as f64;
+                (0..len)
+                    .map(|_| {
+                        let sample = Self::pattern_sample(pattern, phase) as f32 * self.volume;
+                        phase = (phase + step).fract();
+                        sample
+                    })
+                    .collect()
+            }
+            (Some(_), None) => {
+                let step = self.frequency / self.sample_rate as f64;
+                (0..len)
+                    .map(|_| {
+                        let sample = self.waveform.sample(self.phase) as f32 * self.volume;
+                        self.phase = (self.phase + step).fract();
+                        sample
+                    })
+                    .collect()
+            }
+            (None, _) => {
+                // Start every beep at the same point in the waveform
+                self.phase = 0.0;
+                vec![0.0; len]
+            }
+        };
+        sink.write_samples(&samples)
+    }
+
+    /// Gets the value of a 1-bit audio pattern at `phase`, in the range `-1.0..=1.0`
+    ///
+    /// `phase` is measured in loops of the pattern, and must be in the range `0.0..1.0`
+    fn pattern_sample(pattern: &[u8; 16], phase: f64) -> f64 {
+        let bit = (phase * PATTERN_BITS) as usize;
+        match pattern[bit / 8] & (0x80 >> (bit % 8)) {
+            0 => -1.0,
+            _ => 1.0,
+        }
+    }
+}
+
+impl Default for Synth {
+    fn default() -> Self {
+        Synth {
+            sample_rate: 48000,
+            frequency: 440.0,
+            waveform: Waveform::default(),
+            volume: 0.25,
+            phase: 0.0,
+            remainder: 0.0,
+        }
+    }
+}
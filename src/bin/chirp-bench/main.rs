@@ -0,0 +1,102 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Measures how many instructions per second Chirp can run, without a UI
+
+use chirp::{error::Result, *};
+use gumdrop::*;
+use std::{fs::read, path::PathBuf, time::Instant};
+
+/// A small workload, mixing arithmetic, calls, skips and memory accesses
+///
+/// This doesn't draw, since waiting for the display would leave the CPU idle.
+#[rustfmt::skip]
+const WORKLOAD: &[u8] = &[
+    0x60, 0x00, // 200: mov   #00, v0
+    0x61, 0x00, // 202: mov   #00, v1
+    0x70, 0x01, // 204: add   #01, v0
+    0x81, 0x04, // 206: add   v0, v1
+    0x22, 0x10, // 208: call  210
+    0x30, 0x00, // 20a: se    #00, v0
+    0x12, 0x04, // 20c: jmp   204
+    0x12, 0x00, // 20e: jmp   200
+    0xa3, 0x00, // 210: mov   $300, I
+    0xf1, 0x1e, // 212: add   v1, I
+    0x82, 0x06, // 214: shr   v0, v2
+    0xf2, 0x33, // 216: bcd   v2, I
+    0x00, 0xee, // 218: ret
+];
+
+fn main() -> Result<()> {
+    let options = Arguments::parse_args_default_or_exit();
+    let platform = options.platform.unwrap_or_default();
+    let rom = match &options.file {
+        Some(file) => read(file)?,
+        None => WORKLOAD.to_vec(),
+    };
+    let map = platform.memory_map();
+    let steps = options.steps.unwrap_or(100_000);
+    let frames = options.frames.unwrap_or(100);
+
+    let mut best = 0.0f64;
+    for run in 1..=options.runs.unwrap_or(5) {
+        let mut bus = map
+            .bus()
+            .load_region(Charset, BigFont::default().charset())
+            .load_region(Program, &rom);
+        let mut cpu = CPU::new(
+            map.screen.start,
+            map.charset.start as u16,
+            map.program.start as u16,
+            map.stack_top(),
+            Dis::default(),
+            vec![],
+            Flags {
+                quirks: platform.quirks(),
+                stack_depth: platform.stack_depth(),
+                mode: platform.mode(),
+                speed: steps,
+                idle_skip: options.idle_skip,
+                ..Default::default()
+            },
+        );
+        cpu.seed(0);
+
+        let start = Instant::now();
+        for _ in 0..frames {
+            cpu.run_frame(&mut bus)?;
+        }
+        let elapsed = start.elapsed().as_secs_f64();
+        let mips = cpu.cycle() as f64 / elapsed / 1_000_000.0;
+        best = best.max(mips);
+        println!(
+            "run {run}: {} instructions in {elapsed:.3}s ({mips:.2} MIPS)",
+            cpu.cycle()
+        );
+    }
+    println!("best: {best:.2} MIPS");
+    Ok(())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Options, Hash)]
+struct Arguments {
+    #[options(help = "Show help text")]
+    help: bool,
+    #[options(help = "Benchmark a ROM, instead of the built-in workload", free)]
+    pub file: Option<PathBuf>,
+    #[options(
+        help = "Emulate a platform (vip, chip-48, schip-legacy, schip-modern, xo-chip, octo)"
+    )]
+    pub platform: Option<Platform>,
+    #[options(help = "Number of instructions to run per frame (default 100000)")]
+    pub steps: Option<usize>,
+    #[options(help = "Number of frames to run (default 100)")]
+    pub frames: Option<usize>,
+    #[options(help = "Number of times to repeat the benchmark (default 5)")]
+    pub runs: Option<usize>,
+    #[options(
+        help = "Fast-forward busy-wait loops to the end of each frame",
+        no_short
+    )]
+    pub idle_skip: bool,
+}
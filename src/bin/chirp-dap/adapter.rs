@@ -0,0 +1,532 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Answers Debug Adapter Protocol requests, by driving a [Chip8] and a [Debugger]
+//!
+//! Breakpoints can't be set in assembly source, since a ROM doesn't say where it came
+//! from. Instead, the adapter serves a disassembly of the ROM as a source of its own,
+//! with one instruction per line, and breakpoints are set there.
+
+use chirp::{
+    cpu::breakpoint::{Breakpoint, Trigger},
+    debugger::{disassemble, number, Debugger},
+    error::{Error, Result},
+    *,
+};
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+/// The `sourceReference` of the disassembly
+const DISASSEMBLY: i64 = 1;
+/// The only thread
+const THREAD: i64 = 1;
+/// The `variablesReference` of the registers
+const REGISTERS: i64 = 1;
+/// The `variablesReference` of the keypad
+const KEYS: i64 = 2;
+
+/// Reads one message, or [None] once the input is closed
+pub fn read_message(input: &mut impl BufRead) -> Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        match header.trim_end() {
+            "" if length.is_some() => break,
+            "" => {}
+            header => {
+                if let Some(len) = header.strip_prefix("Content-Length:") {
+                    length = len.trim().parse::<usize>().ok();
+                }
+            }
+        }
+    }
+    let mut body = vec![0; length.unwrap_or_default()];
+    input.read_exact(&mut body)?;
+    Ok(Some(
+        serde_json::from_slice(&body).map_err(io::Error::from)?,
+    ))
+}
+
+/// Writes one message, with its header
+pub fn write_message(out: &mut impl Write, message: &Value) -> Result<()> {
+    let body = message.to_string();
+    write!(out, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    out.flush()?;
+    Ok(())
+}
+
+/// Debugs one [Chip8], for one client
+#[derive(Debug)]
+pub struct Adapter<W: Write> {
+    out: W,
+    seq: i64,
+    ch8: Option<Chip8>,
+    debugger: Debugger,
+    // The name of the ROM, and the address of each line of its disassembly
+    name: String,
+    lines: Vec<usize>,
+    // The breakpoints set in the disassembly
+    breakpoints: Vec<usize>,
+    stop_on_entry: bool,
+    // Whether the machine is running because of a step, rather than a `continue`
+    stepping: bool,
+}
+
+impl<W: Write> Adapter<W> {
+    /// Constructs an [Adapter] which writes its messages to `out`
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            seq: 0,
+            ch8: None,
+            debugger: Debugger::new(),
+            name: String::new(),
+            lines: vec![],
+            breakpoints: vec![],
+            stop_on_entry: false,
+            stepping: false,
+        }
+    }
+
+    /// Returns true if the machine is waiting to [run a frame](Adapter::run_frame)
+    pub fn is_running(&self) -> bool {
+        self.ch8.as_ref().is_some_and(|ch8| !ch8.cpu.flags.pause)
+    }
+
+    /// Runs one frame, and tells the client if the machine stopped
+    pub fn run_frame(&mut self) -> Result<()> {
+        let Some(ch8) = &mut self.ch8 else {
+            return Ok(());
+        };
+        match ch8.cpu.run_frame(&mut ch8.bus).map(|_| ()) {
+            Err(e) => self.stopped(Some(e)),
+            // Nobody can press a key, except by setting a variable
+            Ok(()) if ch8.cpu.flags.keypause || ch8.cpu.flags.pause => self.stopped(None),
+            Ok(()) => Ok(()),
+        }
+    }
+
+    /// Handles one request. Returns false when the client disconnects.
+    pub fn handle(&mut self, request: &Value) -> Result<bool> {
+        if request["type"] != "request" {
+            return Ok(true);
+        }
+        let command = request["command"].as_str().unwrap_or_default();
+        let args = &request["arguments"];
+        let result = match command {
+            "initialize" => {
+                self.respond(
+                    request,
+                    json!({
+                        "supportsConfigurationDoneRequest": true,
+                        "supportsConditionalBreakpoints": true,
+                        "supportsHitConditionalBreakpoints": true,
+                        "supportsSetVariable": true,
+                        "supportsReadMemoryRequest": true,
+                    }),
+                )?;
+                return self.event("initialized", json!({})).map(|_| true);
+            }
+            "launch" => self.launch(args),
+            "disconnect" | "terminate" => {
+                self.respond(request, json!({}))?;
+                return Ok(false);
+            }
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD, "name": "CPU" }] })),
+            "source" => match args["sourceReference"].as_i64() {
+                Some(DISASSEMBLY) => self.disassembly(),
+                _ => Err("There's no such source".to_string()),
+            },
+            "setBreakpoints" => self.set_breakpoints(args),
+            "setExceptionBreakpoints" => Ok(json!({})),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => Ok(json!({ "scopes": [
+                { "name": "Registers", "presentationHint": "registers", "variablesReference": REGISTERS, "expensive": false },
+                { "name": "Keys", "variablesReference": KEYS, "expensive": false },
+            ]})),
+            "variables" => self.variables(args),
+            "setVariable" => self.set_variable(args),
+            "readMemory" => self.read_memory(args),
+            // Anything which runs the machine answers before it stops
+            "configurationDone" | "continue" | "next" | "stepIn" | "stepOut" | "pause" => {
+                match &self.ch8 {
+                    Some(_) => {
+                        let body = match command {
+                            "continue" => json!({ "allThreadsContinued": true }),
+                            _ => json!({}),
+                        };
+                        self.respond(request, body)?;
+                        self.resume(command)?;
+                        return Ok(true);
+                    }
+                    None => Err("Nothing has been launched".to_string()),
+                }
+            }
+            command => Err(format!("Unsupported request: {command}")),
+        };
+        match result {
+            Ok(body) => self.respond(request, body)?,
+            Err(message) => self.fail(request, &message)?,
+        }
+        Ok(true)
+    }
+
+    /// Loads the ROM at `program`, paused until the client is done configuring
+    fn launch(&mut self, args: &Value) -> std::result::Result<Value, String> {
+        let Some(program) = args["program"].as_str() else {
+            return Err("Launching needs a `program` to run".to_string());
+        };
+        let platform: Platform = match args["platform"].as_str() {
+            Some(platform) => platform.parse().map_err(|e: Error| e.to_string())?,
+            None => Platform::default(),
+        };
+        let rom = std::fs::read(program).map_err(|e| format!("{program}: {e}"))?;
+        let mode = platform.mode();
+        let map = platform.memory_map();
+        let bus = map
+            .bus()
+            .load_region(Charset, BigFont::default().charset())
+            .load_region(Program, &rom);
+        let cpu = CPU::new(
+            map.screen.start,
+            map.charset.start as u16,
+            map.program.start as u16,
+            map.stack_top(),
+            Dis::default(),
+            vec![],
+            Flags {
+                quirks: platform.quirks(),
+                stack_depth: platform.stack_depth(),
+                mode,
+                pause: true,
+                speed: 8,
+                idle_skip: !args["noIdleSkip"].as_bool().unwrap_or_default(),
+                ..Default::default()
+            },
+        );
+        let ch8 = Chip8 { cpu, bus };
+        // Disassemble the ROM, one instruction per line
+        let (start, end) = (map.program.start, map.program.start + rom.len());
+        self.lines = std::iter::successors(Some(start), |&addr| {
+            Some(addr + disassemble(&ch8, addr).0).filter(|&next| next < end)
+        })
+        .take_while(|&addr| addr < end)
+        .collect();
+        self.name = Path::new(program)
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| program.to_string());
+        self.stop_on_entry = args["stopOnEntry"].as_bool().unwrap_or_default();
+        self.ch8 = Some(ch8);
+        Ok(json!({}))
+    }
+
+    /// Runs the machine, for `configurationDone`, `continue`, `pause` and the steps
+    fn resume(&mut self, command: &str) -> Result<()> {
+        let Some(ch8) = &mut self.ch8 else {
+            return Ok(());
+        };
+        let line = match command {
+            "configurationDone" if self.stop_on_entry => return self.event_stopped("entry", None),
+            "configurationDone" | "continue" => "continue",
+            "pause" => {
+                ch8.cpu.flags.pause = true;
+                return self.event_stopped("pause", None);
+            }
+            "next" => "next",
+            "stepIn" => "step",
+            _ => "finish",
+        };
+        // The debugger's output would only get in the way of the protocol
+        self.debugger.run(line, ch8, &mut io::sink())?;
+        self.stepping = line != "continue";
+        // Single steps, and steps which can't go anywhere, are already done
+        if self.stepping && ch8.cpu.flags.pause {
+            self.stepping = false;
+            return self.event_stopped("step", None);
+        }
+        Ok(())
+    }
+
+    /// Tells the client why the machine stopped
+    fn stopped(&mut self, error: Option<Error>) -> Result<()> {
+        let Some(ch8) = &mut self.ch8 else {
+            return Ok(());
+        };
+        let stepping = std::mem::take(&mut self.stepping);
+        let (reason, text) = match &error {
+            Some(Error::BreakpointHit {
+                trigger: Trigger::Breakpoint { id },
+                ..
+            }) => match id {
+                Some(id) if self.breakpoints.contains(id) => ("breakpoint", None),
+                None => ("breakpoint", None),
+                // `next` and `finish` stop with a breakpoint of their own
+                Some(_) => ("step", None),
+            },
+            Some(e @ Error::BreakpointHit { .. }) => ("data breakpoint", Some(e.to_string())),
+            Some(e) => ("exception", Some(e.to_string())),
+            None if ch8.cpu.flags.keypause => ("pause", Some("Waiting for a key".to_string())),
+            None if stepping => ("step", None),
+            None => ("pause", Some("Halted".to_string())),
+        };
+        self.debugger.stopped(ch8, error, &mut io::sink())?;
+        self.event_stopped(reason, text)
+    }
+
+    /// Lists the disassembly, one instruction per line
+    fn disassembly(&self) -> std::result::Result<Value, String> {
+        let ch8 = self.machine()?;
+        let content: Vec<String> = self
+            .lines
+            .iter()
+            .map(|&addr| format!("{addr:03x}: {}", disassemble(ch8, addr).1))
+            .collect();
+        Ok(json!({ "content": content.join("\n") }))
+    }
+
+    /// Replaces every breakpoint in the disassembly
+    fn set_breakpoints(&mut self, args: &Value) -> std::result::Result<Value, String> {
+        let requested = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        if args["source"]["sourceReference"].as_i64() != Some(DISASSEMBLY) {
+            let unverified = json!({
+                "verified": false,
+                "message": "Breakpoints can only be set in the disassembly",
+            });
+            return Ok(json!({ "breakpoints": vec![unverified; requested.len()] }));
+        }
+        let source = self.source();
+        let Some(ch8) = &mut self.ch8 else {
+            return Err("Nothing has been launched".to_string());
+        };
+        for id in self.breakpoints.drain(..) {
+            ch8.cpu.remove_breakpoint(id);
+        }
+        let mut breakpoints = vec![];
+        for requested in requested {
+            let line = requested["line"].as_u64().unwrap_or_default() as usize;
+            let breakpoint = match line.checked_sub(1).and_then(|line| self.lines.get(line)) {
+                Some(&addr) => breakpoint(addr, &requested),
+                None => Err("There's no instruction on this line".to_string()),
+            };
+            breakpoints.push(match breakpoint {
+                Ok(breakpoint) => {
+                    let id = ch8.cpu.add_breakpoint(breakpoint);
+                    self.breakpoints.push(id);
+                    json!({ "id": id, "verified": true, "line": line, "source": source })
+                }
+                Err(message) => json!({ "verified": false, "line": line, "message": message }),
+            });
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    /// Lists the pc, then the caller of each return address on the stack
+    fn stack_trace(&self) -> std::result::Result<Value, String> {
+        let ch8 = self.machine()?;
+        let mut frames = vec![ch8.cpu.pc() as usize];
+        let sp = ch8.cpu.sp();
+        let top = ch8
+            .bus
+            .get_region_range(Stack)
+            .map_or(0, |stack| stack.end.saturating_sub(2));
+        for slot in (sp + 2..=top).step_by(2) {
+            let Some(&[hi, lo]) = ch8.bus.get(slot..slot + 2) else {
+                break;
+            };
+            frames.push(u16::from_be_bytes([hi, lo]).wrapping_sub(2) as usize);
+        }
+        let frames: Vec<Value> = frames
+            .iter()
+            .enumerate()
+            .map(|(id, &addr)| {
+                let mut frame = json!({
+                    "id": id,
+                    "name": format!("{addr:03x}: {}", disassemble(ch8, addr).1),
+                    "line": 0,
+                    "column": 0,
+                    "instructionPointerReference": format!("{addr:#x}"),
+                });
+                if let Ok(line) = self.lines.binary_search(&addr) {
+                    frame["line"] = json!(line + 1);
+                    frame["column"] = json!(1);
+                    frame["source"] = self.source();
+                }
+                frame
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    /// Lists the registers, or the keypad
+    fn variables(&self, args: &Value) -> std::result::Result<Value, String> {
+        let cpu = &self.machine()?.cpu;
+        let variables: Vec<Value> = match args["variablesReference"].as_i64() {
+            Some(REGISTERS) => {
+                let mut registers: Vec<Value> = (cpu.v().iter().enumerate())
+                    .map(|(reg, value)| variable(&format!("V{reg:X}"), &format!("{value:#04x}")))
+                    .collect();
+                for (name, addr) in [
+                    ("I", cpu.i() as usize),
+                    ("PC", cpu.pc() as usize),
+                    ("SP", cpu.sp()),
+                ] {
+                    let mut register = variable(name, &format!("{addr:#05x}"));
+                    register["memoryReference"] = json!(format!("{addr:#x}"));
+                    registers.push(register);
+                }
+                registers.push(variable("DT", &cpu.delay().to_string()));
+                registers.push(variable("ST", &cpu.sound().to_string()));
+                registers
+            }
+            Some(KEYS) => (cpu.keys().iter().enumerate())
+                .map(|(key, held)| variable(&format!("{key:X}"), &held.to_string()))
+                .collect(),
+            _ => return Err("There's no such variable".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Sets a register, or presses or releases a key
+    fn set_variable(&mut self, args: &Value) -> std::result::Result<Value, String> {
+        let Some(ch8) = &mut self.ch8 else {
+            return Err("Nothing has been launched".to_string());
+        };
+        let cpu = &mut ch8.cpu;
+        let name = args["name"].as_str().unwrap_or_default();
+        let value = args["value"].as_str().unwrap_or_default();
+        let invalid = || format!("Can't set {name} to {value}");
+        if args["variablesReference"].as_i64() == Some(KEYS) {
+            let key = usize::from_str_radix(name, 16).map_err(|_| invalid())?;
+            let held = match value.trim() {
+                "true" | "1" => cpu.press(key),
+                "false" | "0" => cpu.release(key),
+                _ => return Err(invalid()),
+            };
+            held.map_err(|e| e.to_string())?;
+            return Ok(json!({ "value": cpu.keys()[key].to_string() }));
+        }
+        let value = number(value).ok_or_else(invalid)?;
+        match name {
+            "I" => _ = cpu.set_i(value as u16),
+            "PC" => _ = cpu.set_pc(value as u16),
+            "DT" => _ = cpu.set_delay(value as u8),
+            "ST" => _ = cpu.set_sound(value as u8),
+            name => {
+                let reg = name
+                    .strip_prefix('V')
+                    .and_then(|reg| usize::from_str_radix(reg, 16).ok())
+                    .ok_or_else(invalid)?;
+                cpu.set_v(reg, value as u8).map_err(|e| e.to_string())?;
+            }
+        }
+        Ok(json!({ "value": args["value"] }))
+    }
+
+    /// Reads memory, without touching the bus' access checks
+    fn read_memory(&self, args: &Value) -> std::result::Result<Value, String> {
+        let bus = &self.machine()?.bus;
+        let reference = args["memoryReference"].as_str().unwrap_or_default();
+        let addr = number(reference).ok_or_else(|| format!("Can't read memory at {reference}"))?;
+        let addr = addr.saturating_add_signed(args["offset"].as_i64().unwrap_or_default() as isize);
+        let count = args["count"].as_u64().unwrap_or_default() as usize;
+        let memory = bus
+            .get(addr.min(bus.len())..bus.len().min(addr.saturating_add(count)))
+            .unwrap_or_default();
+        Ok(json!({
+            "address": format!("{addr:#x}"),
+            "data": base64(memory),
+            "unreadableBytes": count - memory.len(),
+        }))
+    }
+
+    fn machine(&self) -> std::result::Result<&Chip8, String> {
+        self.ch8
+            .as_ref()
+            .ok_or_else(|| "Nothing has been launched".to_string())
+    }
+
+    fn source(&self) -> Value {
+        json!({ "name": format!("{} (disassembly)", self.name), "sourceReference": DISASSEMBLY })
+    }
+
+    fn event_stopped(&mut self, reason: &str, text: Option<String>) -> Result<()> {
+        let mut body = json!({ "reason": reason, "threadId": THREAD, "allThreadsStopped": true });
+        if let Some(text) = text {
+            body["text"] = json!(text);
+        }
+        self.event("stopped", body)
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": true,
+            "command": request["command"],
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "success": false,
+            "command": request["command"],
+            "message": message,
+        }))
+    }
+
+    fn send(&mut self, mut message: Value) -> Result<()> {
+        self.seq += 1;
+        message["seq"] = json!(self.seq);
+        write_message(&mut self.out, &message)
+    }
+}
+
+/// Builds a breakpoint at `addr`, with the `condition` and `hitCondition` the client asked for
+fn breakpoint(addr: usize, requested: &Value) -> std::result::Result<Breakpoint, String> {
+    let mut breakpoint = Breakpoint::new(addr as u16);
+    if let Some(condition) = requested["condition"].as_str() {
+        breakpoint = breakpoint.when(condition.parse().map_err(|e: Error| e.to_string())?);
+    }
+    // Stops on the nth hit
+    if let Some(hits) = requested["hitCondition"].as_str() {
+        let hits = number(hits).ok_or_else(|| format!("Invalid hit count: {hits}"))?;
+        breakpoint = breakpoint.ignoring(hits.saturating_sub(1));
+    }
+    Ok(breakpoint)
+}
+
+fn variable(name: &str, value: &str) -> Value {
+    json!({ "name": name, "value": value, "variablesReference": 0 })
+}
+
+/// Encodes bytes as base64, for `readMemory`
+fn base64(bytes: &[u8]) -> String {
+    const DIGITS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::new();
+    for chunk in bytes.chunks(3) {
+        let word = chunk.iter().enumerate().fold(0u32, |word, (index, &byte)| {
+            word | (byte as u32) << (16 - 8 * index)
+        });
+        for digit in 0..4 {
+            match digit <= chunk.len() {
+                true => out.push(DIGITS[(word >> (18 - 6 * digit) & 0x3f) as usize] as char),
+                false => out.push('='),
+            }
+        }
+    }
+    out
+}
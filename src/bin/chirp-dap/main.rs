@@ -0,0 +1,57 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Chirp-DAP: Debugs Chip-8 programs from an editor, over the Debug Adapter Protocol
+//!
+//! Messages are read from stdin and written to stdout, so nothing else may print there.
+//! The machine runs without a window, at 60 frames per second.
+
+mod adapter;
+#[cfg(test)]
+mod tests;
+
+use adapter::{read_message, Adapter};
+use chirp::{
+    clock::{Clock, Realtime},
+    error::Result,
+};
+use std::{
+    io::{stdin, stdout},
+    sync::mpsc::{channel, TryRecvError},
+    thread,
+};
+
+fn main() -> Result<()> {
+    // Requests are read on their own thread, so the machine keeps running between them
+    let (send, requests) = channel();
+    thread::spawn(move || {
+        let mut stdin = stdin().lock();
+        while let Ok(Some(request)) = read_message(&mut stdin) {
+            if send.send(request).is_err() {
+                return;
+            }
+        }
+    });
+    let mut adapter = Adapter::new(stdout());
+    let mut clock = Realtime::default();
+    loop {
+        let request = match adapter.is_running() {
+            true => match requests.try_recv() {
+                Ok(request) => request,
+                Err(TryRecvError::Empty) => {
+                    clock.wait_for_frame();
+                    adapter.run_frame()?;
+                    continue;
+                }
+                Err(TryRecvError::Disconnected) => return Ok(()),
+            },
+            false => match requests.recv() {
+                Ok(request) => request,
+                Err(_) => return Ok(()),
+            },
+        };
+        if !adapter.handle(&request)? {
+            return Ok(());
+        }
+    }
+}
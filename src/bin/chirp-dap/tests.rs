@@ -0,0 +1,230 @@
+//! Tests for chirp-dap
+
+use super::adapter::*;
+use serde_json::{json, Value};
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+#[path = "../../../tests/common/mod.rs"]
+mod common;
+
+/// Collects everything the adapter sends
+#[derive(Clone, Debug, Default)]
+struct Client(Rc<RefCell<Vec<u8>>>);
+
+impl Write for Client {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Client {
+    /// Takes every message sent since the last call
+    fn messages(&self) -> Vec<Value> {
+        let sent = std::mem::take(&mut *self.0.borrow_mut());
+        let mut sent = &sent[..];
+        std::iter::from_fn(|| read_message(&mut sent).unwrap()).collect()
+    }
+}
+
+/// Launches the ROM, stopped on entry
+fn setup(name: &str) -> (Adapter<Client>, Client) {
+    let client = Client::default();
+    let mut adapter = Adapter::new(client.clone());
+    let path = std::env::temp_dir().join(format!("chirp-dap-{name}.ch8"));
+    std::fs::write(&path, common::SUBROUTINE).unwrap();
+    request(&mut adapter, "initialize", json!({}));
+    request(
+        &mut adapter,
+        "launch",
+        json!({ "program": path, "stopOnEntry": true }),
+    );
+    request(&mut adapter, "configurationDone", json!({}));
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!("entry", stopped(&client.messages()));
+    (adapter, client)
+}
+
+/// Sends a request, and runs the machine until it stops
+fn request(adapter: &mut Adapter<Client>, command: &str, arguments: Value) {
+    let request =
+        json!({ "seq": 1, "type": "request", "command": command, "arguments": arguments });
+    assert!(adapter.handle(&request).unwrap());
+    while adapter.is_running() {
+        adapter.run_frame().unwrap();
+    }
+}
+
+/// Sends a request, and gets the body of the response
+fn body(adapter: &mut Adapter<Client>, client: &Client, command: &str, arguments: Value) -> Value {
+    request(adapter, command, arguments);
+    let messages = client.messages();
+    let response = messages
+        .iter()
+        .find(|message| message["type"] == "response")
+        .unwrap();
+    assert_eq!(true, response["success"], "{response}");
+    response["body"].clone()
+}
+
+/// Gets the reason the machine stopped
+fn stopped(messages: &[Value]) -> String {
+    let event = messages
+        .iter()
+        .find(|message| message["event"] == "stopped")
+        .unwrap();
+    event["body"]["reason"].as_str().unwrap().to_string()
+}
+
+/// Gets the line each stack frame is on
+fn lines(adapter: &mut Adapter<Client>, client: &Client) -> Vec<u64> {
+    let trace = body(adapter, client, "stackTrace", json!({ "threadId": 1 }));
+    let frames = trace["stackFrames"].as_array().unwrap();
+    frames
+        .iter()
+        .map(|frame| frame["line"].as_u64().unwrap())
+        .collect()
+}
+
+#[test]
+fn initialize() {
+    let client = Client::default();
+    let mut adapter = Adapter::new(client.clone());
+    request(&mut adapter, "initialize", json!({}));
+    let messages = client.messages();
+    assert_eq!(true, messages[0]["body"]["supportsReadMemoryRequest"]);
+    assert_eq!("initialized", messages[1]["event"]);
+}
+
+#[test]
+fn breakpoints() {
+    let (mut adapter, client) = setup("breakpoints");
+    let source = json!({ "sourceReference": 1 });
+    // Line 8 is 20e, in the subroutine
+    let set = body(
+        &mut adapter,
+        &client,
+        "setBreakpoints",
+        json!({ "source": source, "breakpoints": [{ "line": 8 }, { "line": 99 }] }),
+    );
+    assert_eq!(true, set["breakpoints"][0]["verified"]);
+    assert_eq!(false, set["breakpoints"][1]["verified"]);
+    request(&mut adapter, "continue", json!({ "threadId": 1 }));
+    assert_eq!("breakpoint", stopped(&client.messages()));
+    // The subroutine, then its caller
+    assert_eq!(vec![8, 2], lines(&mut adapter, &client));
+    // Assembly files can't have breakpoints
+    let set = body(
+        &mut adapter,
+        &client,
+        "setBreakpoints",
+        json!({ "source": { "path": "test.asm" }, "breakpoints": [{ "line": 1 }] }),
+    );
+    assert_eq!(false, set["breakpoints"][0]["verified"]);
+}
+
+#[test]
+fn steps() {
+    let (mut adapter, client) = setup("steps");
+    request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+    assert_eq!("step", stopped(&client.messages()));
+    // Over the call
+    request(&mut adapter, "next", json!({ "threadId": 1 }));
+    assert_eq!("step", stopped(&client.messages()));
+    assert_eq!(vec![3], lines(&mut adapter, &client));
+    // Into, and back out of, the call
+    let (mut adapter, client) = setup("steps-out");
+    request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+    request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+    client.messages();
+    assert_eq!(vec![6, 2], lines(&mut adapter, &client));
+    request(&mut adapter, "stepOut", json!({ "threadId": 1 }));
+    assert_eq!("step", stopped(&client.messages()));
+    assert_eq!(vec![3], lines(&mut adapter, &client));
+}
+
+#[test]
+fn variables() {
+    let (mut adapter, client) = setup("variables");
+    request(&mut adapter, "stepIn", json!({ "threadId": 1 }));
+    client.messages();
+    let registers = body(
+        &mut adapter,
+        &client,
+        "variables",
+        json!({ "variablesReference": 1 }),
+    );
+    let registers = registers["variables"].as_array().unwrap();
+    assert_eq!(
+        json!({ "name": "V0", "value": "0x05", "variablesReference": 0 }),
+        registers[0]
+    );
+    assert_eq!("0x202", registers[17]["memoryReference"]);
+    body(
+        &mut adapter,
+        &client,
+        "setVariable",
+        json!({ "variablesReference": 1, "name": "VA", "value": "0x2a" }),
+    );
+    body(
+        &mut adapter,
+        &client,
+        "setVariable",
+        json!({ "variablesReference": 2, "name": "7", "value": "true" }),
+    );
+    let keys = body(
+        &mut adapter,
+        &client,
+        "variables",
+        json!({ "variablesReference": 2 }),
+    );
+    assert_eq!("true", keys["variables"][7]["value"]);
+    let registers = body(
+        &mut adapter,
+        &client,
+        "variables",
+        json!({ "variablesReference": 1 }),
+    );
+    assert_eq!("0x2a", registers["variables"][10]["value"]);
+}
+
+#[test]
+fn read_memory() {
+    let (mut adapter, client) = setup("read-memory");
+    let memory = body(
+        &mut adapter,
+        &client,
+        "readMemory",
+        json!({ "memoryReference": "0x200", "offset": 2, "count": 5 }),
+    );
+    // 22 0a 71 01 a3
+    assert_eq!(
+        json!({ "address": "0x202", "data": "IgpxAaM=", "unreadableBytes": 0 }),
+        memory
+    );
+    let memory = body(
+        &mut adapter,
+        &client,
+        "readMemory",
+        json!({ "memoryReference": "0xffffff", "count": 4 }),
+    );
+    assert_eq!(4, memory["unreadableBytes"]);
+}
+
+#[test]
+fn halt() {
+    let (mut adapter, client) = setup("halt");
+    request(&mut adapter, "continue", json!({ "threadId": 1 }));
+    assert_eq!("pause", stopped(&client.messages()));
+    assert_eq!(vec![10], lines(&mut adapter, &client));
+    let source = body(
+        &mut adapter,
+        &client,
+        "source",
+        json!({ "sourceReference": 1 }),
+    );
+    let source = source["content"].as_str().unwrap();
+    assert_eq!(Some("212: jmp    212"), source.lines().nth(9));
+}
@@ -7,20 +7,23 @@ fn main() -> Result<()> {
     let options = Arguments::parse_args_default_or_exit();
     let contents = &read(&options.file)?;
     let disassembler = Dis::default();
-    for (addr, insn) in contents[options.offset..].chunks_exact(2).enumerate() {
-        let insn = u16::from_be_bytes(
-            insn.try_into()
-                .expect("Iterated over 2-byte chunks, got <2 bytes"),
-        );
+    let mut addr = options.offset;
+    while addr + 1 < contents.len() {
+        let (len, disassembly) = disassembler.once_slice(&contents[addr..]);
+        let insn = &contents[addr..(addr + len).min(contents.len())];
         println!(
             "{}",
             format_args!(
-                "{:03x}: {} {:04x}",
-                2 * addr + 0x200 + options.offset,
-                disassembler.once(insn),
-                insn.bright_black(),
+                "{:03x}: {} {}",
+                addr + 0x200,
+                disassembly,
+                insn.iter()
+                    .map(|byte| format!("{byte:02x}"))
+                    .collect::<String>()
+                    .bright_black(),
             )
         );
+        addr += len;
     }
     Ok(())
 }
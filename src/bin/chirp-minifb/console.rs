@@ -0,0 +1,165 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! A [Debugger] console on stdin, which runs alongside the window, or without one
+//!
+//! Lines are read on their own thread, with line editing and a persistent history,
+//! so the window keeps running while the console waits for a command.
+//! Ctrl-C interrupts the machine while it runs.
+
+use chirp::{debugger::Debugger, error::Result, Chip8, Error};
+use rustyline::{error::ReadlineError, DefaultEditor};
+use std::{
+    io::stdout,
+    path::PathBuf,
+    sync::mpsc::{channel, Receiver, Sender, TryRecvError},
+    thread,
+};
+
+const PROMPT: &str = "(chirp) ";
+
+/// What the reader thread got from stdin
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Input {
+    Line(String),
+    /// Ctrl-C was pressed
+    Interrupt,
+    /// Stdin was closed
+    Closed,
+}
+
+#[derive(Debug)]
+pub struct Console {
+    debugger: Debugger,
+    /// Lines typed at the prompt
+    lines: Receiver<Input>,
+    /// Tells the reader thread to show the next prompt
+    ready: Sender<()>,
+    closed: bool,
+}
+
+impl Console {
+    /// Starts reading commands, after running the commands in `script`
+    pub fn new(script: Option<PathBuf>, history: PathBuf) -> Result<Self> {
+        let mut debugger = Debugger::new();
+        if let Some(script) = script {
+            debugger.source(script)?;
+        }
+        let (send_line, lines) = channel();
+        let (ready, wait) = channel();
+        thread::spawn(move || read_lines(history, send_line, wait));
+        let console = Console {
+            debugger,
+            lines,
+            ready,
+            closed: false,
+        };
+        console.prompt();
+        Ok(console)
+    }
+
+    /// Runs the commands typed since the last call, without waiting for more
+    ///
+    /// Returns false when asked to quit.
+    pub fn poll(&mut self, ch8: &mut Chip8) -> Result<bool> {
+        while let Some(line) = self.debugger.next_scripted(ch8) {
+            println!("{PROMPT}{line}");
+            if !self.run(&line, ch8) {
+                return Ok(false);
+            }
+        }
+        loop {
+            match self.lines.try_recv() {
+                Ok(Input::Line(line)) => {
+                    let running = self.run(&line, ch8);
+                    self.prompt();
+                    if !running {
+                        return Ok(false);
+                    }
+                }
+                Ok(Input::Interrupt) => {
+                    self.debugger.interrupt(ch8, &mut stdout())?;
+                    self.prompt();
+                }
+                // Without a console, the window can still be used
+                Ok(Input::Closed) | Err(TryRecvError::Disconnected) => {
+                    self.closed = true;
+                    return Ok(true);
+                }
+                Err(TryRecvError::Empty) => return Ok(true),
+            }
+        }
+    }
+
+    /// Waits for the next command, and runs it, for running without a window
+    ///
+    /// Returns false when asked to quit, or when stdin is closed.
+    pub fn wait(&mut self, ch8: &mut Chip8) -> Result<bool> {
+        if let Some(line) = self.debugger.next_scripted(ch8) {
+            println!("{PROMPT}{line}");
+            return Ok(self.run(&line, ch8));
+        }
+        if self.closed {
+            return Ok(false);
+        }
+        match self.lines.recv() {
+            Ok(Input::Line(line)) => {
+                let running = self.run(&line, ch8);
+                self.prompt();
+                Ok(running)
+            }
+            Ok(Input::Interrupt) => {
+                self.debugger.interrupt(ch8, &mut stdout())?;
+                self.prompt();
+                Ok(true)
+            }
+            Ok(Input::Closed) | Err(_) => Ok(false),
+        }
+    }
+
+    /// Reports why the CPU stopped
+    pub fn stopped(&mut self, ch8: &mut Chip8, error: Option<Error>) -> Result<()> {
+        self.debugger.stopped(ch8, error, &mut stdout())
+    }
+
+    /// Runs one command, printing any error. Returns false when asked to quit.
+    fn run(&mut self, line: &str, ch8: &mut Chip8) -> bool {
+        match self.debugger.run(line, ch8, &mut stdout()) {
+            Ok(running) => running,
+            Err(e) => {
+                eprintln!("{e}");
+                true
+            }
+        }
+    }
+
+    fn prompt(&self) {
+        let _ = self.ready.send(());
+    }
+}
+
+/// Reads lines from stdin, one per prompt, until stdin is closed
+fn read_lines(history: PathBuf, lines: Sender<Input>, ready: Receiver<()>) {
+    let Ok(mut editor) = DefaultEditor::new() else {
+        let _ = lines.send(Input::Closed);
+        return;
+    };
+    // There's no history the first time around
+    let _ = editor.load_history(&history);
+    while ready.recv().is_ok() {
+        let input = match editor.readline(PROMPT) {
+            Ok(line) => {
+                if !line.trim().is_empty() && editor.add_history_entry(line.as_str()).is_ok() {
+                    let _ = editor.save_history(&history);
+                }
+                Input::Line(line)
+            }
+            // Ctrl-C abandons the line, and interrupts the machine
+            Err(ReadlineError::Interrupted) => Input::Interrupt,
+            Err(_) => Input::Closed,
+        };
+        if lines.send(input).is_err() {
+            return;
+        }
+    }
+}
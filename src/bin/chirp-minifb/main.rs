@@ -4,31 +4,48 @@
 //! Chirp: A chip-8 interpreter in Rust
 //! Hello, world!
 
+mod console;
 #[cfg(test)]
 mod tests;
 mod ui;
 
-use chirp::error::Error::BreakpointHit;
-use chirp::{error::Result, *};
+use chirp::error::{Error, Error::BreakpointHit};
+use chirp::{
+    audio::{PcmSink, Sink, Synth, WavSink, Waveform},
+    clock::{Clock, Realtime},
+    cpu::stack::StackPolicy,
+    error::Result,
+    gdb::GdbStub,
+    rewind::Budget,
+    trace::{Format, Tracer},
+    *,
+};
+use console::Console;
 use gumdrop::*;
 use owo_colors::OwoColorize;
-use std::fs::read;
-use std::{
-    path::PathBuf,
-    time::{Duration, Instant},
-};
+use std::fs::{read, File};
+use std::{path::PathBuf, thread::sleep, time::Duration, time::Instant};
 use ui::*;
 
 pub fn main() -> Result<()> {
     let options = Arguments::parse_args_default_or_exit();
-    let state = State::new(options)?;
-    for result in state {
+    let mut state = State::new(options)?;
+    if state.ui.is_none() {
+        if let Err(e) = state.headless() {
+            eprintln!("{}", e.bold().red());
+        }
+    }
+    for result in &mut state {
         if let Err(e) = result {
             eprintln!("{}", e.bold().red());
             break;
         }
     }
-    Ok(())
+    state.print_sanitizer();
+    if let Some(tracer) = state.ch8.cpu.tracer_mut() {
+        tracer.flush()?;
+    }
+    state.save_audio()
 }
 
 /// Parses a hexadecimal string into a u16
@@ -36,6 +53,46 @@ fn parse_hex(value: &str) -> std::result::Result<u16, std::num::ParseIntError> {
     u16::from_str_radix(value, 16)
 }
 
+/// A range of addresses or cycles, from `START..END`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct Span<T> {
+    start: T,
+    end: T,
+}
+
+/// Parses a range like `200..300`, in hexadecimal
+fn parse_hex_range(value: &str) -> std::result::Result<Span<u16>, String> {
+    let (start, end) = value.split_once("..").ok_or("expected START..END")?;
+    let (start, end) = (parse_hex(start), parse_hex(end));
+    Ok(Span {
+        start: start.map_err(|e| e.to_string())?,
+        end: end.map_err(|e| e.to_string())?,
+    })
+}
+
+/// Parses a range like `1000..2000`, in decimal
+fn parse_range(value: &str) -> std::result::Result<Span<usize>, String> {
+    let (start, end) = value.split_once("..").ok_or("expected START..END")?;
+    let (start, end) = (start.parse(), end.parse());
+    Ok(Span {
+        start: start.map_err(|e: std::num::ParseIntError| e.to_string())?,
+        end: end.map_err(|e: std::num::ParseIntError| e.to_string())?,
+    })
+}
+
+/// Parses a comma-separated list of four hexadecimal colors into a [FrameBufferFormat]
+fn parse_palette(value: &str) -> std::result::Result<FrameBufferFormat, String> {
+    let colors = value
+        .split(',')
+        .map(|color| u32::from_str_radix(color.trim().trim_start_matches('#'), 16))
+        .collect::<std::result::Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    match colors[..] {
+        [bg, fg, fg2, blend] => Ok(FrameBufferFormat { fg, bg, fg2, blend }),
+        _ => Err(format!("expected 4 colors, got {}", colors.len())),
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Options, Hash)]
 struct Arguments {
     #[options(help = "Load a ROM to run on Chirp.", required, free)]
@@ -47,45 +104,70 @@ struct Arguments {
     #[options(help = "Enable pause mode at startup.")]
     pub pause: bool,
 
-    #[options(help = "Set the instructions-per-delay rate, or use realtime.")]
-    pub speed: Option<usize>,
     #[options(help = "Set the instructions-per-frame rate.")]
-    pub step: Option<usize>,
-    #[options(help = "Enable performance benchmarking on stderr (requires -S)")]
+    pub speed: Option<usize>,
+    #[options(help = "Enable performance benchmarking on stderr")]
     pub perf: bool,
+    #[options(
+        help = "Run at the speed of the COSMAC VIP, instead of a fixed rate.",
+        no_short
+    )]
+    pub vip: bool,
+    #[options(
+        help = "Run busy-wait loops in full, instead of skipping to the next frame.",
+        no_short
+    )]
+    pub no_idle_skip: bool,
 
     #[options(
-        help = "Run in (Chip8, SChip, XOChip) mode.",
+        help = "Run in (Chip8, SChip, XOChip) mode, if no platform is given.",
         //parse(from_str = "parse_mode")
     )]
     pub mode: Option<Mode>,
-
     #[options(
-        short = "z",
-        help = "Disable setting vF to 0 after a bitwise operation."
+        help = "Emulate (vip, chip-48, schip-legacy, schip-modern, xo-chip, octo).",
+        no_short
     )]
-    pub vfreset: bool,
+    pub platform: Option<Platform>,
     #[options(
-        short = "x",
-        help = "Disable waiting for vblank after issuing a draw call."
+        help = "Adjust the platform's quirks, like shift=on,clip=off.",
+        no_short,
+        meta = "SPEC"
     )]
-    pub drawsync: bool,
+    pub quirks: Option<String>,
 
     #[options(
-        short = "c",
-        help = "Use CHIP-48 style DMA instructions, which don't touch I."
+        help = "Handle stack overflows with (error, wrap, emulate).",
+        no_short,
+        meta = "POLICY"
     )]
-    pub memory: bool,
+    pub stack: Option<StackPolicy>,
+
     #[options(
-        short = "v",
-        help = "Use CHIP-48 style bit-shifts, which don't touch vY."
+        help = "Stop on illegal memory accesses, like writes to the charset.",
+        no_short
     )]
-    pub shift: bool,
+    pub strict: bool,
+
     #[options(
-        short = "b",
-        help = "Use SUPER-CHIP style indexed jump, which is indexed relative to v[adr]."
+        help = "Report uninitialized reads, self-modifying code, and more on exit.",
+        no_short
     )]
-    pub jumping: bool,
+    pub sanitize: bool,
+
+    #[options(help = "Seed the random number generator, for reproducible runs.")]
+    pub seed: Option<u64>,
+
+    #[options(help = "Use the (SChip, Octo) style big font.")]
+    pub font: Option<BigFont>,
+
+    #[options(
+        help = "Set the display colors, as BG,FG,FG2,BLEND in hex.",
+        parse(try_from_str = "parse_palette"),
+        meta = "COLORS"
+    )]
+    pub palette: Option<FrameBufferFormat>,
+
     #[options(
         long = "break",
         help = "Set breakpoints for the emulator to stop at.",
@@ -101,100 +183,366 @@ struct Arguments {
     pub data: u16,
     #[options(help = "Set the target framerate.", default = "60", meta = "FR")]
     pub frame_rate: u64,
+
+    #[options(
+        help = "Keep Super-Chip flags in this directory.",
+        no_short,
+        meta = "DIR"
+    )]
+    pub rpl: Option<PathBuf>,
+    #[options(help = "Keep save states in this directory.", no_short, meta = "DIR")]
+    pub states: Option<PathBuf>,
+    #[options(
+        help = "Keep this many frames of rewind history. (default: 600)",
+        no_short,
+        meta = "FRAMES"
+    )]
+    pub rewind: Option<usize>,
+    #[options(
+        help = "Limit the rewind history to this many megabytes, instead.",
+        no_short,
+        meta = "MB"
+    )]
+    pub rewind_mb: Option<usize>,
+
+    #[options(help = "Open a debugger console on stdin.", no_short)]
+    pub console: bool,
+    #[options(help = "Run without a window, in the debugger console.", no_short)]
+    pub headless: bool,
+    #[options(
+        help = "Wait for gdb to connect on this address, like 127.0.0.1:1234.",
+        no_short,
+        meta = "ADDR"
+    )]
+    pub gdb: Option<String>,
+    #[options(
+        help = "Run debugger commands from this file at startup.",
+        no_short,
+        meta = "FILE"
+    )]
+    pub script: Option<PathBuf>,
+
+    #[options(
+        help = "Write a record of each instruction to a file.",
+        no_short,
+        meta = "FILE"
+    )]
+    pub trace: Option<PathBuf>,
+    #[options(
+        help = "Write the trace as (text, json) lines.",
+        no_short,
+        meta = "FORMAT"
+    )]
+    pub trace_format: Option<Format>,
+    #[options(
+        help = "Only trace instructions at these addresses, like 200..300.",
+        no_short,
+        parse(try_from_str = "parse_hex_range"),
+        meta = "RANGE"
+    )]
+    pub trace_pc: Option<Span<u16>>,
+    #[options(
+        help = "Only trace instructions on these cycles, like 1000..2000.",
+        no_short,
+        parse(try_from_str = "parse_range"),
+        meta = "RANGE"
+    )]
+    pub trace_cycles: Option<Span<usize>>,
+    #[options(
+        help = "Only trace the last N instructions, when something goes wrong.",
+        no_short,
+        meta = "N"
+    )]
+    pub flight_recorder: Option<usize>,
+
+    #[options(help = "Record the sound to a WAV file.", meta = "FILE")]
+    pub wav: Option<PathBuf>,
+    #[options(
+        help = "Stream the sound to a file or pipe, as 48kHz 16-bit PCM.",
+        no_short,
+        meta = "FILE"
+    )]
+    pub pcm: Option<PathBuf>,
+    #[options(
+        help = "Set the frequency of the beep, in Hz.",
+        default = "440",
+        meta = "HZ"
+    )]
+    pub beep: u32,
+    #[options(help = "Set the shape of the beep (square, triangle, sawtooth, sine).")]
+    pub waveform: Option<Waveform>,
 }
 
 #[derive(Debug)]
 struct State {
-    pub speed: usize,
-    pub step: Option<usize>,
-    pub rate: u64,
     pub perf: bool,
     pub ch8: Chip8,
-    pub ui: UI,
-    pub ft: Instant,
+    /// The window, unless running headless
+    pub ui: Option<UI>,
+    pub console: Option<Console>,
+    pub gdb: Option<GdbStub>,
+    pub clock: Realtime,
+    pub synth: Synth,
+    pub wav: Option<(PathBuf, WavSink)>,
+    pub pcm: Option<PcmSink<File>>,
+    pub rom: Vec<u8>,
+    pub rpl_dir: PathBuf,
+    pub rpl: [u8; 16],
 }
 
 impl State {
     fn new(options: Arguments) -> Result<Self> {
+        let platform = options
+            .platform
+            .unwrap_or_else(|| options.mode.clone().unwrap_or_default().into());
+        let mode = platform.mode();
+        let mut quirks = platform.quirks();
+        if let Some(spec) = &options.quirks {
+            quirks.apply(spec)?;
+        }
+        eprintln!("Platform: {platform}\nQuirks: {quirks}");
+        let map = platform.memory_map();
+        let rom = read(&options.file)?;
+        let rpl_dir = options.rpl.unwrap_or_else(rpl::default_dir);
+        let rpl = rpl::load(&rpl_dir, &rom)?;
+        let states_dir = options.states.unwrap_or_else(state::default_dir);
+        // Scripts, headless sessions and gdb start before the program does
+        let pause =
+            options.pause || options.headless || options.script.is_some() || options.gdb.is_some();
+        let gdb = match &options.gdb {
+            Some(addr) => {
+                let gdb = GdbStub::bind(addr.as_str())?;
+                eprintln!("Waiting for gdb on {}", gdb.local_addr()?);
+                Some(gdb)
+            }
+            None => None,
+        };
+        // Without a window, gdb can drive the machine on its own
+        let headless = options.headless && gdb.is_none();
+        let console = match options.console || headless || options.script.is_some() {
+            true => {
+                // The command history is kept next to the save states
+                let history = state::default_dir().with_file_name("history");
+                if let Some(dir) = history.parent() {
+                    std::fs::create_dir_all(dir)?;
+                }
+                Some(Console::new(options.script, history)?)
+            }
+            false => None,
+        };
+        let mut bus = map.bus();
+        if options.sanitize {
+            bus = bus.sanitize();
+        }
         let mut state = State {
-            speed: options.speed.unwrap_or(8),
-            step: options.step,
-            rate: options.frame_rate,
             perf: options.perf,
             ch8: Chip8 {
-                bus: bus! {
+                bus: bus
                     // Load the charset into ROM
-                    Charset [0x0050..0x00A0] = include_bytes!("../../mem/charset.bin"),
+                    .load_region(Charset, options.font.unwrap_or_default().charset())
                     // Load the ROM file into RAM
-                    Program [0x0200..0x1000] = &read(&options.file)?,
-                    // Create a screen
-                    Screen  [0x1000..0x1100],
-                    // Create a stack
-                    Stack   [0x0EA0..0x0F00],
-                },
+                    .load_region(Program, &rom),
                 cpu: CPU::new(
-                    0x1000,
-                    0x50,
-                    0x200,
-                    0xefe,
+                    map.screen.start,
+                    map.charset.start as u16,
+                    map.program.start as u16,
+                    map.stack_top(),
                     Dis::default(),
                     options.breakpoints,
                     Flags {
-                        quirks: options.mode.unwrap_or_default().into(),
+                        quirks,
+                        stack_policy: options.stack.unwrap_or_default(),
+                        stack_depth: platform.stack_depth(),
+                        mode,
                         debug: options.debug,
-                        pause: options.pause,
-                        monotonic: options.speed,
+                        pause,
+                        speed: options.speed.unwrap_or(8),
+                        vip_timing: options.vip,
+                        idle_skip: !options.no_idle_skip,
                         ..Default::default()
                     },
                 ),
             },
-            ui: UIBuilder::new(128, 64, &options.file).build()?,
-            ft: Instant::now(),
+            ui: match options.headless {
+                true => None,
+                false => Some(
+                    UIBuilder {
+                        format: options.palette.unwrap_or_default(),
+                        states: (0..10)
+                            .map(|slot| state::path(&states_dir, &rom, slot))
+                            .collect(),
+                        rewind: match (options.rewind_mb, options.rewind) {
+                            (Some(mb), _) => Budget::Bytes(mb << 20),
+                            (None, frames) => Budget::Frames(frames.unwrap_or(600)),
+                        },
+                        ..UIBuilder::new(128, 64, &options.file)
+                    }
+                    .build()?,
+                ),
+            },
+            console,
+            gdb,
+            clock: Realtime::new(options.frame_rate),
+            synth: Synth::default(),
+            wav: None,
+            pcm: None,
+            rom,
+            rpl_dir,
+            rpl,
         };
-        // Flip the state of the quirks
-        state.ch8.cpu.flags.quirks.bin_ops ^= options.vfreset;
-        state.ch8.cpu.flags.quirks.dma_inc ^= options.memory;
-        state.ch8.cpu.flags.quirks.draw_wait ^= options.drawsync;
-        state.ch8.cpu.flags.quirks.shift ^= options.shift;
-        state.ch8.cpu.flags.quirks.stupid_jumps ^= options.jumping;
+        state.ch8.cpu.set_rpl(rpl);
+        state.ch8.bus.set_strict(options.strict);
+        if let Some(path) = &options.trace {
+            let mut tracer = Tracer::create(path, options.trace_format.unwrap_or_default())?;
+            if let Some(Span { start, end }) = options.trace_pc {
+                tracer = tracer.within(start..end);
+            }
+            if let Some(Span { start, end }) = options.trace_cycles {
+                tracer = tracer.during(start..end);
+            }
+            if let Some(len) = options.flight_recorder {
+                tracer = tracer.flight_recorder(len);
+            }
+            state.ch8.cpu.set_tracer(Some(tracer));
+        }
+        if let Some(seed) = options.seed {
+            state.ch8.cpu.seed(seed);
+        }
+        state.synth.frequency = options.beep as f64;
+        state.synth.waveform = options.waveform.unwrap_or_default();
+        if let Some(path) = options.wav {
+            state.wav = Some((path, WavSink::new(state.synth.sample_rate)));
+        }
+        if let Some(path) = options.pcm {
+            state.pcm = Some(PcmSink::new(File::create(path)?));
+        }
         state.ch8.bus.write(0x1feu16, options.data);
         Ok(state)
     }
     fn keys(&mut self) -> Result<bool> {
-        self.ui.keys(&mut self.ch8)
+        match &mut self.ui {
+            Some(ui) => ui.keys(&mut self.ch8),
+            None => Ok(true),
+        }
     }
     fn frame(&mut self) -> Result<bool> {
-        self.ui.frame(&mut self.ch8)
+        if self.wav.is_some() || self.pcm.is_some() {
+            let mut samples = vec![];
+            self.synth.render(&self.ch8.cpu, 1, &mut samples)?;
+            if let Some((_, sink)) = &mut self.wav {
+                sink.write_samples(&samples)?;
+            }
+            if let Some(sink) = &mut self.pcm {
+                sink.write_samples(&samples)?;
+            }
+        }
+        // Save the flags as soon as they change, so they survive crashes
+        if self.rpl != *self.ch8.cpu.rpl() {
+            self.rpl = *self.ch8.cpu.rpl();
+            rpl::save(&self.rpl_dir, &self.rom, &self.rpl)?;
+        }
+        match &mut self.ui {
+            Some(ui) => ui.frame(&mut self.ch8),
+            None => Ok(true),
+        }
     }
-    fn tick_cpu(&mut self) -> Result<()> {
-        if !self.ch8.cpu.flags.pause {
-            let rate = self.speed;
-            match self.step {
-                Some(ticks) => {
-                    let time = Instant::now();
-                    self.ch8.cpu.multistep(&mut self.ch8.bus, ticks)?;
-                    if self.perf {
-                        let time = time.elapsed();
-                        let nspt = time.as_secs_f64() / ticks as f64;
-                        eprintln!(
-                            "{ticks},\t{time:.05?},\t{:.4} nspt,\t{} ipf,\t{} mips",
-                            nspt * 1_000_000_000.0,
-                            ((1.0 / 60.0f64) / nspt).trunc(),
-                            (1.0 / nspt).trunc() / 1_000_000.0,
-                        );
+    /// Runs the machine without a window, whenever the debugger console or gdb lets it
+    fn headless(&mut self) -> Result<()> {
+        loop {
+            if let Some(gdb) = &mut self.gdb {
+                if !gdb.poll(&mut self.ch8)? {
+                    return Ok(());
+                }
+            }
+            let paused = self.ch8.cpu.flags.pause;
+            match (&mut self.console, &self.gdb) {
+                // Nothing else can resume the machine, so wait for the console
+                (Some(console), None) if paused => {
+                    if !console.wait(&mut self.ch8)? {
+                        return Ok(());
                     }
                 }
-                None => {
-                    self.ch8.cpu.multistep(&mut self.ch8.bus, rate)?;
+                (Some(console), _) => {
+                    if !console.poll(&mut self.ch8)? {
+                        return Ok(());
+                    }
+                }
+                (None, None) => return Ok(()),
+                (None, Some(_)) => {}
+            }
+            if self.ch8.cpu.flags.pause {
+                // Wait for gdb, without spinning
+                sleep(Duration::from_millis(1));
+                continue;
+            }
+            let result = self.tick_cpu();
+            self.frame()?;
+            match result {
+                Err(e) => self.stopped(Some(e))?,
+                // Nobody can press a key without a window
+                Ok(()) if self.ch8.cpu.flags.keypause => {
+                    eprintln!("Waiting for a key.");
+                    self.stopped(None)?;
                 }
+                Ok(()) if self.ch8.cpu.flags.pause => self.stopped(None)?,
+                Ok(()) => {}
+            }
+        }
+    }
+    /// Reports why the machine stopped to the debugger console and gdb, and keeps it around
+    /// to inspect
+    fn stopped(&mut self, error: Option<Error>) -> Result<()> {
+        if let Some(gdb) = &mut self.gdb {
+            gdb.stopped(&mut self.ch8, error.as_ref())?;
+        }
+        match (&mut self.console, error) {
+            (Some(console), error) => console.stopped(&mut self.ch8, error),
+            (None, Some(e)) => {
+                eprintln!("{e}");
+                Ok(())
+            }
+            (None, None) => Ok(()),
+        }
+    }
+    fn print_sanitizer(&self) {
+        if let Some(sanitizer) = self.ch8.bus.sanitizer() {
+            let reports = sanitizer.reports();
+            eprintln!("Sanitizer found {} problem(s)", reports.len());
+            for report in reports {
+                eprintln!("{report}");
             }
         }
+    }
+    fn save_audio(&self) -> Result<()> {
+        if let Some((path, sink)) = &self.wav {
+            sink.save(path)?;
+        }
         Ok(())
     }
-    fn wait_for_next_frame(&mut self) {
-        let rate = 1_000_000_000 / self.rate + 1;
-        std::thread::sleep(Duration::from_nanos(rate).saturating_sub(self.ft.elapsed()));
-        self.ft = Instant::now();
+    fn tick_cpu(&mut self) -> Result<()> {
+        if let Some(ui) = &mut self.ui {
+            // While rewinding, the UI steps the machine back instead
+            if ui.rewinding() {
+                return Ok(());
+            }
+            ui.record(&self.ch8);
+        }
+        if !self.ch8.cpu.flags.pause {
+            let (time, cycle) = (Instant::now(), self.ch8.cpu.cycle());
+            self.ch8.cpu.run_frame(&mut self.ch8.bus)?;
+            if self.perf {
+                let time = time.elapsed();
+                let ticks = self.ch8.cpu.cycle() - cycle;
+                let nspt = time.as_secs_f64() / ticks.max(1) as f64;
+                eprintln!(
+                    "{ticks},\t{time:.05?},\t{:.4} nspt,\t{} ipf,\t{} mips",
+                    nspt * 1_000_000_000.0,
+                    ((1.0 / 60.0f64) / nspt).trunc(),
+                    (1.0 / nspt).trunc() / 1_000_000.0,
+                );
+            }
+        }
+        Ok(())
     }
 }
 
@@ -203,19 +551,42 @@ impl Iterator for State {
 
     /// Pretty heavily abusing iterators here, in an annoying way
     fn next(&mut self) -> Option<Self::Item> {
-        self.wait_for_next_frame();
+        // Without a window, the machine only runs in State::headless
+        self.ui.as_ref()?;
+        self.clock.wait_for_frame();
         match self.keys() {
             Ok(opt) if !opt => return None,
             Err(e) => return Some(Err(e)), // summary lol
             _ => (),
         }
+        if let Some(console) = &mut self.console {
+            match console.poll(&mut self.ch8) {
+                Ok(opt) if !opt => return None,
+                Err(e) => return Some(Err(e)),
+                _ => (),
+            }
+        }
+        if let Some(gdb) = &mut self.gdb {
+            match gdb.poll(&mut self.ch8) {
+                Ok(opt) if !opt => return None,
+                Err(e) => return Some(Err(e)),
+                _ => (),
+            }
+        }
         // Allow breakpoint hit messages
-        match self.tick_cpu() {
-            Err(BreakpointHit { addr, next }) => {
-                eprintln!("Breakpoint hit: {:3x} ({:4x})", addr, next);
+        let running = !self.ch8.cpu.flags.pause;
+        let debugging = self.console.is_some() || self.gdb.is_some();
+        let result = match self.tick_cpu() {
+            Err(e) if debugging => self.stopped(Some(e)),
+            Ok(()) if debugging && running && self.ch8.cpu.flags.pause => self.stopped(None),
+            Err(e @ BreakpointHit { .. }) => {
+                eprintln!("{e}");
+                Ok(())
             }
-            Err(e) => return Some(Err(e)),
-            _ => (),
+            result => result,
+        };
+        if let Err(e) = result {
+            return Some(Err(e));
         }
         match self.frame() {
             Ok(opt) if !opt => return None,
@@ -67,6 +67,7 @@ mod framebuffer_format {
         let fbf = FrameBufferFormat {
             fg: 0x12345678,
             bg: 0x90abcdef,
+            ..Default::default()
         };
         let fbf2 = fbf.clone();
         assert_eq!(fbf, fbf2);
@@ -81,11 +82,13 @@ mod framebuffer_format {
         assert_ne!(
             FrameBufferFormat {
                 fg: 0xff00ff,
-                bg: 0x00ff00
+                bg: 0x00ff00,
+                ..Default::default()
             },
             FrameBufferFormat {
                 fg: 0x00ff00,
-                bg: 0xff00ff
+                bg: 0xff00ff,
+                ..Default::default()
             },
         );
     }
@@ -96,6 +99,7 @@ mod framebuffer_format {
                 == FrameBufferFormat {
                     fg: 0xffffff,
                     bg: 0xffffff,
+                    ..Default::default()
                 }
                 .min(FrameBufferFormat::default())
         );
@@ -106,6 +110,30 @@ mod framebuffer_format {
         FrameBufferFormat::default().hash(&mut hasher);
         println!("{hasher:?}");
     }
+    #[test]
+    fn color() {
+        let fbf = FrameBufferFormat {
+            fg: 1,
+            bg: 0,
+            fg2: 2,
+            blend: 3,
+        };
+        assert_eq!([0, 1, 2, 3], [0, 1, 2, 3].map(|value| fbf.color(value)));
+    }
+    #[test]
+    fn parse_palette() {
+        assert_eq!(
+            Ok(FrameBufferFormat {
+                fg: 0x00ff00,
+                bg: 0x000000,
+                fg2: 0xff0000,
+                blend: 0xffffff,
+            }),
+            crate::parse_palette("000000,00ff00,#ff0000,ffffff")
+        );
+        assert!(crate::parse_palette("000000,00ff00").is_err());
+        assert!(crate::parse_palette("000000,00ff00,ff0000,nope").is_err());
+    }
 }
 
 mod framebuffer {
@@ -143,4 +171,41 @@ mod framebuffer {
         FrameBuffer::default().hash(&mut hasher);
         println!("{hasher:?}");
     }
+
+    #[test]
+    fn draw_one_plane() {
+        let format = FrameBufferFormat::default();
+        let mut fb = FrameBuffer::default();
+        fb.draw(&bus! { Screen [0x000..0x100] = b"\x80" });
+        assert_eq!(64 * 32, fb.buffer().len());
+        assert_eq!(format.fg, fb.buffer()[0]);
+        assert_eq!(format.bg, fb.buffer()[1]);
+    }
+
+    #[test]
+    fn draw_two_planes() {
+        let format = FrameBufferFormat::default();
+        let mut fb = FrameBuffer::default();
+        let mut bus = bus! { Screen [0x000..0x200] };
+        // plane 1 holds pixels 0 and 2, plane 2 holds pixels 1 and 2
+        bus.write(0x000u16, 0xa0u8);
+        bus.write(0x100u16, 0x60u8);
+        fb.draw(&bus);
+        assert_eq!(64 * 32, fb.buffer().len());
+        assert_eq!(
+            [format.fg, format.fg2, format.blend, format.bg],
+            fb.buffer()[..4]
+        );
+    }
+
+    #[test]
+    fn draw_two_planes_hires() {
+        let format = FrameBufferFormat::default();
+        let mut fb = FrameBuffer::default();
+        let mut bus = bus! { Screen [0x000..0x800] };
+        bus.write(0x7ffu16, 0x01u8);
+        fb.draw(&bus);
+        assert_eq!(128 * 64, fb.buffer().len());
+        assert_eq!(format.fg2, fb.buffer()[128 * 64 - 1]);
+    }
 }
@@ -13,6 +13,7 @@ use std::{
 use chirp::{
     bus::{Bus, Region},
     error::Result,
+    rewind::{Budget, Rewind},
     Chip8,
 };
 use minifb::*;
@@ -23,6 +24,11 @@ pub struct UIBuilder {
     pub height: usize,
     pub name: Option<&'static str>,
     pub rom: Option<PathBuf>,
+    /// The files which hold each save slot
+    pub states: Vec<PathBuf>,
+    /// How much rewind history to keep
+    pub rewind: Budget,
+    pub format: FrameBufferFormat,
     pub window_options: WindowOptions,
 }
 
@@ -45,8 +51,12 @@ impl UIBuilder {
                 self.window_options,
             )?,
             keyboard: Default::default(),
-            fb: Default::default(),
+            fb: FrameBuffer::default().with_format(self.format.clone()),
             rom: self.rom.to_owned().unwrap_or_default(),
+            states: self.states.clone(),
+            slot: 0,
+            rewind: Rewind::new(self.rewind),
+            rewinding: false,
             time: Instant::now(),
         };
         Ok(ui)
@@ -60,6 +70,9 @@ impl Default for UIBuilder {
             height: 64,
             name: Some("Chip-8 Interpreter"),
             rom: None,
+            states: vec![],
+            rewind: Default::default(),
+            format: Default::default(),
             window_options: WindowOptions {
                 title: true,
                 resize: false,
@@ -74,8 +87,26 @@ impl Default for UIBuilder {
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FrameBufferFormat {
+    /// Color of pixels set only in plane 1
     pub fg: u32,
+    /// Color of pixels set in no plane
     pub bg: u32,
+    /// Color of pixels set only in plane 2 (XO-Chip)
+    pub fg2: u32,
+    /// Color of pixels set in both planes (XO-Chip)
+    pub blend: u32,
+}
+
+impl FrameBufferFormat {
+    /// Gets the color of a 2-bit pixel value, where bit N is set if the pixel is set in plane N+1
+    pub fn color(&self, value: u8) -> u32 {
+        match value & 3 {
+            0 => self.bg,
+            1 => self.fg,
+            2 => self.fg2,
+            _ => self.blend,
+        }
+    }
 }
 
 impl Default for FrameBufferFormat {
@@ -83,6 +114,8 @@ impl Default for FrameBufferFormat {
         FrameBufferFormat {
             fg: 0x0011a434,
             bg: 0x001E2431,
+            fg2: 0x00d19a2e,
+            blend: 0x00e5e9f0,
         }
     }
 }
@@ -104,30 +137,43 @@ impl FrameBuffer {
             format: Default::default(),
         }
     }
-    pub fn render(&mut self, window: &mut Window, bus: &Bus) -> Result<()> {
+    pub fn with_format(mut self, format: FrameBufferFormat) -> Self {
+        self.format = format;
+        self
+    }
+    #[allow(dead_code)] // this code is used in tests thank you
+    pub fn buffer(&self) -> &[u32] {
+        &self.buffer
+    }
+    /// Converts the [Region::Screen] into pixels, without presenting them
+    pub fn draw(&mut self, bus: &Bus) {
         if let Some(screen) = bus.get_region(Region::Screen) {
             // Resizing the buffer does not unmap memory.
             // After the first use of high-res mode, this is pretty cheap
-            (self.width, self.height) = match screen.len() {
-                256 => (64, 32),
-                1024 => (128, 64),
+            let planes;
+            (self.width, self.height, planes) = match screen.len() {
+                256 => (64, 32, 1),
+                512 => (64, 32, 2),
+                1024 => (128, 64, 1),
+                2048 => (128, 64, 2),
                 _ => {
-                    unimplemented!("Screen must be 64*32 or 128*64");
+                    unimplemented!("Screen must be 64*32 or 128*64, with one or two planes");
                 }
             };
             self.buffer.resize(self.width * self.height, 0);
-            for (idx, byte) in screen.iter().enumerate() {
+            let plane_len = screen.len() / planes;
+            for idx in 0..plane_len {
                 for bit in 0..8 {
-                    self.buffer[8 * idx + bit] = if byte & (1 << (7 - bit)) as u8 != 0 {
-                        self.format.fg
-                    } else {
-                        self.format.bg
-                        // .wrapping_add(0x001104 * (idx / self.width) as u32)
-                        // .wrapping_add(0x141000 * (idx & 3) as u32)
-                    }
+                    let value = (0..planes).fold(0, |value, plane| {
+                        value | ((screen[plane * plane_len + idx] >> (7 - bit)) & 1) << plane
+                    });
+                    self.buffer[8 * idx + bit] = self.format.color(value);
                 }
             }
         }
+    }
+    pub fn render(&mut self, window: &mut Window, bus: &Bus) -> Result<()> {
+        self.draw(bus);
         window.update_with_buffer(&self.buffer, self.width, self.height)?;
         Ok(())
     }
@@ -145,6 +191,10 @@ pub struct UI {
     keyboard: Vec<Key>,
     fb: FrameBuffer,
     rom: PathBuf,
+    states: Vec<PathBuf>,
+    slot: usize,
+    rewind: Rewind,
+    rewinding: bool,
     time: Instant,
 }
 
@@ -167,6 +217,16 @@ impl UI {
         Ok(true)
     }
 
+    /// Returns true while the rewind key is held, and the CPU shouldn't run
+    pub fn rewinding(&self) -> bool {
+        self.rewinding
+    }
+
+    /// Records the state of the machine in the rewind history, before it runs
+    pub fn record(&mut self, ch8: &Chip8) {
+        self.rewind.record(ch8)
+    }
+
     pub fn keys(&mut self, ch8: &mut Chip8) -> Result<bool> {
         // TODO: Remove this hacky workaround for minifb's broken get_keys_* functions.
         let get_keys_pressed = || {
@@ -182,6 +242,13 @@ impl UI {
                 .filter(|key| !self.window.get_keys().contains(key))
         };
         use crate::ui::Region::*;
+        // Rewind one frame per frame, for as long as the key is held
+        self.rewinding = self.window.is_key_down(Key::Backspace);
+        if self.rewinding {
+            self.rewind.frame_back(ch8)?;
+        }
+        let shift =
+            self.window.is_key_down(Key::LeftShift) || self.window.is_key_down(Key::RightShift);
         for key in get_keys_released() {
             if let Some(key) = identify_key(key) {
                 ch8.cpu.release(key)?;
@@ -214,8 +281,13 @@ impl UI {
                         "Unpaused"
                     }
                 }),
+                F6 | Enter if shift => match self.rewind.step_back(ch8)? {
+                    true => eprintln!("Step back"),
+                    false => eprintln!("Nothing to rewind"),
+                },
                 F6 | Enter => {
                     eprintln!("Step");
+                    self.rewind.record(ch8);
                     ch8.cpu.singlestep(&mut ch8.bus)?;
                 }
                 F7 => {
@@ -230,7 +302,23 @@ impl UI {
                     eprintln!("Soft reset state.cpu {:03x}", ch8.cpu.pc());
                     ch8.cpu.soft_reset();
                     ch8.bus.clear_region(Screen);
+                    self.rewind.clear();
+                }
+                F10 if !self.states.is_empty() => {
+                    self.slot = (self.slot + 1) % self.states.len();
+                    eprintln!("Selected save slot {}.", self.slot);
                 }
+                F11 => match self.save_state(ch8) {
+                    Ok(()) => eprintln!("Saved to slot {}.", self.slot),
+                    Err(e) => eprintln!("Couldn't save to slot {}: {e}", self.slot),
+                },
+                F12 => match self.load_state(ch8) {
+                    Ok(()) => {
+                        eprintln!("Loaded slot {}.", self.slot);
+                        self.rewind.clear();
+                    }
+                    Err(e) => eprintln!("Couldn't load slot {}: {e}", self.slot),
+                },
                 Escape => return Ok(false),
                 key => {
                     if let Some(key) = identify_key(key) {
@@ -242,6 +330,23 @@ impl UI {
         self.keyboard = self.window.get_keys();
         Ok(true)
     }
+    fn slot_path(&self) -> Result<&Path> {
+        match self.states.get(self.slot) {
+            Some(path) => Ok(path),
+            None => Err(std::io::Error::from(std::io::ErrorKind::NotFound).into()),
+        }
+    }
+    fn save_state(&self, ch8: &Chip8) -> Result<()> {
+        let path = self.slot_path()?;
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, ch8.save_state())?;
+        Ok(())
+    }
+    fn load_state(&self, ch8: &mut Chip8) -> Result<()> {
+        ch8.load_state(&std::fs::read(self.slot_path()?)?)
+    }
 }
 
 pub fn identify_key(key: Key) -> Option<usize> {
@@ -4,9 +4,21 @@
 //! The Bus connects the CPU to Memory
 //!
 //! This is more of a memory management unit + some utils for reading/writing
+//!
+//! Each [Region] carries [Permissions], which are enforced in strict mode
+//! (see [Bus::set_strict]). Outside of strict mode, any access is allowed.
 
-use crate::error::{Error::MissingRegion, Result};
+use crate::{
+    cpu::breakpoint::{self, Trigger, Watchpoint},
+    error::{
+        Error::{self, MissingRegion},
+        Result,
+    },
+    sanitizer::Sanitizer,
+    state::{invalid, Decoder, Encoder},
+};
 use std::{
+    cell::{Cell, Ref, RefCell},
     fmt::{Debug, Display, Formatter},
     ops::Range,
     slice::SliceIndex,
@@ -48,10 +60,13 @@ pub trait Write<T> {
 }
 
 /// Represents a named region in memory
+///
+/// Regions may overlap, in which case the smallest region's [Permissions] apply.
 #[non_exhaustive]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Region {
-    /// Character ROM (but writable!)
+    /// Character ROM (only writable outside of strict mode)
     Charset,
     /// Program memory
     Program,
@@ -61,6 +76,7 @@ pub enum Region {
     Stack,
     #[doc(hidden)]
     /// Total number of named regions
+    #[cfg_attr(feature = "serde", serde(skip))]
     Count,
 }
 
@@ -80,11 +96,144 @@ impl Display for Region {
     }
 }
 
+impl Region {
+    /// Every named region, in order
+    pub const ALL: [Region; Region::Count as usize] = [
+        Region::Charset,
+        Region::Program,
+        Region::Screen,
+        Region::Stack,
+    ];
+
+    /// Gets the default [Permissions] of this region
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, bus::Permissions};
+    /// assert_eq!(Permissions::READ_ONLY, Charset.permissions());
+    /// assert!(!Screen.permissions().execute);
+    /// ```
+    pub fn permissions(&self) -> Permissions {
+        match self {
+            Region::Charset => Permissions::READ_ONLY,
+            Region::Program => Permissions::ALL,
+            _ => Permissions::READ_WRITE,
+        }
+    }
+}
+
+/// Represents a kind of memory access
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Access {
+    /// Reading data
+    Read,
+    /// Writing data
+    Write,
+    /// Fetching an instruction
+    Execute,
+}
+
+impl Display for Access {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Access::Read => "read",
+            Access::Write => "write",
+            Access::Execute => "execute",
+        })
+    }
+}
+
+/// The kinds of [Access] permitted in a [Region]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Permissions {
+    /// Data may be read
+    pub read: bool,
+    /// Data may be written
+    pub write: bool,
+    /// Instructions may be fetched
+    pub execute: bool,
+}
+
+impl Permissions {
+    /// Permits reading only
+    pub const READ_ONLY: Self = Self {
+        read: true,
+        write: false,
+        execute: false,
+    };
+    /// Permits reading and writing, but not executing
+    pub const READ_WRITE: Self = Self {
+        read: true,
+        write: true,
+        execute: false,
+    };
+    /// Permits every kind of access
+    pub const ALL: Self = Self {
+        read: true,
+        write: true,
+        execute: true,
+    };
+
+    /// Gets whether `access` is permitted
+    pub fn allows(&self, access: Access) -> bool {
+        match access {
+            Access::Read => self.read,
+            Access::Write => self.write,
+            Access::Execute => self.execute,
+        }
+    }
+}
+
+/// Records the first access violation since the last [Bus::take_fault]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Fault {
+    access: Access,
+    addr: usize,
+    region: Option<Region>,
+}
+
+impl From<Fault> for Error {
+    fn from(
+        Fault {
+            access,
+            addr,
+            region,
+        }: Fault,
+    ) -> Self {
+        match region {
+            Some(region) => Error::AccessViolation {
+                access,
+                addr,
+                region,
+            },
+            None => Error::UnmappedAccess { access, addr },
+        }
+    }
+}
+
 /// Stores memory in a series of named regions with ranges
+///
+/// With the `serde` feature, the [Sanitizer] isn't serialized, so a deserialized
+/// bus has none. Its watchpoints are kept.
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Bus {
     memory: Vec<u8>,
     region: [Option<Range<usize>>; Region::Count as usize],
+    // Overrides the default permissions of each region
+    permissions: [Option<Permissions>; Region::Count as usize],
+    strict: bool,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    fault: Cell<Option<Fault>>,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    sanitizer: Option<RefCell<Sanitizer>>,
+    watchpoints: Vec<Watchpoint>,
+    // The watched accesses made since the last check, with the value before each
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watched: RefCell<Vec<(Access, usize, u8)>>,
+    // The old value of each byte written during an instruction, in strict mode
+    #[cfg_attr(feature = "serde", serde(skip))]
+    journal: Option<Vec<(usize, u8)>>,
 }
 
 impl Bus {
@@ -196,9 +345,11 @@ impl Bus {
     ///# }
     /// ```
     pub fn load_region(mut self, name: Region, data: &[u8]) -> Self {
-        use std::io::Write;
-        if let Some(mut region) = self.get_region_mut(name) {
-            region.write(data).ok(); // TODO: THIS SUCKS
+        if let Some(region) = self.get_region_mut(name) {
+            // Data which doesn't fit in the region is truncated
+            let len = data.len().min(region.len());
+            region[..len].copy_from_slice(&data[..len]);
+            self.shadow_load(name, len);
         }
         self
     }
@@ -227,12 +378,246 @@ impl Bus {
     /// ```
     pub fn clear_region(&mut self, name: Region) -> &mut Self {
         if let Some(region) = self.get_region_mut(name) {
-            region.fill(0)
+            region.fill(0);
+            let len = region.len();
+            self.shadow_load(name, len);
         }
         self
     }
 
+    /// Attaches a [Sanitizer], which shadows every byte of memory
+    ///
+    /// Only data loaded afterwards counts as initialized, so attach it before loading.
+    /// # Examples
+    /// ```rust
+    ///# use chirp::*;
+    ///     let bus = Bus::new()
+    ///         .add_region(Program, 0x200..0x1000)
+    ///         .sanitize()
+    ///         .load_region(Program, &[0x00, 0xe0]);
+    ///     let _: u16 = bus.read(0x200u16);
+    ///     let _: u16 = bus.read(0x202u16);
+    ///     // Only the second read was uninitialized
+    ///     assert_eq!(2, bus.sanitizer().unwrap().reports().len());
+    /// ```
+    pub fn sanitize(mut self) -> Self {
+        self.sanitizer = Some(Default::default());
+        self
+    }
+
+    /// Gets the attached [Sanitizer], if there is one
+    pub fn sanitizer(&self) -> Option<Ref<'_, Sanitizer>> {
+        self.sanitizer.as_ref().map(RefCell::borrow)
+    }
+
+    /// Gets the attached [Sanitizer] mutably, if there is one
+    pub fn sanitizer_mut(&mut self) -> Option<&mut Sanitizer> {
+        self.sanitizer.as_mut().map(RefCell::get_mut)
+    }
+
+    /// Marks the first `len` bytes of a named region as initialized
+    fn shadow_load(&mut self, name: Region, len: usize) {
+        let Some(range) = self.get_region_range(name) else {
+            return;
+        };
+        if let Some(sanitizer) = self.sanitizer_mut() {
+            sanitizer.load(range.start..range.start + len);
+        }
+    }
+
+    /// Gets the [Permissions] of a named region
+    /// # Examples
+    /// ```rust
+    ///# use chirp::{*, bus::Permissions};
+    ///     let bus = Bus::new();
+    ///     assert_eq!(Permissions::ALL, bus.permissions(Program));
+    /// ```
+    pub fn permissions(&self, name: Region) -> Permissions {
+        self.permissions
+            .get(name as usize)
+            .copied()
+            .flatten()
+            .unwrap_or_else(|| name.permissions())
+    }
+
+    /// Overrides the [Permissions] of a named region
+    /// # Examples
+    /// ```rust
+    ///# use chirp::{*, bus::Permissions};
+    ///     let mut bus = Bus::new();
+    ///     bus.set_permissions(Charset, Permissions::READ_WRITE);
+    ///     assert_eq!(Permissions::READ_WRITE, bus.permissions(Charset));
+    /// ```
+    pub fn set_permissions(&mut self, name: Region, permissions: Permissions) -> &mut Self {
+        if let Some(slot) = self.permissions.get_mut(name as usize) {
+            *slot = Some(permissions);
+        }
+        self
+    }
+
+    /// Gets whether the bus is in strict mode
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Enables or disables strict mode
+    ///
+    /// In strict mode, accesses through [Read] and [Write] are checked against the
+    /// [Permissions] of each region. Denied writes are dropped, denied reads return
+    /// the usual `0xc5` sentinel, and the first violation is kept for [Bus::take_fault].
+    /// The [CPU](crate::cpu::CPU) stops on any instruction which caused a violation, and undoes
+    /// it entirely: its registers are put back, and so is any memory it wrote before the fault.
+    ///
+    /// Memory outside of every region may be read and written, but not executed.
+    /// Memory past the end of the bus may not be accessed at all.
+    /// # Examples
+    /// ```rust
+    ///# use chirp::*;
+    ///     let mut bus = bus! { Charset [0x50..0xa0] };
+    ///     bus.set_strict(true);
+    ///     bus.write(0x50u16, 0xffu8);
+    ///     assert_eq!(0u8, bus.read(0x50u16));
+    ///     assert!(bus.take_fault().is_some());
+    /// ```
+    pub fn set_strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Checks whether an `access` to every address in `range` is permitted
+    ///
+    /// Outside of strict mode, every access is permitted.
+    /// # Examples
+    /// ```rust
+    ///# use chirp::{*, bus::Access};
+    ///     let mut bus = bus! { Screen [0xf00..0x1000] };
+    ///     assert!(bus.check(Access::Execute, 0xf00..0xf02).is_ok());
+    ///     bus.set_strict(true);
+    ///     assert!(bus.check(Access::Execute, 0xf00..0xf02).is_err());
+    /// ```
+    pub fn check(&self, access: Access, range: Range<usize>) -> Result<()> {
+        match self.find_fault(access, range) {
+            Some(fault) => Err(fault.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Takes the first access violation since the last call, if there was one
+    pub fn take_fault(&self) -> Option<Error> {
+        self.fault.take().map(Error::from)
+    }
+
+    /// Checks an `access`, recording the violation if it's denied.
+    /// Returns whether the access may go ahead.
+    pub(crate) fn access(&self, access: Access, range: Range<usize>) -> bool {
+        match self.find_fault(access, range.clone()) {
+            Some(fault) => {
+                if self.fault.get().is_none() {
+                    self.fault.set(Some(fault));
+                }
+                false
+            }
+            None => {
+                self.watch(access, range.clone());
+                self.shadow(access, range);
+                true
+            }
+        }
+    }
+
+    /// Logs an access to any watched address, for [Bus::check_watchpoints]
+    fn watch(&self, access: Access, range: Range<usize>) {
+        if self.watchpoints.is_empty() || access == Access::Execute {
+            return;
+        }
+        let mut watched = self.watched.borrow_mut();
+        for addr in range {
+            if self.watchpoints.iter().any(|wp| wp.range().contains(&addr)) {
+                let old = self.memory.get(addr).copied().unwrap_or(0xc5);
+                watched.push((access, addr, old));
+            }
+        }
+    }
+
+    /// Starts keeping the old value of every byte written, in strict mode
+    pub(crate) fn start_journal(&mut self) {
+        self.journal = self.strict.then(Vec::new);
+    }
+
+    /// Stops keeping the old values of written bytes, and puts them back if `undo` is set
+    pub(crate) fn end_journal(&mut self, undo: bool) {
+        let Some(journal) = self.journal.take() else {
+            return;
+        };
+        if undo {
+            for (addr, old) in journal.into_iter().rev() {
+                self.memory[addr] = old;
+            }
+        }
+    }
+
+    /// Writes `data` at `addr`, keeping the old values in the journal, if there is one
+    fn store(&mut self, addr: usize, data: &[u8]) {
+        let Some(slice) = self.memory.get_mut(addr..addr + data.len()) else {
+            return;
+        };
+        if let Some(journal) = &mut self.journal {
+            journal.extend(slice.iter().enumerate().map(|(i, &old)| (addr + i, old)));
+        }
+        slice.copy_from_slice(data);
+    }
+
+    /// Passes an access on to the [Sanitizer], if there is one
+    fn shadow(&self, access: Access, range: Range<usize>) {
+        let Some(sanitizer) = &self.sanitizer else {
+            return;
+        };
+        let mut sanitizer = sanitizer.borrow_mut();
+        match access {
+            Access::Read => {
+                // The screen is always initialized
+                let screen = self.get_region_range(Region::Screen).unwrap_or_default();
+                for addr in range.filter(|addr| !screen.contains(addr)) {
+                    sanitizer.read(addr..addr + 1);
+                }
+            }
+            Access::Write => sanitizer.write(range),
+            Access::Execute => sanitizer.execute(range),
+        }
+    }
+
+    /// Finds the first address in `range` where `access` is denied, in strict mode
+    fn find_fault(&self, access: Access, range: Range<usize>) -> Option<Fault> {
+        if !self.strict {
+            return None;
+        }
+        range.into_iter().find_map(|addr| {
+            let fault = |region| Fault {
+                access,
+                addr,
+                region,
+            };
+            if addr >= self.len() {
+                return Some(fault(None));
+            }
+            let region = Region::ALL
+                .into_iter()
+                .filter_map(|name| Some((name, self.get_region_range(name)?)))
+                .filter(|(_, range)| range.contains(&addr))
+                .min_by_key(|(_, range)| range.len())
+                .map(|(name, _)| name);
+            match region {
+                Some(name) if self.permissions(name).allows(access) => None,
+                // Unmapped memory may be used for data, but never for code
+                None if access != Access::Execute => None,
+                region => Some(fault(region)),
+            }
+        })
+    }
+
     /// Gets a slice of bus memory
+    ///
+    /// This doesn't check [Permissions], even in strict mode.
     /// # Examples
     /// ```rust
     ///# use chirp::*;
@@ -251,6 +636,8 @@ impl Bus {
     }
 
     /// Gets a mutable slice of bus memory
+    ///
+    /// This doesn't check [Permissions], even in strict mode.
     /// # Examples
     /// ```rust
     ///# use chirp::*;
@@ -283,6 +670,22 @@ impl Bus {
         self.get(self.region.get(name as usize)?.clone()?)
     }
 
+    /// Gets the range of addresses covered by a named region of memory
+    /// # Examples
+    /// ```rust
+    ///# use chirp::*;
+    ///# fn main() -> Result<()> {
+    ///     let bus = Bus::new()
+    ///         .add_region(Program, 0..10);
+    ///     assert_eq!(Some(0..10), bus.get_region_range(Program));
+    ///     assert_eq!(None, bus.get_region_range(Stack));
+    ///#    Ok(())
+    ///# }
+    /// ```
+    pub fn get_region_range(&self, name: Region) -> Option<Range<usize>> {
+        self.region.get(name as usize)?.clone()
+    }
+
     /// Gets a mutable slice of a named region of memory
     /// # Examples
     /// ```rust
@@ -324,6 +727,14 @@ impl Bus {
     pub fn print_screen(&self) -> Result<()> {
         const REGION: Region = Region::Screen;
         if let Some(screen) = self.get_region(REGION) {
+            // XO-Chip screens hold two bitplanes, which are printed overlaid
+            let planes = 1 + (screen.len().ilog2() as usize & 1);
+            let plane_len = screen.len() / planes;
+            let screen: Vec<u8> = (0..plane_len)
+                .map(|index| {
+                    (0..planes).fold(0, |byte, plane| byte | screen[plane * plane_len + index])
+                })
+                .collect();
             let len_log2 = screen.len().ilog2() / 2;
             #[allow(unused_variables)]
             let (width, height) = (2u32.pow(len_log2 - 1), 2u32.pow(len_log2));
@@ -337,16 +748,13 @@ impl Bus {
                     .iter()
                     .enumerate()
                     .flat_map(|(bytei, byte)| {
-                        (0..8)
-                            .into_iter()
-                            .enumerate()
-                            .filter_map(move |(biti, bit)| {
-                                if (byte << bit) & 0x80 != 0 {
-                                    Some(bytei * 8 + biti)
-                                } else {
-                                    None
-                                }
-                            })
+                        (0..8).enumerate().filter_map(move |(biti, bit)| {
+                            if (byte << bit) & 0x80 != 0 {
+                                Some(bytei * 8 + biti)
+                            } else {
+                                None
+                            }
+                        })
                     })
                     .for_each(|index| canvas.set(index as u32 % (width), index as u32 / (width)));
                 println!("{}", canvas.frame());
@@ -371,10 +779,129 @@ impl Bus {
     }
 }
 
+// save states
+impl Bus {
+    /// Writes the bus' memory, region map and permissions
+    pub(crate) fn save_state(&self, state: &mut Encoder) {
+        state.bytes(&self.memory);
+        for region in &self.region {
+            state.option(region.as_ref(), |state, range| {
+                state.usize(range.start);
+                state.usize(range.end);
+            });
+        }
+        for permissions in self.permissions {
+            state.option(permissions, |state, permissions| {
+                state.bool(permissions.read);
+                state.bool(permissions.write);
+                state.bool(permissions.execute);
+            });
+        }
+        state.bool(self.strict);
+        state.usize(self.watchpoints.len());
+        self.watchpoints.iter().for_each(|wp| wp.save_state(state));
+    }
+
+    /// Reads a bus written by [Bus::save_state], keeping this bus' [Sanitizer]
+    pub(crate) fn load_state(&self, state: &mut Decoder) -> Result<Self> {
+        let mut bus = self.clone();
+        bus.memory = state.bytes()?.to_vec();
+        for region in bus.region.iter_mut() {
+            *region = state.option(|state| Ok(state.usize()?..state.usize()?))?;
+            if region
+                .as_ref()
+                .is_some_and(|range| range.end > bus.memory.len())
+            {
+                return Err(invalid("region out of bounds"));
+            }
+        }
+        for permissions in bus.permissions.iter_mut() {
+            *permissions = state.option(|state| {
+                Ok(Permissions {
+                    read: state.bool()?,
+                    write: state.bool()?,
+                    execute: state.bool()?,
+                })
+            })?;
+        }
+        bus.strict = state.bool()?;
+        bus.watchpoints = (0..state.usize()?)
+            .map(|_| Watchpoint::load_state(state))
+            .collect::<Result<_>>()?;
+        bus.fault.set(None);
+        bus.watched.get_mut().clear();
+        Ok(bus)
+    }
+}
+
+// watchpoints
+impl Bus {
+    /// Removes the [Watchpoint] with the given id, returning it
+    pub fn remove_watchpoint(&mut self, id: usize) -> Option<Watchpoint> {
+        let index = self.watchpoints.iter().position(|wp| wp.id() == id)?;
+        Some(self.watchpoints.remove(index))
+    }
+
+    /// Gets the watchpoints added with [CPU::add_watchpoint](crate::cpu::CPU::add_watchpoint)
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Gets the watchpoints mutably, so they can be taken out while history is replayed
+    pub(crate) fn watchpoints_mut(&mut self) -> &mut Vec<Watchpoint> {
+        &mut self.watchpoints
+    }
+
+    /// Forgets the accesses made since the last check, like those made by a frontend
+    pub(crate) fn clear_watched(&mut self) {
+        self.watched.get_mut().clear();
+    }
+
+    /// Checks the accesses made since the last check against every [Watchpoint],
+    /// and gets the first one which stops the CPU
+    ///
+    /// Each watchpoint counts at most one hit per check.
+    pub(crate) fn check_watchpoints(&mut self) -> Option<Trigger> {
+        let watched = std::mem::take(self.watched.get_mut());
+        if watched.is_empty() {
+            return None;
+        }
+        let accesses: Vec<_> = watched
+            .into_iter()
+            .map(|(access, addr, old)| {
+                let new = self.memory.get(addr).copied().unwrap_or(0xc5);
+                (access, addr, old, new)
+            })
+            .collect();
+        let mut index = 0;
+        while index < self.watchpoints.len() {
+            let wp = &self.watchpoints[index];
+            if let Some((addr, old, new)) = wp.find(&accesses) {
+                let watch = wp.watch();
+                let stop = breakpoint::hit(&mut self.watchpoints, index, Watchpoint::counters);
+                if let Some(id) = stop {
+                    return Some(Trigger::Watchpoint {
+                        id,
+                        watch,
+                        addr,
+                        old,
+                        new,
+                    });
+                }
+            }
+            index += 1;
+        }
+        None
+    }
+}
+
 impl Read<u8> for Bus {
     /// Read a u8 from address `addr`
     fn read(&self, addr: impl Into<usize>) -> u8 {
         let addr: usize = addr.into();
+        if !self.access(Access::Read, addr..addr + 1) {
+            return 0xc5;
+        }
         *self.memory.get(addr).unwrap_or(&0xc5)
     }
 }
@@ -383,6 +910,9 @@ impl Read<u16> for Bus {
     /// Read a u16 from address `addr`
     fn read(&self, addr: impl Into<usize>) -> u16 {
         let addr: usize = addr.into();
+        if !self.access(Access::Read, addr..addr + 2) {
+            return 0xc5c5;
+        }
         if let Some(bytes) = self.memory.get(addr..addr + 2) {
             u16::from_be_bytes(bytes.try_into().expect("Should get 2 bytes"))
         } else {
@@ -395,6 +925,9 @@ impl Read<u32> for Bus {
     /// Read a u16 from address `addr`
     fn read(&self, addr: impl Into<usize>) -> u32 {
         let addr: usize = addr.into();
+        if !self.access(Access::Read, addr..addr + 4) {
+            return 0xc5c5;
+        }
         if let Some(bytes) = self.memory.get(addr..addr + 4) {
             u32::from_be_bytes(bytes.try_into().expect("Should get 4 bytes"))
         } else {
@@ -407,6 +940,9 @@ impl Read<u64> for Bus {
     /// Read a u16 from address `addr`
     fn read(&self, addr: impl Into<usize>) -> u64 {
         let addr: usize = addr.into();
+        if !self.access(Access::Read, addr..addr + 8) {
+            return 0xc5c5;
+        }
         if let Some(bytes) = self.memory.get(addr..addr + 8) {
             u64::from_be_bytes(bytes.try_into().expect("Should get 8 bytes"))
         } else {
@@ -419,6 +955,9 @@ impl Read<u128> for Bus {
     /// Read a u16 from address `addr`
     fn read(&self, addr: impl Into<usize>) -> u128 {
         let addr: usize = addr.into();
+        if !self.access(Access::Read, addr..addr + 16) {
+            return 0xc5c5;
+        }
         if let Some(bytes) = self.memory.get(addr..addr + 16) {
             u128::from_be_bytes(bytes.try_into().expect("Should get 16 bytes"))
         } else {
@@ -431,9 +970,10 @@ impl Write<u8> for Bus {
     /// Write a u8 to address `addr`
     fn write(&mut self, addr: impl Into<usize>, data: u8) {
         let addr: usize = addr.into();
-        if let Some(byte) = self.get_mut(addr) {
-            *byte = data;
+        if !self.access(Access::Write, addr..addr + 1) {
+            return;
         }
+        self.store(addr, &[data]);
     }
 }
 
@@ -441,9 +981,10 @@ impl Write<u16> for Bus {
     /// Write a u16 to address `addr`
     fn write(&mut self, addr: impl Into<usize>, data: u16) {
         let addr: usize = addr.into();
-        if let Some(slice) = self.get_mut(addr..addr + 2) {
-            data.to_be_bytes().as_mut().swap_with_slice(slice);
+        if !self.access(Access::Write, addr..addr + 2) {
+            return;
         }
+        self.store(addr, &data.to_be_bytes());
     }
 }
 
@@ -451,9 +992,10 @@ impl Write<u32> for Bus {
     /// Write a u16 to address `addr`
     fn write(&mut self, addr: impl Into<usize>, data: u32) {
         let addr: usize = addr.into();
-        if let Some(slice) = self.get_mut(addr..addr + 4) {
-            data.to_be_bytes().as_mut().swap_with_slice(slice);
+        if !self.access(Access::Write, addr..addr + 4) {
+            return;
         }
+        self.store(addr, &data.to_be_bytes());
     }
 }
 
@@ -461,9 +1003,10 @@ impl Write<u64> for Bus {
     /// Write a u16 to address `addr`
     fn write(&mut self, addr: impl Into<usize>, data: u64) {
         let addr: usize = addr.into();
-        if let Some(slice) = self.get_mut(addr..addr + 8) {
-            data.to_be_bytes().as_mut().swap_with_slice(slice);
+        if !self.access(Access::Write, addr..addr + 8) {
+            return;
         }
+        self.store(addr, &data.to_be_bytes());
     }
 }
 
@@ -471,13 +1014,14 @@ impl Write<u128> for Bus {
     /// Write a u16 to address `addr`
     fn write(&mut self, addr: impl Into<usize>, data: u128) {
         let addr: usize = addr.into();
-        if let Some(slice) = self.get_mut(addr..addr + 16) {
-            data.to_be_bytes().as_mut().swap_with_slice(slice);
+        if !self.access(Access::Write, addr..addr + 16) {
+            return;
         }
+        self.store(addr, &data.to_be_bytes());
     }
 }
 
-#[cfg(target_feature = "rhexdump")]
+#[cfg(feature = "rhexdump")]
 impl Display for Bus {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         use rhexdump::Rhexdump;
@@ -485,10 +1029,10 @@ impl Display for Bus {
         rhx.set_bytes_per_group(2)
             .expect("2 <= MAX_BYTES_PER_GROUP (8)");
         rhx.display_duplicate_lines(false);
-        for (&name, range) in &self.region {
+        for range in self.region.iter().flatten() {
             writeln!(
                 f,
-                "[{name}]\n{}\n",
+                "[{range:04x?}]\n{}\n",
                 rhx.hexdump(&self.memory[range.clone()])
             )?
         }
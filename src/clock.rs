@@ -0,0 +1,61 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Paces frames against a [Clock], for frontends which run in real time
+//!
+//! [CPU::run_frame](crate::cpu::CPU::run_frame) never reads the wall clock, so the
+//! timers only depend on the number of frames run. A frontend which wants to run
+//! at 60 frames per second waits on a [Clock] between frames.
+
+use std::time::{Duration, Instant};
+
+/// Decides when the next frame should start
+pub trait Clock {
+    /// Blocks until it's time to run the next frame
+    fn wait_for_frame(&mut self);
+}
+
+/// A [Clock] which sleeps between frames, to run at a fixed frame rate
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Realtime {
+    period: Duration,
+    last: Instant,
+}
+
+impl Realtime {
+    /// Constructs a [Realtime] clock which runs `rate` frames per second
+    /// # Examples
+    /// ```rust
+    /// # use chirp::clock::*;
+    /// let mut clock = Realtime::new(60);
+    /// clock.wait_for_frame();
+    /// ```
+    pub fn new(rate: u64) -> Self {
+        Self {
+            period: Duration::from_nanos(1_000_000_000 / rate.max(1)),
+            last: Instant::now(),
+        }
+    }
+}
+
+impl Default for Realtime {
+    /// Constructs a [Realtime] clock which runs at 60 frames per second
+    fn default() -> Self {
+        Self::new(60)
+    }
+}
+
+impl Clock for Realtime {
+    fn wait_for_frame(&mut self) {
+        std::thread::sleep(self.period.saturating_sub(self.last.elapsed()));
+        self.last = Instant::now();
+    }
+}
+
+/// A [Clock] which never waits, for running as fast as possible
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Unpaced;
+
+impl Clock for Unpaced {
+    fn wait_for_frame(&mut self) {}
+}
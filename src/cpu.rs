@@ -6,70 +6,97 @@
 #[cfg(test)]
 mod tests;
 
+pub mod breakpoint;
+pub mod decoder;
 pub mod disassembler;
 pub mod flags;
+pub mod font;
+mod idle;
 pub mod instruction;
+pub mod memory_map;
 pub mod mode;
+pub mod platform;
 pub mod quirks;
+pub mod rng;
+pub mod stack;
+mod state;
+pub mod timing;
 
 use self::{
     disassembler::{Dis, Disassembler, Insn},
     flags::Flags,
     mode::Mode,
     quirks::Quirks,
+    rng::{BoxedRng, Rng},
+    stack::StackPolicy,
 };
 use crate::{
-    bus::{Bus, Read, Region, Write},
+    audio::{FRAME_RATE, PATTERN_BITS},
+    bus::{Access, Bus, Read, Region, Write},
     error::{Error, Result},
+    trace::{Record, Tracer},
 };
-use imperative_rs::InstructionSet;
 use owo_colors::OwoColorize;
-use rand::random;
-use std::time::Instant;
 
 type Reg = usize;
 type Adr = u16;
 type Nib = u8;
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-struct Timers {
-    frame: Instant,
-    insn: Instant,
-}
-
-impl Default for Timers {
-    fn default() -> Self {
-        let now = Instant::now();
-        Self {
-            frame: now,
-            insn: now,
-        }
-    }
+/// The registers an instruction can change, kept so a faulting instruction can be undone
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Registers {
+    pc: Adr,
+    sp: usize,
+    i: Adr,
+    v: [u8; 16],
+    delay: f64,
+    sound: f64,
+    rpl: [u8; 16],
+    planes: u8,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
 }
 
 /// Represents the internal state of the CPU interpreter
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CPU {
     /// Flags that control how the CPU behaves, but which aren't inherent to the
     /// chip-8. Includes [Quirks], target IPF, etc.
     pub flags: Flags,
     // memory map info
-    screen: Adr,
+    screen: usize,
     font: Adr,
     // registers
     pc: Adr,
-    sp: Adr,
+    sp: usize,
     i: Adr,
     v: [u8; 16],
     delay: f64,
     sound: f64,
+    // Super-Chip state
+    rpl: [u8; 16],
+    // XO-Chip state
+    planes: u8,
+    pattern: Option<[u8; 16]>,
+    pitch: u8,
+    // Where the audio pattern started playing in the last frame, if the sound timer ran in it
+    phase: Option<f64>,
     // I/O
     keys: [bool; 16],
+    rng: BoxedRng,
     // Execution data
-    timers: Timers,
     cycle: usize,
+    frame_cycles: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    idle: Option<idle::Snapshot>,
     breakpoints: Vec<Adr>,
+    conditional: Vec<breakpoint::Breakpoint>,
+    next_id: usize,
+    #[cfg_attr(feature = "serde", serde(skip))]
     disassembler: Dis,
+    #[cfg_attr(feature = "serde", serde(skip))]
+    tracer: Option<Tracer>,
 }
 
 // public interface
@@ -86,15 +113,15 @@ impl CPU {
     ///     0xefe,  // top of stack
     ///     Dis::default(),
     ///     vec![], // Breakpoints
-    ///     ControlFlags::default()
+    ///     Flags::default()
     /// );
     /// dbg!(cpu);
     /// ```
     pub fn new(
-        screen: Adr,
+        screen: usize,
         font: Adr,
         pc: Adr,
-        sp: Adr,
+        sp: usize,
         disassembler: Dis,
         breakpoints: Vec<Adr>,
         flags: Flags,
@@ -143,8 +170,8 @@ impl CPU {
     /// Releases a key, and reports whether the key's state changed.  
     /// If key is outside range `0..=0xF`, returns [Error::InvalidKey].
     ///
-    /// If [ControlFlags::keypause] was enabled, it is disabled,
-    /// and the [ControlFlags::lastkey] is recorded.
+    /// If [Flags::keypause] was enabled, it is disabled,
+    /// and the [Flags::lastkey] is recorded.
     /// # Examples
     /// ```rust
     /// # use chirp::*;
@@ -210,6 +237,19 @@ impl CPU {
         self.v.as_slice()
     }
 
+    /// Gets which keys are held, indexed by key
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.press(0x7).unwrap();
+    /// assert!(cpu.keys()[0x7]);
+    /// assert_eq!(1, cpu.keys().iter().filter(|&&held| held).count());
+    /// ```
+    pub fn keys(&self) -> &[bool] {
+        self.keys.as_slice()
+    }
+
     /// Gets the program counter
     /// # Examples
     /// ```rust
@@ -232,6 +272,43 @@ impl CPU {
         self.i
     }
 
+    /// Sets the program counter
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.set_pc(0x300);
+    /// assert_eq!(0x300, cpu.pc());
+    /// ```
+    pub fn set_pc(&mut self, pc: Adr) -> &mut Self {
+        self.pc = pc;
+        self
+    }
+
+    /// Sets the I register
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.set_i(0x300);
+    /// assert_eq!(0x300, cpu.i());
+    /// ```
+    pub fn set_i(&mut self, i: Adr) -> &mut Self {
+        self.i = i;
+        self
+    }
+
+    /// Gets the stack pointer, which points at the next free slot on the stack
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(0xefe, cpu.sp());
+    /// ```
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
     /// Gets the value in the Sound Timer register
     /// # Examples
     /// ```rust
@@ -254,10 +331,175 @@ impl CPU {
         self.delay as u8
     }
 
+    /// Sets the Sound Timer register
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.set_sound(30);
+    /// assert_eq!(30, cpu.sound());
+    /// ```
+    pub fn set_sound(&mut self, sound: u8) -> &mut Self {
+        self.sound = sound as f64;
+        self
+    }
+
+    /// Sets the Delay Timer register
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.set_delay(30);
+    /// assert_eq!(30, cpu.delay());
+    /// ```
+    pub fn set_delay(&mut self, delay: u8) -> &mut Self {
+        self.delay = delay as f64;
+        self
+    }
+
+    /// Gets the Super-Chip RPL flag registers, saved and loaded by `Fx75`/`Fx85`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(&[0; 16], cpu.rpl());
+    /// ```
+    pub fn rpl(&self) -> &[u8; 16] {
+        &self.rpl
+    }
+
+    /// Sets the Super-Chip RPL flag registers, for restoring them from storage
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.set_rpl([0xc5; 16]);
+    /// assert_eq!(&[0xc5; 16], cpu.rpl());
+    /// ```
+    pub fn set_rpl(&mut self, rpl: [u8; 16]) -> &mut Self {
+        self.rpl = rpl;
+        self
+    }
+
+    /// Seeds the random number generator used by `Cxbb`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let (mut a, mut b) = (CPU::default(), CPU::default());
+    /// a.seed(1234);
+    /// b.seed(1234);
+    /// assert_eq!(a.rng().state(), b.rng().state());
+    /// ```
+    pub fn seed(&mut self, seed: u64) -> &mut Self {
+        self.rng.0.set_state(seed);
+        self
+    }
+
+    /// Gets the random number generator used by `Cxbb`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.seed(1234);
+    /// let state = cpu.rng().state();
+    /// ```
+    pub fn rng(&self) -> &dyn Rng {
+        self.rng.0.as_ref()
+    }
+
+    /// Replaces the random number generator used by `Cxbb`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, cpu::rng::Xorshift};
+    /// let mut cpu = CPU::default();
+    /// cpu.set_rng(Xorshift::new(1234));
+    /// ```
+    pub fn set_rng(&mut self, rng: impl Rng + 'static) -> &mut Self {
+        self.rng = BoxedRng(Box::new(rng));
+        self
+    }
+
+    /// Gets the bitmask of XO-Chip bitplanes selected for drawing
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(1, cpu.planes());
+    /// ```
+    pub fn planes(&self) -> u8 {
+        self.planes
+    }
+
+    /// Gets the XO-Chip audio pattern buffer, if one has been loaded
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(None, cpu.pattern());
+    /// ```
+    pub fn pattern(&self) -> Option<&[u8; 16]> {
+        self.pattern.as_ref()
+    }
+
+    /// Gets the value in the XO-Chip pitch register
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(64, cpu.pitch());
+    /// ```
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// Gets the rate at which the XO-Chip audio pattern is played back, in bits per second
+    ///
+    /// This is `4000 * 2^((pitch - 64) / 48)`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// assert_eq!(4000.0, cpu.playback_rate());
+    /// ```
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    /// Gets how far the XO-Chip audio pattern had played when the last frame started,
+    /// in loops of the pattern, or [None] if the sound timer wasn't running during it
+    ///
+    /// This is set at each vertical blank, before the sound timer ticks, so a frame
+    /// which set the sound timer to 1 still makes a sound. The pattern moves on by a
+    /// frame's worth of playback each frame the timer runs, and starts over after it stops.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// # fn main() -> Result<()> {
+    /// let mut cpu = CPU::default();
+    /// let mut bus = bus! {
+    ///     Program [0x0200..0x1000] = &[
+    ///         0x70, 0x01, // add #01, v0
+    ///         0x12, 0x00, // jump 0x200
+    ///     ],
+    /// };
+    /// cpu.set_sound(2).run_frame(&mut bus)?;
+    /// assert_eq!(Some(0.0), cpu.pattern_phase());
+    /// cpu.run_frame(&mut bus)?;
+    /// // 4000 bits per second, in a 128 bit pattern, at 60 frames per second
+    /// assert_eq!(Some(4000.0 / 128.0 / 60.0), cpu.pattern_phase());
+    /// cpu.run_frame(&mut bus)?;
+    /// assert_eq!(None, cpu.pattern_phase());
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn pattern_phase(&self) -> Option<f64> {
+        self.phase
+    }
+
     /// Gets the number of cycles the CPU has executed
     ///
-    /// If cpu.flags.monotonic is Some, the cycle count will be
-    /// updated even when the CPU is in drawpause or keypause
+    /// Unless [Flags::vip_timing] is set, the cycle count is
+    /// updated even when the CPU is paused, or in drawpause or keypause
     /// # Examples
     /// ```rust
     /// # use chirp::*;
@@ -280,7 +522,7 @@ impl CPU {
     ///     0xefe,
     ///     Dis::default(),
     ///     vec![],
-    ///     ControlFlags::default()
+    ///     Flags::default()
     /// );
     /// cpu.flags.keypause = true;
     /// cpu.flags.draw_wait = true;
@@ -322,6 +564,31 @@ impl CPU {
         self
     }
 
+    /// Attaches a [Tracer], which records each instruction before it runs, or detaches it
+    ///
+    /// Returns the tracer which was attached before, so it can be [flushed](Tracer::flush).
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, trace::*};
+    /// let mut cpu = CPU::default();
+    /// assert!(cpu.set_tracer(Some(Tracer::new(std::io::sink(), Format::Text))).is_none());
+    /// assert_eq!(Some(Format::Text), cpu.tracer().map(Tracer::format));
+    /// assert!(cpu.set_tracer(None).is_some());
+    /// ```
+    pub fn set_tracer(&mut self, tracer: Option<Tracer>) -> Option<Tracer> {
+        std::mem::replace(&mut self.tracer, tracer)
+    }
+
+    /// Gets the attached [Tracer], if there is one
+    pub fn tracer(&self) -> Option<&Tracer> {
+        self.tracer.as_ref()
+    }
+
+    /// Gets the attached [Tracer] mutably, if there is one
+    pub fn tracer_mut(&mut self) -> Option<&mut Tracer> {
+        self.tracer.as_mut()
+    }
+
     /// Gets a slice of breakpoints
     /// # Examples
     /// ```rust
@@ -365,7 +632,8 @@ impl CPU {
 
     /// Unpauses the emulator for `steps` ticks
     ///
-    /// Ticks the timers every `rate` ticks
+    /// Ends a frame every [Flags::speed] ticks (or every frame's worth of
+    /// cycles, with [Flags::vip_timing]), even if that's partway through `steps`.
     /// # Examples
     /// ```rust
     /// # use chirp::*;
@@ -390,45 +658,137 @@ impl CPU {
         Ok(self)
     }
 
-    /// Simulates vertical blanking
+    /// Runs one frame: [Flags::speed] instructions (or a frame's worth of cycles, with
+    /// [Flags::vip_timing]), then ticks the timers once and ends any display wait.
+    ///
+    /// Stops early if the emulator is paused by the user, or a breakpoint is hit.
+    /// The rest of the frame is run by the next call.
+    ///
+    /// If [Flags::idle_skip] is set, busy-wait loops are fast-forwarded to the end of
+    /// the frame, without changing the outcome.
+    ///
+    /// This never looks at the wall clock. Pacing frames in real time is
+    /// left to the frontend (see [Clock](crate::clock::Clock)).
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.flags.speed = 10;
+    /// let mut bus = bus!{
+    ///     Program [0x0200..0x0f00] = &[
+    ///         0x60, 0x05, // mov #05, v0
+    ///         0xf0, 0x15, // mov v0, DT
+    ///         0x71, 0x01, // add #01, v1
+    ///         0x12, 0x04, // jump 0x204
+    ///     ],
+    /// };
+    /// cpu.run_frame(&mut bus)
+    ///     .expect("The program should only have valid opcodes.");
+    /// assert_eq!(10, cpu.cycle());
+    /// assert_eq!(4, cpu.delay());
+    /// ```
+    /// With [Flags::vip_timing], each instruction costs its COSMAC VIP cycle count
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut cpu = CPU::default();
+    /// cpu.flags.vip_timing = true;
+    /// let mut bus = bus!{
+    ///     Program [0x0200..0x0f00] = &[
+    ///         0x60, 0x00, // mov #00, v0
+    ///         0x12, 0x00, // jump 0x200
+    ///     ],
+    /// };
+    /// cpu.run_frame(&mut bus)
+    ///     .expect("The program should only have valid opcodes.");
+    /// // The display takes 1821 of the frame's 3668 cycles,
+    /// // and each iteration of the loop costs 6 + 23 cycles
+    /// assert_eq!((3668 - 1821) / 29 * 2 + 2, cpu.cycle());
+    /// ```
+    pub fn run_frame(&mut self, bus: &mut Bus) -> Result<&mut Self> {
+        while self.frame_cycles < self.frame_length() && !self.flags.pause {
+            let pc = self.pc;
+            self.tick(bus)?;
+            if self.flags.idle_skip
+                && !self.flags.debug
+                && self.tracer.is_none()
+                && !self.watching(bus)
+            {
+                self.skip_idle_loop(pc, bus);
+            }
+        }
+        self.idle = None;
+        self.vertical_blank();
+        Ok(self)
+    }
+
+    /// Simulates vertical blanking, if the current frame is over
     ///
-    /// If monotonic timing is `enabled`:
-    /// - Ticks the sound and delay timers according to CPU cycle count
+    /// Once a frame's worth of instructions (or cycles, with [Flags::vip_timing])
+    /// have been spent:
+    /// - Notes whether the frame made a sound ([CPU::pattern_phase]), then ticks the sound
+    ///   and delay timers once
     /// - Disables framepause
-    /// If monotonic timing is `disabled`:
-    /// - Subtracts the elapsed time in fractions of a frame
-    ///   from st/dt
-    /// - Disables framepause if the duration exceeds that of a frame
     #[inline(always)]
     pub fn vertical_blank(&mut self) -> &mut Self {
         if self.flags.pause {
             return self;
         }
-        // Use a monotonic counter when testing
-        if let Some(speed) = self.flags.monotonic {
-            if self.flags.draw_wait {
-                self.flags.draw_wait = self.cycle % speed != 0;
-            }
-            let speed = 1.0 / speed as f64;
-            self.delay -= speed;
-            self.sound -= speed;
-            return self;
-        };
-
-        // Convert the elapsed time to 60ths of a second
-        let frame = Instant::now();
-        let time = (frame - self.timers.frame).as_secs_f64() * 60.0;
-        self.timers.frame = frame;
-        if time > 1.0 {
+        let length = self.frame_length();
+        if self.frame_cycles >= length {
+            self.frame_cycles -= length;
             self.flags.draw_wait = false;
+            // The frame which just ended sounded if the timer was running before it ticks
+            let step = self.playback_rate() / PATTERN_BITS / FRAME_RATE;
+            self.phase = match (self.sound > 0.0, self.phase) {
+                (true, Some(phase)) => Some((phase + step).fract()),
+                (true, None) => Some(0.0),
+                (false, _) => None,
+            };
+            self.delay = (self.delay - 1.0).max(0.0);
+            self.sound = (self.sound - 1.0).max(0.0);
         }
-        if self.delay > 0.0 {
-            self.delay -= time;
+        self
+    }
+
+    /// Skips the rest of the frame's iterations of a busy-wait loop, if `pc` closed one
+    ///
+    /// See [idle] for how loops are recognized.
+    fn skip_idle_loop(&mut self, pc: Adr, bus: &Bus) {
+        // Loops are only checked when they jump back to their first instruction
+        let head = match bus.get(pc as usize..).and_then(decoder::decode) {
+            Some((_, Insn::jmp { A })) if A <= pc && A == self.pc => A,
+            _ => return,
+        };
+        // The rest of the loop must run straight through to the jump
+        let mut addr = head as usize;
+        while addr < pc as usize {
+            match bus.get(addr..).and_then(decoder::decode) {
+                Some((len, insn)) if idle::is_pure(&insn) => addr += len,
+                _ => {
+                    self.idle = None;
+                    return;
+                }
+            }
         }
-        if self.sound > 0.0 {
-            self.sound -= time;
+        let now = idle::Snapshot::of(self);
+        if let Some((insns, cost)) = self
+            .idle
+            .as_ref()
+            .and_then(|then| now.iteration_since(then))
+        {
+            let iterations = (self.frame_length().saturating_sub(self.frame_cycles)) / cost;
+            self.cycle += iterations * insns;
+            self.frame_cycles += iterations * cost;
+        }
+        self.idle = Some(idle::Snapshot::of(self));
+    }
+
+    /// Gets the length of a frame, in instructions (or VIP machine cycles)
+    fn frame_length(&self) -> usize {
+        match self.flags.vip_timing {
+            true => timing::INTERPRETER_CYCLES,
+            false => self.flags.speed.max(1),
         }
-        self
     }
 
     /// Executes a single instruction
@@ -437,6 +797,9 @@ impl CPU {
     /// This result contains information about the breakpoint, but can be safely ignored.
     ///
     /// Returns [Error::UnimplementedInstruction] if the instruction at `pc` is unimplemented.
+    ///
+    /// If the bus is in strict mode, returns [Error::AccessViolation] or [Error::UnmappedAccess]
+    /// when the instruction at `pc` makes an illegal access, undoing it and leaving `pc` pointing at it.
     /// # Examples
     /// ```rust
     /// # use chirp::*;
@@ -459,7 +822,6 @@ impl CPU {
     /// # use chirp::error::Error;
     /// let mut cpu = CPU::default();
     /// # cpu.flags.debug = true;        // enable live disassembly
-    /// # cpu.flags.monotonic = Some(8); // enable monotonic/test timing
     /// let mut bus = bus!{
     ///     Program [0x0200..0x0f00] = &[
     ///         0xff, 0xff, // invalid!
@@ -471,58 +833,176 @@ impl CPU {
     ///     .expect_err("Should return Error::InvalidInstruction { 0xffff }");
     /// ```
     pub fn tick(&mut self, bus: &mut Bus) -> Result<&mut Self> {
+        let result = self.fetch_execute(bus);
+        // The flight recorder dumps the instructions which led up to the error,
+        // but a breakpoint or watchpoint is only a pause
+        if let (Err(e), Some(tracer)) = (&result, &mut self.tracer) {
+            if !matches!(e, Error::BreakpointHit { .. }) {
+                tracer.dump()?;
+            }
+        }
+        result.map(|_| self)
+    }
+
+    /// Runs the next instruction, for [CPU::tick]
+    fn fetch_execute(&mut self, bus: &mut Bus) -> Result<()> {
         // Do nothing if paused
         if self.flags.is_paused() {
-            // always tick in test mode
-            if self.flags.monotonic.is_some() {
+            // While the user has it paused, no time passes at all
+            if self.flags.pause {
+                return Ok(());
+            }
+            if self.flags.vip_timing {
+                // The VIP idles until the next frame interrupt while waiting for a key or vblank
+                self.frame_cycles = self.frame_cycles.max(timing::INTERPRETER_CYCLES);
+            } else {
+                // Waiting for a key or vblank still uses up one of the frame's instructions
                 self.cycle += 1;
+                self.frame_cycles += 1;
             }
-            return Ok(self);
+            return Ok(());
         }
         self.cycle += 1;
-        // fetch opcode
-        let opcode: &[u8; 2] = if let Some(slice) = bus.get(self.pc as usize..self.pc as usize + 2)
-        {
+        // fetch opcode (XO-Chip's `f000 aaaa` is the only 4-byte instruction)
+        let pc = self.pc as usize;
+        if let Some(sanitizer) = bus.sanitizer_mut() {
+            sanitizer.set_context(self.pc, self.cycle);
+        }
+        // Forget any access violations or watched accesses which didn't come from an instruction
+        bus.take_fault();
+        bus.clear_watched();
+        if !bus.access(Access::Execute, pc..pc + 2) {
+            if let Some(e) = bus.take_fault() {
+                self.flags.pause = true;
+                return Err(e);
+            }
+        }
+        let opcode: &[u8] = if let Some(slice) = bus.get(pc..pc + 4).or(bus.get(pc..pc + 2)) {
             slice
-                .try_into()
-                .expect("`slice` should be exactly 2 bytes.")
         } else {
-            return Err(Error::InvalidBusRange {
-                range: self.pc as usize..self.pc as usize + 2,
-            });
+            return Err(Error::InvalidBusRange { range: pc..pc + 2 });
         };
 
         // Print opcode disassembly:
         if self.flags.debug {
-            println!("{:?}", self.timers.insn.elapsed().bright_black());
-            self.timers.insn = Instant::now();
-            std::print!(
+            std::println!(
                 "{:3} {:03x}: {:<36}",
                 self.cycle.bright_black(),
                 self.pc,
-                self.disassembler.once(u16::from_be_bytes(*opcode))
+                self.disassembler.once_slice(opcode).1
             );
         }
 
-        // decode opcode
-        if let Ok((inc, insn)) = Insn::decode(opcode) {
+        // decode opcode (XO-Chip's extensions are only understood in XO-Chip mode)
+        let xochip = self.flags.mode == Mode::XOChip;
+        let decoded = decoder::decode(opcode).filter(|(_, insn)| xochip || !insn.is_xochip());
+
+        if let Some(tracer) = &mut self.tracer {
+            if tracer.wants(self.pc, self.cycle) {
+                let len = decoded.as_ref().map_or(2, |(len, _)| *len);
+                tracer.record(Record {
+                    cycle: self.cycle,
+                    pc: self.pc,
+                    opcode: opcode[..len].to_vec(),
+                    v: self.v,
+                    i: self.i,
+                    sp: self.sp,
+                    dt: self.delay as u8,
+                    st: self.sound as u8,
+                })?;
+            }
+        }
+        if let Some((inc, insn)) = decoded {
+            // The operand of a long instruction is fetched too
+            if !bus.access(Access::Execute, pc + 2..pc + inc) {
+                if let Some(e) = bus.take_fault() {
+                    self.flags.pause = true;
+                    return Err(e);
+                }
+            }
+            let registers = self.registers();
+            if self.flags.vip_timing {
+                let x = match insn {
+                    Insn::draw { x, .. } => self.v[x],
+                    _ => 0,
+                };
+                self.frame_cycles += timing::cycles(&insn, x);
+            } else {
+                self.frame_cycles += 1;
+            }
             self.pc = self.pc.wrapping_add(inc as u16);
-            self.execute(bus, insn);
+            bus.start_journal();
+            let executed = self.execute(bus, insn);
+            // Undo the whole instruction on an access violation, in strict mode
+            let fault = bus.take_fault();
+            bus.end_journal(fault.is_some());
+            executed?;
+            if let Some(e) = fault {
+                self.restore(registers);
+                self.flags.pause = true;
+                return Err(e);
+            }
+            if insn.is_skip() && self.pc == (pc + inc + 2) as Adr {
+                if self.flags.vip_timing {
+                    self.frame_cycles += timing::SKIP_TAKEN;
+                }
+                // XO-Chip skips hop over the entirety of a `f000 aaaa` long load
+                // (peeking at the skipped instruction isn't a memory access by the program)
+                if xochip && bus.get(pc + inc..pc + inc + 2) == Some(&[0xf0, 0x00]) {
+                    self.pc = self.pc.wrapping_add(2);
+                }
+            }
         } else {
             return Err(Error::UnimplementedInstruction {
-                word: u16::from_be_bytes(*opcode),
+                word: u16::from_be_bytes([opcode[0], opcode[1]]),
             });
         }
 
-        // process breakpoints
-        if !self.breakpoints.is_empty() && self.breakpoints.contains(&self.pc) {
+        // process watchpoints and breakpoints
+        if let Some(trigger) = bus.check_watchpoints().or_else(|| self.check_breakpoints()) {
             self.flags.pause = true;
             return Err(Error::BreakpointHit {
                 addr: self.pc,
                 next: bus.read(self.pc),
+                trigger,
             });
         }
-        Ok(self)
+        Ok(())
+    }
+
+    /// Copies the registers an instruction can change
+    fn registers(&self) -> Registers {
+        Registers {
+            pc: self.pc,
+            sp: self.sp,
+            i: self.i,
+            v: self.v,
+            delay: self.delay,
+            sound: self.sound,
+            rpl: self.rpl,
+            planes: self.planes,
+            pattern: self.pattern,
+            pitch: self.pitch,
+        }
+    }
+
+    /// Puts back the registers copied by [CPU::registers]
+    fn restore(&mut self, registers: Registers) {
+        let Registers {
+            pc,
+            sp,
+            i,
+            v,
+            delay,
+            sound,
+            rpl,
+            planes,
+            pattern,
+            pitch,
+        } = registers;
+        (self.pc, self.sp, self.i, self.v) = (pc, sp, i, v);
+        (self.delay, self.sound, self.rpl) = (delay, sound, rpl);
+        (self.planes, self.pattern, self.pitch) = (planes, pattern, pitch);
     }
 
     /// Dumps the current state of all CPU registers, and the cycle count
@@ -594,15 +1074,25 @@ impl Default for CPU {
             v: [0; 16],
             delay: 0.0,
             sound: 0.0,
+            planes: 1,
+            rpl: [0; 16],
+            pattern: None,
+            pitch: 64,
+            phase: None,
             cycle: 0,
+            frame_cycles: 0,
+            idle: None,
             keys: [false; 16],
+            rng: Default::default(),
             flags: Flags {
                 debug: true,
                 ..Default::default()
             },
-            timers: Default::default(),
             breakpoints: vec![],
+            conditional: vec![],
+            next_id: 0,
             disassembler: Dis::default(),
+            tracer: None,
         }
     }
 }
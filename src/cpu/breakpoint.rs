@@ -0,0 +1,682 @@
+//! Stops the [CPU] when a [Breakpoint] is reached, or a [Watchpoint] is accessed
+//!
+//! Plain breakpoints, set with [CPU::set_break], stop the CPU every time it reaches
+//! their address. A [Breakpoint] added with [CPU::add_breakpoint] can also have a
+//! [Condition] on the registers and timers, ignore its first few hits, or remove
+//! itself the first time it stops the CPU.
+//!
+//! A [Watchpoint] added with [CPU::add_watchpoint] stops the CPU after an instruction
+//! reads, writes, or changes memory in its range.
+//!
+//! Either way, the CPU stops with [Error::BreakpointHit], which carries the [Trigger].
+//! Breakpoints and watchpoints share one set of ids, and are kept in
+//! [save states](crate::state) along with their hit counts.
+
+use super::{Adr, CPU};
+use crate::{
+    bus::{Access, Bus},
+    debugger::number,
+    error::{Error, Result},
+    state::{invalid, Decoder, Encoder},
+};
+use std::{
+    fmt::{Display, Formatter},
+    ops::Range,
+    str::FromStr,
+};
+
+/// A breakpoint, with optional [Condition], ignore count, and one-shot behavior
+/// # Examples
+/// ```rust
+/// # use chirp::{*, cpu::breakpoint::*};
+/// # fn main() -> Result<()> {
+/// let bp = Breakpoint::new(0x204)
+///     .when("v3 == 0x10 && I > 0x400".parse()?)
+///     .ignoring(2)
+///     .once();
+/// assert_eq!(0x204, bp.addr());
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Breakpoint {
+    addr: Adr,
+    condition: Option<Condition>,
+    ignore: usize,
+    temporary: bool,
+    hits: usize,
+    id: usize,
+}
+
+impl Breakpoint {
+    /// Constructs a [Breakpoint] which stops every time the CPU reaches `addr`
+    pub fn new(addr: Adr) -> Self {
+        Self {
+            addr,
+            condition: None,
+            ignore: 0,
+            temporary: false,
+            hits: 0,
+            id: 0,
+        }
+    }
+    /// Only stops when the `condition` holds
+    pub fn when(mut self, condition: Condition) -> Self {
+        self.condition = Some(condition);
+        self
+    }
+    /// Lets the first `ignore` hits go by, without stopping
+    pub fn ignoring(mut self, ignore: usize) -> Self {
+        self.ignore = ignore;
+        self
+    }
+    /// Removes the breakpoint the first time it stops the CPU
+    pub fn once(mut self) -> Self {
+        self.temporary = true;
+        self
+    }
+    /// Gets the address of the breakpoint
+    pub fn addr(&self) -> Adr {
+        self.addr
+    }
+    /// Gets the condition of the breakpoint, if it has one
+    pub fn condition(&self) -> Option<&Condition> {
+        self.condition.as_ref()
+    }
+    /// Gets the number of times the breakpoint has been hit, including ignored hits
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+    /// Gets the id given to the breakpoint by [CPU::add_breakpoint]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    fn counters(&mut self) -> Counters<'_> {
+        Counters {
+            hits: &mut self.hits,
+            ignore: self.ignore,
+            temporary: self.temporary,
+            id: self.id,
+        }
+    }
+    /// Writes the breakpoint into a save state
+    pub(crate) fn save_state(&self, state: &mut Encoder) {
+        state.u16(self.addr);
+        let condition = self.condition.as_ref().map(Condition::to_string);
+        state.option(condition, |state, condition| {
+            state.bytes(condition.as_bytes())
+        });
+        save_counters(self.ignore, self.temporary, self.hits, self.id, state);
+    }
+    /// Reads a breakpoint written by [Breakpoint::save_state]
+    pub(crate) fn load_state(state: &mut Decoder) -> Result<Self> {
+        let addr = state.u16()?;
+        let condition = state.option(|state| {
+            let condition = std::str::from_utf8(state.bytes()?)
+                .map_err(|_| invalid("condition isn't UTF-8"))?;
+            condition.parse()
+        })?;
+        let (ignore, temporary, hits, id) = load_counters(state)?;
+        Ok(Self {
+            addr,
+            condition,
+            ignore,
+            temporary,
+            hits,
+            id,
+        })
+    }
+}
+
+/// Selects which accesses a [Watchpoint] stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Watch {
+    /// Stops when memory is read
+    Read,
+    /// Stops when memory is written, even with the value it already held
+    Write,
+    /// Stops when memory is written with a different value
+    Change,
+}
+
+impl Display for Watch {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Watch::Read => "read",
+            Watch::Write => "write",
+            Watch::Change => "change",
+        })
+    }
+}
+
+/// A watchpoint on a range of [Bus](crate::bus::Bus) addresses
+///
+/// Only the program's reads and writes are watched. Instruction fetches, and accesses
+/// from outside the CPU (like the frontend drawing the screen), aren't.
+/// # Examples
+/// ```rust
+/// # use chirp::{*, cpu::breakpoint::*};
+/// let wp = Watchpoint::new(0x3f0..0x3f1, Watch::Change).once();
+/// assert_eq!(0x3f0..0x3f1, wp.range());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Watchpoint {
+    range: Range<usize>,
+    watch: Watch,
+    ignore: usize,
+    temporary: bool,
+    hits: usize,
+    id: usize,
+}
+
+impl Watchpoint {
+    /// Constructs a [Watchpoint] which stops on every `watch` access in `range`
+    pub fn new(range: Range<usize>, watch: Watch) -> Self {
+        Self {
+            range,
+            watch,
+            ignore: 0,
+            temporary: false,
+            hits: 0,
+            id: 0,
+        }
+    }
+    /// Lets the first `ignore` hits go by, without stopping
+    pub fn ignoring(mut self, ignore: usize) -> Self {
+        self.ignore = ignore;
+        self
+    }
+    /// Removes the watchpoint the first time it stops the CPU
+    pub fn once(mut self) -> Self {
+        self.temporary = true;
+        self
+    }
+    /// Gets the watched range of addresses
+    pub fn range(&self) -> Range<usize> {
+        self.range.clone()
+    }
+    /// Gets the kind of access being watched
+    pub fn watch(&self) -> Watch {
+        self.watch
+    }
+    /// Gets the number of instructions which hit the watchpoint, including ignored hits
+    pub fn hits(&self) -> usize {
+        self.hits
+    }
+    /// Gets the id given to the watchpoint by [CPU::add_watchpoint]
+    pub fn id(&self) -> usize {
+        self.id
+    }
+    pub(crate) fn counters(&mut self) -> Counters<'_> {
+        Counters {
+            hits: &mut self.hits,
+            ignore: self.ignore,
+            temporary: self.temporary,
+            id: self.id,
+        }
+    }
+    /// Finds the first of an instruction's `accesses` (as access, address, old value and
+    /// new value) which this watchpoint is watching
+    pub(crate) fn find(&self, accesses: &[(Access, usize, u8, u8)]) -> Option<(usize, u8, u8)> {
+        accesses.iter().find_map(|&(access, addr, old, new)| {
+            let watched = match (self.watch, access) {
+                (Watch::Read, Access::Read) | (Watch::Write, Access::Write) => true,
+                (Watch::Change, Access::Write) => old != new,
+                _ => false,
+            };
+            (watched && self.range.contains(&addr)).then_some((addr, old, new))
+        })
+    }
+    /// Writes the watchpoint into a save state
+    pub(crate) fn save_state(&self, state: &mut Encoder) {
+        state.usize(self.range.start);
+        state.usize(self.range.end);
+        state.u8(match self.watch {
+            Watch::Read => 0,
+            Watch::Write => 1,
+            Watch::Change => 2,
+        });
+        save_counters(self.ignore, self.temporary, self.hits, self.id, state);
+    }
+    /// Reads a watchpoint written by [Watchpoint::save_state]
+    pub(crate) fn load_state(state: &mut Decoder) -> Result<Self> {
+        let range = state.usize()?..state.usize()?;
+        let watch = match state.u8()? {
+            0 => Watch::Read,
+            1 => Watch::Write,
+            2 => Watch::Change,
+            _ => return Err(invalid("unknown watch")),
+        };
+        let (ignore, temporary, hits, id) = load_counters(state)?;
+        Ok(Self {
+            range,
+            watch,
+            ignore,
+            temporary,
+            hits,
+            id,
+        })
+    }
+}
+
+fn save_counters(ignore: usize, temporary: bool, hits: usize, id: usize, state: &mut Encoder) {
+    state.usize(ignore);
+    state.bool(temporary);
+    state.usize(hits);
+    state.usize(id);
+}
+
+fn load_counters(state: &mut Decoder) -> Result<(usize, bool, usize, usize)> {
+    Ok((
+        state.usize()?,
+        state.bool()?,
+        state.usize()?,
+        state.usize()?,
+    ))
+}
+
+/// Counts a hit on a breakpoint or watchpoint, and decides whether it stops the CPU
+///
+/// Returns the id of the point which stopped the CPU, removing it if it's temporary.
+pub(crate) fn hit<T>(
+    points: &mut Vec<T>,
+    index: usize,
+    counters: fn(&mut T) -> Counters<'_>,
+) -> Option<usize> {
+    let Counters {
+        hits,
+        ignore,
+        temporary,
+        id,
+    } = counters(&mut points[index]);
+    *hits += 1;
+    if *hits <= ignore {
+        return None;
+    }
+    if temporary {
+        points.remove(index);
+    }
+    Some(id)
+}
+
+/// The parts of a breakpoint or watchpoint which decide whether a hit stops the CPU
+pub(crate) struct Counters<'a> {
+    hits: &'a mut usize,
+    ignore: usize,
+    temporary: bool,
+    id: usize,
+}
+
+impl CPU {
+    /// Adds a [Breakpoint], returning its id
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, cpu::breakpoint::*};
+    /// # fn main() -> Result<()> {
+    /// let mut cpu = CPU::default();
+    /// let mut bus = bus! {
+    ///     Program [0x0200..0x0f00] = &[
+    ///         0x70, 0x01, // add #01, v0
+    ///         0x12, 0x00, // jmp 200
+    ///     ],
+    /// };
+    /// let id = cpu.add_breakpoint(Breakpoint::new(0x202).when("v0 == 3".parse()?));
+    /// let Err(Error::BreakpointHit { trigger, .. }) = cpu.multistep(&mut bus, 10) else {
+    ///     panic!("The breakpoint should be hit");
+    /// };
+    /// assert_eq!(Trigger::Breakpoint { id: Some(id) }, trigger);
+    /// assert_eq!(3, cpu.v()[0]);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn add_breakpoint(&mut self, mut breakpoint: Breakpoint) -> usize {
+        self.next_id += 1;
+        breakpoint.id = self.next_id;
+        self.conditional.push(breakpoint);
+        self.next_id
+    }
+
+    /// Removes the [Breakpoint] with the given id, returning it
+    pub fn remove_breakpoint(&mut self, id: usize) -> Option<Breakpoint> {
+        let index = self.conditional.iter().position(|bp| bp.id == id)?;
+        Some(self.conditional.remove(index))
+    }
+
+    /// Gets the breakpoints added with [CPU::add_breakpoint]
+    pub fn conditional_breakpoints(&self) -> &[Breakpoint] {
+        &self.conditional
+    }
+
+    /// Adds a [Watchpoint] to the `bus`, returning its id
+    ///
+    /// Watchpoints are kept on the [Bus], which watches every access, but take their ids
+    /// from the same count as breakpoints.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, cpu::breakpoint::*};
+    /// let mut cpu = CPU::default();
+    /// let mut bus = bus! {
+    ///     Program [0x0200..0x0f00] = &[
+    ///         0xa3, 0xf0, // mov $3f0, I
+    ///         0x60, 0x2a, // mov #2a, v0
+    ///         0xf0, 0x55, // dma v0, I
+    ///     ],
+    /// };
+    /// let bp = cpu.add_breakpoint(Breakpoint::new(0x300));
+    /// let id = cpu.add_watchpoint(&mut bus, Watchpoint::new(0x3f0..0x3f1, Watch::Change));
+    /// assert_ne!(bp, id);
+    /// let Err(Error::BreakpointHit { addr, trigger, .. }) = cpu.multistep(&mut bus, 3) else {
+    ///     panic!("The watchpoint should be hit");
+    /// };
+    /// assert_eq!(0x206, addr);
+    /// assert_eq!(
+    ///     Trigger::Watchpoint { id, watch: Watch::Change, addr: 0x3f0, old: 0, new: 0x2a },
+    ///     trigger
+    /// );
+    /// ```
+    pub fn add_watchpoint(&mut self, bus: &mut Bus, mut watchpoint: Watchpoint) -> usize {
+        self.next_id += 1;
+        watchpoint.id = self.next_id;
+        bus.watchpoints_mut().push(watchpoint);
+        self.next_id
+    }
+
+    /// Returns true if there are conditional breakpoints or watchpoints, which skipping
+    /// busy-wait loops could jump past
+    pub(super) fn watching(&self, bus: &Bus) -> bool {
+        !self.conditional.is_empty() || !bus.watchpoints().is_empty()
+    }
+
+    /// Takes every breakpoint and watchpoint out of the machine, so it can run without
+    /// stopping at, or counting hits on, any of them. Put them back with [Points::restore].
+    pub(crate) fn take_points(&mut self, bus: &mut Bus) -> Points {
+        Points {
+            breakpoints: std::mem::take(&mut self.breakpoints),
+            conditional: std::mem::take(&mut self.conditional),
+            watchpoints: std::mem::take(bus.watchpoints_mut()),
+        }
+    }
+
+    /// Checks the breakpoints at the current pc, and gets the one which stops the CPU
+    pub(super) fn check_breakpoints(&mut self) -> Option<Trigger> {
+        if self.breakpoints.contains(&self.pc) {
+            return Some(Trigger::Breakpoint { id: None });
+        }
+        let mut index = 0;
+        while index < self.conditional.len() {
+            let bp = &self.conditional[index];
+            if bp.addr == self.pc && bp.condition.as_ref().is_none_or(|c| c.eval(self)) {
+                let stop = hit(&mut self.conditional, index, Breakpoint::counters);
+                if let Some(id) = stop {
+                    return Some(Trigger::Breakpoint { id: Some(id) });
+                }
+            }
+            index += 1;
+        }
+        None
+    }
+}
+
+/// The breakpoints and watchpoints taken out of a machine by [CPU::take_points]
+#[derive(Debug)]
+pub(crate) struct Points {
+    breakpoints: Vec<Adr>,
+    conditional: Vec<Breakpoint>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Points {
+    /// Puts the breakpoints and watchpoints back, replacing any the machine has now
+    pub(crate) fn restore(self, cpu: &mut CPU, bus: &mut Bus) {
+        cpu.breakpoints = self.breakpoints;
+        cpu.conditional = self.conditional;
+        *bus.watchpoints_mut() = self.watchpoints;
+    }
+}
+
+/// What stopped the CPU with [Error::BreakpointHit]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Trigger {
+    /// A breakpoint was reached
+    Breakpoint {
+        /// The id from [CPU::add_breakpoint], or [None] for a plain breakpoint
+        id: Option<usize>,
+    },
+    /// A watched address was accessed
+    Watchpoint {
+        /// The id from [CPU::add_watchpoint]
+        id: usize,
+        /// The kind of access
+        watch: Watch,
+        /// The address which was accessed
+        addr: usize,
+        /// The value before the access
+        old: u8,
+        /// The value after the access
+        new: u8,
+    },
+}
+
+impl Display for Trigger {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Trigger::Breakpoint { id: None } => write!(f, "breakpoint"),
+            Trigger::Breakpoint { id: Some(id) } => write!(f, "breakpoint {id}"),
+            Trigger::Watchpoint {
+                id,
+                watch,
+                addr,
+                old,
+                new,
+            } => write!(
+                f,
+                "watchpoint {id} ({watch} at {addr:03x}: {old:02x} -> {new:02x})"
+            ),
+        }
+    }
+}
+
+/// A condition on the registers and timers, like `v3 == 0x10 && I > 0x400`
+///
+/// A condition is made of comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) joined by
+/// `&&` and `||`, where `&&` binds more tightly. Either side of a comparison can be:
+/// - A register: `v0`..`vF`, `I`, `pc`, `sp`
+/// - A timer: `dt` (delay) or `st` (sound)
+/// - The `cycle` count
+/// - A number, in decimal, or in hex with a `0x`, `#` or `$` prefix
+/// # Examples
+/// ```rust
+/// # use chirp::{*, cpu::breakpoint::*};
+/// # fn main() -> Result<()> {
+/// let condition: Condition = "vF != 0 || v3 == 0x10 && I > $400".parse()?;
+/// assert_eq!("vf != 0x0 || v3 == 0x10 && i > 0x400", condition.to_string());
+/// assert!("v3 = 1".parse::<Condition>().is_err());
+/// #   Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(
+    feature = "serde",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(into = "String", try_from = "String")
+)]
+pub struct Condition {
+    // Any of these must hold, where all of the comparisons in each must hold
+    any: Vec<Vec<Comparison>>,
+}
+
+impl Condition {
+    /// Checks whether the condition holds for the `cpu`
+    pub fn eval(&self, cpu: &CPU) -> bool {
+        self.any
+            .iter()
+            .any(|all| all.iter().all(|cmp| cmp.eval(cpu)))
+    }
+}
+
+impl FromStr for Condition {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCondition {
+            condition: s.to_string(),
+        };
+        let any = s
+            .split("||")
+            .map(|all| {
+                all.split("&&")
+                    .map(|cmp| cmp.parse().map_err(|_| invalid()))
+                    .collect::<Result<Vec<_>>>()
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self { any })
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> Self {
+        condition.to_string()
+    }
+}
+
+impl Display for Condition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (index, all) in self.any.iter().enumerate() {
+            if index > 0 {
+                write!(f, " || ")?;
+            }
+            for (index, cmp) in all.iter().enumerate() {
+                if index > 0 {
+                    write!(f, " && ")?;
+                }
+                write!(f, "{cmp}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// One side of a [Comparison]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Operand {
+    V(usize),
+    I,
+    Pc,
+    Sp,
+    Delay,
+    Sound,
+    Cycle,
+    Value(usize),
+}
+
+impl Operand {
+    fn eval(&self, cpu: &CPU) -> usize {
+        match *self {
+            Operand::V(reg) => cpu.v[reg] as usize,
+            Operand::I => cpu.i as usize,
+            Operand::Pc => cpu.pc as usize,
+            Operand::Sp => cpu.sp,
+            Operand::Delay => cpu.delay as usize,
+            Operand::Sound => cpu.sound as usize,
+            Operand::Cycle => cpu.cycle,
+            Operand::Value(value) => value,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        let s = s.trim().to_lowercase();
+        let hex = |digits: &str| usize::from_str_radix(digits, 16).map_err(|_| ());
+        Ok(match s.as_str() {
+            "i" => Operand::I,
+            "pc" => Operand::Pc,
+            "sp" => Operand::Sp,
+            "dt" => Operand::Delay,
+            "st" => Operand::Sound,
+            "cycle" => Operand::Cycle,
+            _ => {
+                if let Some(reg) = s.strip_prefix('v').filter(|reg| reg.len() == 1) {
+                    Operand::V(hex(reg)?)
+                } else {
+                    Operand::Value(number(&s).ok_or(())?)
+                }
+            }
+        })
+    }
+}
+
+impl Display for Operand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operand::V(reg) => write!(f, "v{reg:x}"),
+            Operand::I => write!(f, "i"),
+            Operand::Pc => write!(f, "pc"),
+            Operand::Sp => write!(f, "sp"),
+            Operand::Delay => write!(f, "dt"),
+            Operand::Sound => write!(f, "st"),
+            Operand::Cycle => write!(f, "cycle"),
+            Operand::Value(value) => write!(f, "{value:#x}"),
+        }
+    }
+}
+
+/// Compares two [Operand]s
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct Comparison {
+    lhs: Operand,
+    op: &'static str,
+    rhs: Operand,
+}
+
+impl Comparison {
+    /// The comparison operators. Longer operators come first, so they're found first.
+    const OPS: [&'static str; 6] = ["==", "!=", "<=", ">=", "<", ">"];
+
+    fn eval(&self, cpu: &CPU) -> bool {
+        let (lhs, rhs) = (self.lhs.eval(cpu), self.rhs.eval(cpu));
+        match self.op {
+            "==" => lhs == rhs,
+            "!=" => lhs != rhs,
+            "<=" => lhs <= rhs,
+            ">=" => lhs >= rhs,
+            "<" => lhs < rhs,
+            _ => lhs > rhs,
+        }
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, ()> {
+        let (op, at) = Self::OPS
+            .into_iter()
+            .find_map(|op| Some((op, s.find(op)?)))
+            .ok_or(())?;
+        Ok(Self {
+            lhs: s[..at].parse()?,
+            op,
+            rhs: s[at + op.len()..].parse()?,
+        })
+    }
+}
+
+impl Display for Comparison {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {} {}", self.lhs, self.op, self.rhs)
+    }
+}
@@ -0,0 +1,41 @@
+//! Decodes instructions with a lookup table, instead of matching every opcode bit by bit
+//!
+//! Every 2-byte opcode is decoded once, the first time the table is used.
+//! Because the table is indexed by opcode, rather than by address, it never
+//! goes stale when a program writes to memory.
+//!
+//! The only 4-byte instruction, XO-Chip's `F000 nnnn`, is decoded on the spot.
+
+use super::disassembler::Insn;
+use imperative_rs::InstructionSet;
+use std::sync::OnceLock;
+
+/// Gets the decoded form of every 2-byte opcode
+fn table() -> &'static [Option<Insn>] {
+    static TABLE: OnceLock<Box<[Option<Insn>]>> = OnceLock::new();
+    TABLE.get_or_init(|| {
+        (0..=u16::MAX)
+            .map(|word| match Insn::decode(&word.to_be_bytes()) {
+                Ok((2, insn)) => Some(insn),
+                _ => None,
+            })
+            .collect()
+    })
+}
+
+/// Decodes the instruction at the start of `bytes`, returning its length and [Insn]
+/// # Examples
+/// ```rust
+/// # use chirp::cpu::{decoder::decode, disassembler::Insn};
+/// assert_eq!(Some((2, Insn::cls)), decode(b"\x00\xe0"));
+/// assert_eq!(Some((4, Insn::longI { A: 0x1234 })), decode(b"\xf0\x00\x12\x34"));
+/// assert_eq!(None, decode(b"\x50\x01"));
+/// ```
+#[inline]
+pub fn decode(bytes: &[u8]) -> Option<(usize, Insn)> {
+    let word = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]);
+    match word {
+        0xf000 => Insn::decode(bytes).ok(),
+        word => table()[word as usize].map(|insn| (2, insn)),
+    }
+}
@@ -8,10 +8,22 @@ use std::fmt::Display;
 pub trait Disassembler {
     /// Disassemble a single instruction
     fn once(&self, insn: u16) -> String;
+    /// Disassemble a single instruction from a slice of memory,
+    /// returning the length of the instruction in bytes
+    ///
+    /// By default, only the first two bytes are disassembled, with [Disassembler::once].
+    fn once_slice(&self, bytes: &[u8]) -> (usize, String) {
+        let insn = u16::from_be_bytes([
+            bytes.first().copied().unwrap_or_default(),
+            bytes.get(1).copied().unwrap_or_default(),
+        ]);
+        (2, self.once(insn))
+    }
 }
 
 #[allow(non_camel_case_types, non_snake_case, missing_docs)]
 #[derive(Clone, Copy, Debug, InstructionSet, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 /// Implements a Disassembler using imperative_rs
 pub enum Insn {
     // Base instruction set
@@ -146,6 +158,57 @@ pub enum Insn {
     /// | fx85 | Load from "flag registers"
     #[opcode = "0xfx85"]
     flgi { x: usize },
+
+    // XO-Chip extensions
+    /// | 00dN | Scroll the screen up
+    #[opcode = "0x00dn"]
+    scu { n: u8 },
+    /// | 5xy2 | Save registers vX..=vY to memory at I
+    #[opcode = "0x5xy2"]
+    save { y: usize, x: usize },
+    /// | 5xy3 | Load registers vX..=vY from memory at I
+    #[opcode = "0x5xy3"]
+    load { y: usize, x: usize },
+    /// | f000 aaaa | Load long address #a into register I
+    #[opcode = "0xf000AAAA"]
+    longI { A: u16 },
+    /// | fN01 | Select bitplanes N for drawing
+    #[opcode = "0xfn01"]
+    plane { n: u8 },
+    /// | f002 | Load 16-byte audio pattern from I
+    #[opcode = "0xf002"]
+    audio,
+    /// | fx3a | Set the audio pitch register to vX
+    #[opcode = "0xfx3a"]
+    pitch { x: usize },
+}
+
+impl Insn {
+    /// Returns true if the instruction conditionally skips the next instruction
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            self,
+            Insn::seb { .. }
+                | Insn::sneb { .. }
+                | Insn::se { .. }
+                | Insn::sne { .. }
+                | Insn::sek { .. }
+                | Insn::snek { .. }
+        )
+    }
+    /// Returns true if the instruction is an XO-Chip extension
+    pub fn is_xochip(&self) -> bool {
+        matches!(
+            self,
+            Insn::scu { .. }
+                | Insn::save { .. }
+                | Insn::load { .. }
+                | Insn::longI { .. }
+                | Insn::plane { .. }
+                | Insn::audio
+                | Insn::pitch { .. }
+        )
+    }
 }
 
 impl Display for Insn {
@@ -197,6 +260,14 @@ impl Display for Insn {
             Insn::hfont { x }      => write!(f, "hfont  v{x:X}"),
             Insn::flgo { x }       => write!(f, "flgo   v{x:X}"),
             Insn::flgi { x }       => write!(f, "flgi   v{x:X}"),
+            // XO-Chip extensions
+            Insn::scu { n }        => write!(f, "scu    #{n:x}"),
+            Insn::save { y, x }    => write!(f, "save   v{x:X}..v{y:X}"),
+            Insn::load { y, x }    => write!(f, "load   v{x:X}..v{y:X}"),
+            Insn::longI { A }      => write!(f, "mov    ${A:04x}, I"),
+            Insn::plane { n }      => write!(f, "plane  #{n:x}"),
+            Insn::audio            => write!(f, "audio  &I"),
+            Insn::pitch { x }      => write!(f, "pitch  v{x:X}"),
         }
     }
 }
@@ -221,10 +292,20 @@ impl Default for Dis {
 
 impl Disassembler for Dis {
     fn once(&self, insn: u16) -> String {
-        if let Ok((_, insn)) = Insn::decode(&insn.to_be_bytes()) {
-            format!("{}", insn.style(self.normal))
+        self.once_slice(&insn.to_be_bytes()).1
+    }
+    fn once_slice(&self, bytes: &[u8]) -> (usize, String) {
+        if let Ok((len, insn)) = Insn::decode(bytes) {
+            (len, format!("{}", insn.style(self.normal)))
         } else {
-            format!("{}", format_args!("inval  {insn:04x}").style(self.invalid))
+            let insn = u16::from_be_bytes([
+                bytes.first().copied().unwrap_or_default(),
+                bytes.get(1).copied().unwrap_or_default(),
+            ]);
+            (
+                2,
+                format!("{}", format_args!("inval  {insn:04x}").style(self.invalid)),
+            )
         }
     }
 }
@@ -1,9 +1,10 @@
 //! Represents [Flags] that aid in implementation but aren't a part of the Chip-8 spec
 
-use super::{Mode, Quirks};
+use super::{stack::StackPolicy, Mode, Quirks};
 
 /// Represents flags that aid in implementation but aren't a part of the Chip-8 spec
-#[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Flags {
     /// Set when debug (live disassembly) mode enabled
     pub debug: bool,
@@ -21,8 +22,37 @@ pub struct Flags {
     pub mode: Mode,
     /// Represents the set of emulator [Quirks] to enable, independent of the [Mode]
     pub quirks: Quirks,
-    /// Represents the number of instructions to run per tick of the internal timer
-    pub monotonic: Option<usize>,
+    /// Selects what happens when the stack overflows or underflows
+    pub stack_policy: StackPolicy,
+    /// Limits the number of nested subroutine calls, on top of the size of the stack region
+    pub stack_depth: Option<usize>,
+    /// Represents the number of instructions to run per frame (per tick of the timers)
+    pub speed: usize,
+    /// Charges each instruction its COSMAC VIP machine-cycle cost, and ticks the
+    /// timers once a frame's worth of cycles have been spent. See [super::timing]
+    pub vip_timing: bool,
+    /// Fast-forwards busy-wait loops to the end of the frame, in [CPU::run_frame](super::CPU::run_frame)
+    pub idle_skip: bool,
+}
+
+impl Default for Flags {
+    fn default() -> Self {
+        Self {
+            debug: false,
+            pause: false,
+            keypause: false,
+            draw_wait: false,
+            draw_mode: false,
+            lastkey: None,
+            mode: Default::default(),
+            quirks: Default::default(),
+            stack_policy: Default::default(),
+            stack_depth: None,
+            speed: 8,
+            vip_timing: false,
+            idle_skip: false,
+        }
+    }
 }
 
 impl Flags {
@@ -0,0 +1,55 @@
+//! Selects the [BigFont] loaded into the [Charset](crate::bus::Region::Charset) region
+//!
+//! Every charset holds the 16 small 4x5 hex digits used by `Fx29`,
+//! followed by the 8x10 big digits used by `Fx30`.
+
+use crate::error::Error;
+use std::str::FromStr;
+
+/// Size of a small (4x5) font glyph, in bytes
+pub const SMALL_GLYPH: u16 = 5;
+/// Size of a big (8x10) font glyph, in bytes
+pub const BIG_GLYPH: u16 = 10;
+/// Offset of the big font from the start of the charset, in bytes
+pub const BIG_FONT_OFFSET: u16 = 16 * SMALL_GLYPH;
+
+/// Selects the style of the big (8x10) font used by `Fx30`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum BigFont {
+    /// Super-Chip 1.1's big font, which only has the digits 0-9
+    #[default]
+    SChip,
+    /// Octo's big font, which has the full hex set 0-F
+    Octo,
+}
+
+impl BigFont {
+    /// Gets the charset, containing the small font followed by this big font
+    /// # Examples
+    /// ```rust
+    /// # use chirp::cpu::font::*;
+    /// // The Super-Chip font has no glyphs for A-F
+    /// assert_eq!(180, BigFont::SChip.charset().len());
+    /// assert_eq!(240, BigFont::Octo.charset().len());
+    /// ```
+    pub fn charset(&self) -> &'static [u8] {
+        match self {
+            BigFont::SChip => include_bytes!("../mem/charset.bin"),
+            BigFont::Octo => include_bytes!("../mem/charset-octo.bin"),
+        }
+    }
+}
+
+impl FromStr for BigFont {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "schip" | "superchip" => Ok(BigFont::SChip),
+            "octo" => Ok(BigFont::Octo),
+            _ => Err(Error::InvalidFont {
+                font: s.to_string(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,80 @@
+//! Recognizes busy-wait loops, so [CPU::run_frame](super::CPU::run_frame) can skip them
+//!
+//! Inside a frame, the timers and keys can't change. So if a loop comes back around
+//! to its first instruction with the CPU in exactly the same state, without having
+//! written to memory, every iteration left in the frame would do the same thing.
+//! Those iterations are skipped, and their instructions and cycles are counted
+//! as though they had run.
+//!
+//! Only whole iterations are skipped. The remains of the frame run as normal,
+//! so the frame ends with the CPU in exactly the state it would've been in.
+
+use super::{disassembler::Insn, Adr, CPU};
+
+/// The parts of the [CPU]'s state which a pure loop can read or change
+#[derive(Clone, Debug, PartialEq)]
+pub(super) struct Snapshot {
+    pc: Adr,
+    sp: usize,
+    i: Adr,
+    v: [u8; 16],
+    delay: f64,
+    sound: f64,
+    keys: [bool; 16],
+    lastkey: Option<usize>,
+    cycle: usize,
+    frame_cycles: usize,
+}
+
+impl Snapshot {
+    /// Takes a snapshot of the [CPU]
+    pub(super) fn of(cpu: &CPU) -> Self {
+        Self {
+            pc: cpu.pc,
+            sp: cpu.sp,
+            i: cpu.i,
+            v: cpu.v,
+            delay: cpu.delay,
+            sound: cpu.sound,
+            keys: cpu.keys,
+            lastkey: cpu.flags.lastkey,
+            cycle: cpu.cycle,
+            frame_cycles: cpu.frame_cycles,
+        }
+    }
+
+    /// If `self` is one iteration after `earlier`, in the same frame, gets the number of
+    /// instructions and frame cycles spent in that iteration
+    pub(super) fn iteration_since(&self, earlier: &Self) -> Option<(usize, usize)> {
+        let same_state = Self {
+            cycle: earlier.cycle,
+            frame_cycles: earlier.frame_cycles,
+            ..self.clone()
+        } == *earlier;
+        match (same_state, self.frame_cycles > earlier.frame_cycles) {
+            (true, true) => Some((
+                self.cycle - earlier.cycle,
+                self.frame_cycles - earlier.frame_cycles,
+            )),
+            _ => None,
+        }
+    }
+}
+
+/// Checks whether `insn` only depends on, and only changes, the state in a [Snapshot]
+///
+/// Instructions which write to memory, touch the stack, draw, or jump, are never idle.
+/// (The jump which closes the loop is checked separately.)
+#[rustfmt::skip]
+pub(super) fn is_pure(insn: &Insn) -> bool {
+    matches!(
+        insn,
+        Insn::seb   { .. } | Insn::sneb  { .. } | Insn::se    { .. } | Insn::sne   { .. }
+        | Insn::movb{ .. } | Insn::addb  { .. } | Insn::mov   { .. } | Insn::or    { .. }
+        | Insn::and { .. } | Insn::xor   { .. } | Insn::add   { .. } | Insn::sub   { .. }
+        | Insn::shr { .. } | Insn::bsub  { .. } | Insn::shl   { .. } | Insn::movI  { .. }
+        | Insn::longI{ .. }| Insn::addI  { .. } | Insn::getdt { .. } | Insn::sek   { .. }
+        | Insn::snek{ .. } | Insn::font  { .. } | Insn::hfont { .. } | Insn::dmai  { .. }
+        | Insn::load{ .. }
+    )
+}
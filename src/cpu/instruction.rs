@@ -3,19 +3,23 @@
 
 //! Contains implementations for each [Insn] as private member functions of [CPU]
 
-use super::*;
+use super::{
+    font::{BIG_FONT_OFFSET, BIG_GLYPH, SMALL_GLYPH},
+    *,
+};
+use crate::sanitizer::Finding;
 
 impl CPU {
     /// Executes a single [Insn]
     #[inline(always)]
     #[rustfmt::skip]
-    pub(super) fn execute(&mut self, bus: &mut Bus, instruction: Insn) {
+    pub(super) fn execute(&mut self, bus: &mut Bus, instruction: Insn) -> Result<()> {
         match instruction {
             // Core Chip-8 instructions
             Insn::cls               => self.clear_screen(bus),
-            Insn::ret               => self.ret(bus),
+            Insn::ret               => self.ret(bus)?,
             Insn::jmp   {       A } => self.jump(A),
-            Insn::call  {       A } => self.call(A, bus),
+            Insn::call  {       A } => self.call(A, bus)?,
             Insn::seb   {    x, B } => self.skip_equals_immediate(x, B),
             Insn::sneb  {    x, B } => self.skip_not_equals_immediate(x, B),
             Insn::se    { y, x    } => self.skip_equals(x, y),
@@ -54,9 +58,18 @@ impl CPU {
             Insn::lores             => self.init_lores(bus),
             Insn::hires             => self.init_hires(bus),
             Insn::hfont {    x    } => self.load_big_sprite(x),
-            Insn::flgo  {    x    } => self.store_flags(x, bus),
-            Insn::flgi  {    x    } => self.load_flags(x, bus),
+            Insn::flgo  {    x    } => self.store_flags(x),
+            Insn::flgi  {    x    } => self.load_flags(x),
+            // XO-Chip extensions
+            Insn::scu   {       n } => self.scroll_up(n, bus),
+            Insn::save  { y, x    } => self.store_range(x, y, bus),
+            Insn::load  { y, x    } => self.load_range(x, y, bus),
+            Insn::longI {       A } => self.load_i_long(A),
+            Insn::plane {       n } => self.select_planes(n),
+            Insn::audio             => self.load_pattern(bus),
+            Insn::pitch {    x    } => self.load_pitch(x),
         }
+        Ok(())
     }
 }
 
@@ -70,13 +83,35 @@ impl CPU {
     /// |`00e0`| Clears the screen memory to 0
     #[inline(always)]
     pub(super) fn clear_screen(&mut self, bus: &mut Bus) {
-        bus.clear_region(Region::Screen);
+        let len = self.plane_len();
+        for plane in self.selected_planes(bus) {
+            if let Some(plane) = bus.get_mut(plane..plane + len) {
+                plane.fill(0);
+            }
+        }
     }
     /// |`00ee`| Returns from subroutine
-    #[inline(always)]
-    pub(super) fn ret(&mut self, bus: &impl Read<u16>) {
+    ///
+    /// Returning with an empty stack is handled according to the [StackPolicy]
+    #[inline(always)]
+    pub(super) fn ret(&mut self, bus: &Bus) -> Result<()> {
+        if let Some((top, capacity)) = self.stack_bounds(bus) {
+            if self.sp == top {
+                match self.flags.stack_policy {
+                    StackPolicy::Error => {
+                        return Err(
+                            self.stack_error(top, |pc, depth| Error::StackUnderflow { pc, depth })
+                        )
+                    }
+                    // Pop the oldest slot of the ring
+                    StackPolicy::Wrap => self.sp = top - 2 * capacity,
+                    StackPolicy::Emulate => {}
+                }
+            }
+        }
         self.sp = self.sp.wrapping_add(2);
         self.pc = bus.read(self.sp);
+        Ok(())
     }
 }
 
@@ -96,11 +131,66 @@ impl CPU {
 // |`2aaa`| Pushes pc onto the stack, then jumps to a
 impl CPU {
     /// |`2aaa`| Pushes pc onto the stack, then jumps to a
-    #[inline(always)]
-    pub(super) fn call(&mut self, a: Adr, bus: &mut impl Write<u16>) {
+    ///
+    /// Calling with a full stack is handled according to the [StackPolicy]
+    #[inline(always)]
+    pub(super) fn call(&mut self, a: Adr, bus: &mut Bus) -> Result<()> {
+        if let Some((top, capacity)) = self.stack_bounds(bus) {
+            if self.stack_depth(top) >= capacity {
+                match self.flags.stack_policy {
+                    StackPolicy::Error => {
+                        return Err(
+                            self.stack_error(top, |pc, depth| Error::StackOverflow { pc, depth })
+                        )
+                    }
+                    // Push into the newest slot of the ring
+                    StackPolicy::Wrap => self.sp = top,
+                    StackPolicy::Emulate => {}
+                }
+            }
+        }
         bus.write(self.sp, self.pc);
         self.sp = self.sp.wrapping_sub(2);
         self.pc = a;
+        Ok(())
+    }
+
+    /// Gets the top of the stack, and the number of return addresses it can hold,
+    /// if the stack is checked
+    ///
+    /// The stack is only checked if the [Region::Stack] exists and contains the stack pointer.
+    #[inline(always)]
+    fn stack_bounds(&self, bus: &Bus) -> Option<(usize, usize)> {
+        if self.flags.stack_policy == StackPolicy::Emulate {
+            return None;
+        }
+        let stack = bus.get_region_range(Region::Stack)?;
+        let top = stack.end.checked_sub(2)?;
+        // sp sits one slot below the bottom of the stack when the stack is full
+        if self.sp > top || self.sp + 2 < stack.start {
+            return None;
+        }
+        let capacity = (top - stack.start) / 2 + 1;
+        Some((
+            top,
+            self.flags
+                .stack_depth
+                .map_or(capacity, |depth| depth.min(capacity)),
+        ))
+    }
+
+    /// Gets the number of return addresses on a stack whose top is `top`
+    #[inline(always)]
+    fn stack_depth(&self, top: usize) -> usize {
+        (top - self.sp) / 2
+    }
+
+    /// Rewinds to the offending instruction, pauses, and builds a stack error
+    #[inline(always)]
+    fn stack_error(&mut self, top: usize, error: impl FnOnce(Adr, usize) -> Error) -> Error {
+        self.pc = self.pc.wrapping_sub(2);
+        self.flags.pause = true;
+        error(self.pc, self.stack_depth(top))
     }
 }
 
@@ -303,7 +393,7 @@ impl CPU {
     /// |`Cxbb`| Stores a random number & the provided byte into vX
     #[inline(always)]
     pub(super) fn rand(&mut self, x: Reg, b: u8) {
-        self.v[x] = random::<u8>() & b;
+        self.v[x] = self.rng.0.next_u8() & b;
     }
 }
 
@@ -333,26 +423,62 @@ impl CPU {
 
     #[inline(always)]
     pub(super) fn draw_sprite(&mut self, x: u16, y: u16, n: Nib, w: u16, h: u16, bus: &mut Bus) {
-        let w_bytes = w / 8;
         self.v[0xf] = 0;
-        if let Some(sprite) = bus.get(self.i as usize..(self.i + n as u16) as usize) {
-            let sprite = sprite.to_vec();
+        let planes = self.selected_planes(bus);
+        self.sanitize_i(n as usize * planes.len(), bus);
+        // Each selected plane consumes the next n bytes of sprite data
+        for (index, plane) in planes.into_iter().enumerate() {
+            let Some(sprite) = self.get_at_i(index * n as usize, n as usize, bus) else {
+                continue;
+            };
             for (line, &sprite) in sprite.iter().enumerate() {
-                let line = line as u16;
-                if y + line >= h {
-                    break;
-                }
-                let sprite = (sprite as u16) << (8 - (x % 8))
-                    & if (x % w) >= (w - 8) { 0xff00 } else { 0xffff };
-                let addr = |x, y| -> u16 { (y + line) * w_bytes + (x / 8) + self.screen };
-                let screen: u16 = bus.read(addr(x, y));
-                bus.write(addr(x, y), screen ^ sprite);
-                if screen & sprite != 0 {
+                let row = (x, y + line as u16);
+                if self.draw_row(sprite as u32, 8, row, (w, h), plane, bus) {
                     self.v[0xf] = 1;
                 }
             }
         }
     }
+
+    /// XORs one `width`-pixel row of a sprite onto a bitplane at (x, y),
+    /// returning whether any pixels collided
+    ///
+    /// Rows which cross the edge of the `(w, h)` screen are wrapped or clipped,
+    /// according to [Quirks::wrap](super::Quirks::wrap)
+    #[inline(always)]
+    fn draw_row(
+        &self,
+        sprite: u32,
+        width: u16,
+        (x, y): (u16, u16),
+        (w, h): (u16, u16),
+        plane: usize,
+        bus: &mut Bus,
+    ) -> bool {
+        let wrap = self.flags.quirks.wrap;
+        let y = match y {
+            y if y < h => y,
+            y if wrap => y % h,
+            _ => return false,
+        };
+        let (w_bytes, line) = (w / 8, plane + (y * w / 8) as usize);
+        // Left-align the sprite, shifted into place within its first byte
+        let sprite = (sprite as u64) << (64 - width - x % 8);
+        let mut collided = false;
+        for byte in 0..=width / 8 {
+            let sprite = (sprite >> (56 - 8 * byte)) as u8;
+            let col = match x / 8 + byte {
+                col if col < w_bytes => col,
+                col if wrap => col % w_bytes,
+                _ => break,
+            };
+            let addr = line + col as usize;
+            let screen: u8 = bus.read(addr);
+            bus.write(addr, screen ^ sprite);
+            collided |= screen & sprite != 0;
+        }
+        collided
+    }
 }
 
 // |`Exbb`| Skips instruction on value of keypress
@@ -431,9 +557,16 @@ impl CPU {
     /// ```py
     /// I += vX;
     /// ```
+    ///
+    /// # Quirk
+    /// The Amiga interpreter sets vF when I overflows past 0xFFF, and Spacefight 2091! relies on it
     #[inline(always)]
     pub(super) fn add_i(&mut self, x: Reg) {
-        self.i += self.v[x] as u16;
+        let sum = self.i as u32 + self.v[x] as u32;
+        self.i = sum as u16;
+        if self.flags.quirks.i_overflow {
+            self.v[0xf] = (sum > 0xfff) as u8;
+        }
     }
     /// |`Fx29`| Load sprite for character x into I
     /// ```py
@@ -441,7 +574,7 @@ impl CPU {
     /// ```
     #[inline(always)]
     pub(super) fn load_sprite(&mut self, x: Reg) {
-        self.i = self.font + (5 * (self.v[x] as Adr % 0x10));
+        self.i = self.font + (SMALL_GLYPH * (self.v[x] as Adr % 0x10));
     }
     /// |`Fx33`| BCD convert X into I`[0..3]`
     #[inline(always)]
@@ -458,18 +591,11 @@ impl CPU {
     /// with the side effect of leaving I as I+X+1 after the transfer is done.
     #[inline(always)]
     pub(super) fn store_dma(&mut self, x: Reg, bus: &mut Bus) {
-        let i = self.i as usize;
-        for (reg, value) in bus
-            .get_mut(i..=i + x)
-            .unwrap_or_default()
-            .iter_mut()
-            .enumerate()
-        {
-            *value = self.v[reg]
-        }
-        if !self.flags.quirks.dma_inc {
-            self.i += x as Adr + 1;
+        self.sanitize_i(x + 1, bus);
+        for reg in 0..=x {
+            bus.write(self.i.wrapping_add(reg as Adr), self.v[reg]);
         }
+        self.dma_increment(x);
     }
     /// |`Fx65`| DMA Load from I to registers 0..=X
     ///
@@ -478,12 +604,68 @@ impl CPU {
     /// with the side effect of leaving I as I+X+1 after the transfer is done.
     #[inline(always)]
     pub(super) fn load_dma(&mut self, x: Reg, bus: &mut Bus) {
-        let i = self.i as usize;
-        for (reg, value) in bus.get(i..=i + x).unwrap_or_default().iter().enumerate() {
-            self.v[reg] = *value;
+        self.sanitize_i(x + 1, bus);
+        for (reg, value) in self
+            .get_at_i(0, x + 1, bus)
+            .unwrap_or_default()
+            .into_iter()
+            .enumerate()
+        {
+            self.v[reg] = value;
+        }
+        self.dma_increment(x);
+    }
+
+    /// Advances I past the registers a DMA instruction transferred
+    ///
+    /// # Quirk
+    /// Chip-48 leaves I as I+X, one short of the original interpreter.
+    #[inline(always)]
+    fn dma_increment(&mut self, x: Reg) {
+        match (self.flags.quirks.dma_inc, self.flags.quirks.dma_inc_x) {
+            (true, _) => {}
+            (false, true) => self.i = self.i.wrapping_add(x as Adr),
+            (false, false) => self.i = self.i.wrapping_add(x as Adr + 1),
+        }
+    }
+
+    /// Reports `len` bytes at I running past the end of memory, if the bus is sanitized
+    ///
+    /// The end of memory is the end of the [Region::Program], if there is one.
+    #[inline(always)]
+    fn sanitize_i(&self, len: usize, bus: &mut Bus) {
+        let end = bus
+            .get_region_range(Region::Program)
+            .map_or(bus.len(), |r| r.end);
+        if self.i as usize + len <= end {
+            return;
         }
-        if !self.flags.quirks.dma_inc {
-            self.i += x as Adr + 1;
+        if let Some(sanitizer) = bus.sanitizer_mut() {
+            sanitizer.report(Finding::IndexOutOfBounds {
+                i: self.i,
+                len,
+                end,
+            });
+        }
+    }
+
+    /// Gets `len` bytes of memory starting at I + `offset`,
+    /// wrapping around the end of the 16-bit address space
+    #[inline(always)]
+    fn get_at_i(&self, offset: usize, len: usize, bus: &Bus) -> Option<Vec<u8>> {
+        const SPACE: usize = 0x10000;
+        let start = (self.i as usize + offset) % SPACE;
+        for range in [
+            start..(start + len).min(SPACE),
+            0..(start + len).saturating_sub(SPACE),
+        ] {
+            if !bus.access(Access::Read, range) {
+                return None;
+            }
+        }
+        match start + len {
+            end if end <= SPACE => bus.get(start..end).map(<[u8]>::to_vec),
+            end => Some([bus.get(start..SPACE)?, bus.get(0..end - SPACE)?].concat()),
         }
     }
 }
@@ -494,44 +676,56 @@ impl CPU {
     /// |`00cN`| Scroll the screen down N lines
     #[inline(always)]
     pub(super) fn scroll_down(&mut self, n: Nib, bus: &mut Bus) {
-        match self.flags.draw_mode {
-            true => {
-                // Get a line from the bus
-                for i in (0..16 * (64 - n as usize)).step_by(16).rev() {
-                    let i = i + self.screen as usize;
-                    let line: u128 = bus.read(i);
-                    bus.write(i - (n as usize * 16), 0u128);
-                    bus.write(i, line);
-                }
-            }
-            false => {
-                // Get a line from the bus
-                for i in (0..8 * (32 - n as usize)).step_by(8).rev() {
-                    let i = i + self.screen as usize;
-                    let line: u64 = bus.read(i);
-                    bus.write(i, 0u64);
-                    bus.write(i + (n as usize * 8), line);
-                }
+        let (line_len, lines) = self.plane_shape();
+        let n = self.scroll_distance(n as usize).min(lines);
+        for plane in self.selected_planes(bus) {
+            if let Some(plane) = bus.get_mut(plane..plane + line_len * lines) {
+                plane.copy_within(..(lines - n) * line_len, n * line_len);
+                plane[..n * line_len].fill(0);
             }
         }
     }
 
     /// |`00fb`| Scroll the screen right
     #[inline(always)]
-    pub(super) fn scroll_right(&mut self, bus: &mut (impl Read<u128> + Write<u128>)) {
-        // Get a line from the bus
-        for i in (0..16 * 64).step_by(16) {
-            //let line: u128 = bus.read(self.screen + i) >> 4;
-            bus.write(self.screen + i, bus.read(self.screen + i) >> 4);
-        }
+    pub(super) fn scroll_right(&mut self, bus: &mut Bus) {
+        let n = self.scroll_distance(4);
+        self.scroll_lines(bus, |line, _| line >> n);
     }
     /// |`00fc`| Scroll the screen right
     #[inline(always)]
-    pub(super) fn scroll_left(&mut self, bus: &mut (impl Read<u128> + Write<u128>)) {
-        // Get a line from the bus
-        for i in (0..16 * 64).step_by(16) {
-            let line: u128 = (bus.read(self.screen + i) & !(0xf << 124)) << 4;
-            bus.write(self.screen + i, line);
+    pub(super) fn scroll_left(&mut self, bus: &mut Bus) {
+        let n = self.scroll_distance(4);
+        self.scroll_lines(bus, |line, mask| (line << n) & mask);
+    }
+
+    /// Gets the distance to scroll, in pixels of the current resolution
+    ///
+    /// # Quirk
+    /// Super-Chip 1.1 always scrolls by hires pixels, so lores scrolls move half as far
+    #[inline(always)]
+    fn scroll_distance(&self, n: usize) -> usize {
+        match self.flags.quirks.half_scroll && !self.flags.draw_mode {
+            true => n / 2,
+            false => n,
+        }
+    }
+
+    /// Applies `f` to every line of every selected plane
+    ///
+    /// Lines are passed to `f` as big-endian integers, along with a mask of the line's width
+    #[inline(always)]
+    fn scroll_lines(&mut self, bus: &mut Bus, f: impl Fn(u128, u128) -> u128) {
+        let (line_len, lines) = self.plane_shape();
+        let mask = u128::MAX >> (128 - 8 * line_len);
+        for plane in self.selected_planes(bus) {
+            let Some(plane) = bus.get_mut(plane..plane + line_len * lines) else {
+                continue;
+            };
+            for line in plane.chunks_exact_mut(line_len) {
+                let value = f(line.iter().fold(0, |acc, &b| acc << 8 | b as u128), mask);
+                line.copy_from_slice(&value.to_be_bytes()[16 - line_len..]);
+            }
         }
     }
 
@@ -548,78 +742,189 @@ impl CPU {
         };
         let (x, y) = (self.v[x] as u16 % w, self.v[y] as u16 % h);
         match n {
-            0 => self.draw_schip_sprite(x, y, w, bus),
+            0 => self.draw_schip_sprite(x, y, w, h, bus),
             _ => self.draw_sprite(x, y, n, w, h, bus),
         }
     }
     /// Draws a 16x16 Super Chip sprite
+    ///
+    /// # Quirk
+    /// Super-Chip 1.1 sets vF to the number of rows that collide, rather than 1
     #[inline(always)]
-    pub(super) fn draw_schip_sprite(&mut self, x: u16, y: u16, w: u16, bus: &mut Bus) {
+    pub(super) fn draw_schip_sprite(&mut self, x: u16, y: u16, w: u16, h: u16, bus: &mut Bus) {
         self.v[0xf] = 0;
-        let w_bytes = w / 8;
-        if let Some(sprite) = bus.get(self.i as usize..(self.i + 32) as usize) {
-            let sprite = sprite.to_owned();
+        let planes = self.selected_planes(bus);
+        self.sanitize_i(32 * planes.len(), bus);
+        // Each selected plane consumes the next 32 bytes of sprite data
+        for (index, plane) in planes.into_iter().enumerate() {
+            let Some(sprite) = self.get_at_i(index * 32, 32, bus) else {
+                continue;
+            };
             for (line, sprite) in sprite.chunks(2).enumerate() {
                 let sprite = u16::from_be_bytes(
                     sprite
                         .try_into()
                         .expect("Chunks should only return 2 bytes"),
                 );
-                let addr = (y + line as u16) * w_bytes + x / 8 + self.screen;
-                let sprite = (sprite as u32) << (16 - (x % 8));
-                let screen: u32 = bus.read(addr);
-                bus.write(addr, screen ^ sprite);
-                if screen & sprite != 0 {
-                    self.v[0xf] += 1;
+                let row = (x, y + line as u16);
+                if self.draw_row(sprite as u32, 16, row, (w, h), plane, bus) {
+                    self.v[0xf] = match self.flags.quirks.count_collisions {
+                        true => self.v[0xf] + 1,
+                        false => 1,
+                    };
                 }
             }
         }
     }
 
-    /// |`Fx30`| (Super-Chip) 16x16 equivalent of Fx29
+    /// |`Fx30`| (Super-Chip) 8x10 equivalent of Fx29
     ///
-    /// TODO: Actually make and import the 16x font
+    /// The big font is stored right after the small font. See [BigFont](super::font::BigFont)
     #[inline(always)]
     pub(super) fn load_big_sprite(&mut self, x: Reg) {
-        self.i = self.font + (5 * 8) + (16 * (self.v[x] as Adr % 0x10));
+        self.i = self.font + BIG_FONT_OFFSET + (BIG_GLYPH * (self.v[x] as Adr % 0x10));
     }
 
-    /// |`Fx75`| (Super-Chip) Save to "flag registers"
-    /// I just chuck it in 0x0..0xf. Screw it.
+    /// |`Fx75`| (Super-Chip) Save v0..=vX to the RPL flag registers
     #[inline(always)]
-    pub(super) fn store_flags(&mut self, x: Reg, bus: &mut Bus) {
-        // TODO: Save these, maybe
-        for (reg, value) in bus
-            .get_mut(0..=x)
-            .unwrap_or_default()
-            .iter_mut()
-            .enumerate()
-        {
-            *value = self.v[reg]
-        }
+    pub(super) fn store_flags(&mut self, x: Reg) {
+        self.rpl[..=x].copy_from_slice(&self.v[..=x]);
     }
 
-    /// |`Fx85`| (Super-Chip) Load from "flag registers"
-    /// I just chuck it in 0x0..0xf. Screw it.
+    /// |`Fx85`| (Super-Chip) Load v0..=vX from the RPL flag registers
     #[inline(always)]
-    pub(super) fn load_flags(&mut self, x: Reg, bus: &mut Bus) {
-        for (reg, value) in bus.get(0..=x).unwrap_or_default().iter().enumerate() {
-            self.v[reg] = *value;
-        }
+    pub(super) fn load_flags(&mut self, x: Reg) {
+        self.v[..=x].copy_from_slice(&self.rpl[..=x]);
     }
 
     /// Initialize lores mode
     pub(super) fn init_lores(&mut self, bus: &mut Bus) {
-        self.flags.draw_mode = false;
-        let scraddr = self.screen as usize;
-        bus.set_region(Region::Screen, scraddr..scraddr + 256);
-        self.clear_screen(bus);
+        self.set_resolution(false, bus);
     }
     /// Initialize hires mode
     pub(super) fn init_hires(&mut self, bus: &mut Bus) {
-        self.flags.draw_mode = true;
-        let scraddr = self.screen as usize;
-        bus.set_region(Region::Screen, scraddr..scraddr + 1024);
-        self.clear_screen(bus);
+        self.set_resolution(true, bus);
+    }
+
+    /// Resizes the [Region::Screen] for the new resolution, and clears it
+    ///
+    /// # Quirk
+    /// Super-Chip 1.1 doesn't clear the screen, and leaves the display memory as it was
+    fn set_resolution(&mut self, hires: bool, bus: &mut Bus) {
+        let planes = self.plane_count(bus);
+        self.flags.draw_mode = hires;
+        let scraddr = self.screen;
+        bus.set_region(Region::Screen, scraddr..scraddr + self.plane_len() * planes);
+        if !self.flags.quirks.keep_screen {
+            bus.clear_region(Region::Screen);
+        }
+    }
+}
+
+//////////////// XO-CHIP ////////////////
+
+impl CPU {
+    /// |`00dN`| (XO-Chip) Scroll the screen up N lines
+    #[inline(always)]
+    pub(super) fn scroll_up(&mut self, n: Nib, bus: &mut Bus) {
+        let (line_len, lines) = self.plane_shape();
+        let n = (n as usize).min(lines);
+        for plane in self.selected_planes(bus) {
+            if let Some(plane) = bus.get_mut(plane..plane + line_len * lines) {
+                plane.copy_within(n * line_len.., 0);
+                plane[(lines - n) * line_len..].fill(0);
+            }
+        }
+    }
+
+    /// Gets the length of a line, in bytes, and the number of lines in one bitplane
+    #[inline(always)]
+    fn plane_shape(&self) -> (usize, usize) {
+        match self.flags.draw_mode {
+            true => (16, 64),
+            false => (8, 32),
+        }
+    }
+
+    /// Gets the size of one bitplane, in bytes, at the current resolution
+    #[inline(always)]
+    fn plane_len(&self) -> usize {
+        let (line_len, lines) = self.plane_shape();
+        line_len * lines
+    }
+
+    /// Gets the number of bitplanes in the [Region::Screen]
+    #[inline(always)]
+    fn plane_count(&self, bus: &Bus) -> usize {
+        bus.get_region(Region::Screen)
+            .map_or(1, |screen| screen.len() / self.plane_len())
+            .max(1)
+    }
+
+    /// Gets the base address of each bitplane selected with `fN01`
+    ///
+    /// Planes that don't exist in the [Region::Screen] are ignored.
+    #[inline(always)]
+    fn selected_planes(&self, bus: &Bus) -> Vec<usize> {
+        (0..self.plane_count(bus))
+            .filter(|plane| self.planes & (1 << plane) != 0)
+            .map(|plane| self.screen + plane * self.plane_len())
+            .collect()
+    }
+
+    /// Gets the registers vX..=vY, in the order they're transferred.
+    ///
+    /// If X > Y, the registers are transferred in reverse order.
+    #[inline(always)]
+    fn register_range(x: Reg, y: Reg) -> Vec<Reg> {
+        if x <= y {
+            (x..=y).collect()
+        } else {
+            (y..=x).rev().collect()
+        }
+    }
+
+    /// |`5xy2`| (XO-Chip) Save registers vX..=vY to memory at I, without touching I
+    #[inline(always)]
+    pub(super) fn store_range(&mut self, x: Reg, y: Reg, bus: &mut Bus) {
+        for (offset, reg) in Self::register_range(x, y).into_iter().enumerate() {
+            bus.write(self.i.wrapping_add(offset as Adr), self.v[reg]);
+        }
+    }
+
+    /// |`5xy3`| (XO-Chip) Load registers vX..=vY from memory at I, without touching I
+    #[inline(always)]
+    pub(super) fn load_range(&mut self, x: Reg, y: Reg, bus: &mut Bus) {
+        for (offset, reg) in Self::register_range(x, y).into_iter().enumerate() {
+            self.v[reg] = bus.read(self.i.wrapping_add(offset as Adr));
+        }
+    }
+
+    /// |`f000 aaaa`| (XO-Chip) Load long address #aaaa into register I
+    #[inline(always)]
+    pub(super) fn load_i_long(&mut self, a: Adr) {
+        self.i = a;
+    }
+
+    /// |`fN01`| (XO-Chip) Select the bitplanes N to draw to
+    #[inline(always)]
+    pub(super) fn select_planes(&mut self, n: Nib) {
+        self.planes = n;
+    }
+
+    /// |`f002`| (XO-Chip) Load the 16-byte audio pattern buffer from memory at I
+    #[inline(always)]
+    pub(super) fn load_pattern(&mut self, bus: &mut Bus) {
+        let mut pattern = [0; 16];
+        for (offset, byte) in pattern.iter_mut().enumerate() {
+            *byte = bus.read(self.i.wrapping_add(offset as Adr));
+        }
+        self.pattern = Some(pattern);
+    }
+
+    /// |`Fx3a`| (XO-Chip) Load vX into the audio pitch register
+    #[inline(always)]
+    pub(super) fn load_pitch(&mut self, x: Reg) {
+        self.pitch = self.v[x];
     }
 }
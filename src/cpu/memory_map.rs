@@ -0,0 +1,84 @@
+//! Describes where each named [Region] of memory lives on the [Bus]
+//!
+//! Since [MemoryMap] implements [From<Mode>],
+//! this can be used to select the appropriate memory layout
+
+use super::Mode;
+use crate::bus::{Bus, Region};
+use std::ops::Range;
+
+/// Describes where each named [Region] of memory lives on the [Bus]
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct MemoryMap {
+    /// Location of the character ROM
+    pub charset: Range<usize>,
+    /// Location of program memory. Programs are loaded at the start of this range.
+    pub program: Range<usize>,
+    /// Location of the screen buffer
+    pub screen: Range<usize>,
+    /// Location of the stack. The stack grows down from the end of this range.
+    pub stack: Range<usize>,
+}
+
+impl MemoryMap {
+    /// The classic 4 KiB layout, with the screen just past the end of program memory
+    pub fn classic() -> Self {
+        MemoryMap {
+            charset: 0x0050..0x0140,
+            program: 0x0200..0x1000,
+            screen: 0x1000..0x1100,
+            stack: 0x0ea0..0x0f00,
+        }
+    }
+
+    /// The XO-Chip 64 KiB layout, with the stack and two-plane screen
+    /// outside of program-visible memory
+    pub fn xochip() -> Self {
+        MemoryMap {
+            charset: 0x0050..0x0140,
+            program: 0x0200..0x10000,
+            screen: 0x10100..0x10300,
+            stack: 0x10000..0x10060,
+        }
+    }
+
+    /// Gets the initial top of the stack
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// assert_eq!(0xefe, MemoryMap::classic().stack_top());
+    /// ```
+    pub fn stack_top(&self) -> usize {
+        self.stack.end - 2
+    }
+
+    /// Creates a [Bus] with every [Region] in this map
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let bus = MemoryMap::xochip().bus();
+    /// assert_eq!(0xfe00, bus.get_region(Program).unwrap().len());
+    /// ```
+    pub fn bus(&self) -> Bus {
+        Bus::new()
+            .add_region(Region::Charset, self.charset.clone())
+            .add_region(Region::Program, self.program.clone())
+            .add_region(Region::Screen, self.screen.clone())
+            .add_region(Region::Stack, self.stack.clone())
+    }
+}
+
+impl From<Mode> for MemoryMap {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Chip8 | Mode::SChip => Self::classic(),
+            Mode::XOChip => Self::xochip(),
+        }
+    }
+}
+
+impl Default for MemoryMap {
+    fn default() -> Self {
+        Self::classic()
+    }
+}
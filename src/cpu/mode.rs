@@ -8,6 +8,7 @@ use std::str::FromStr;
 
 /// Selects the memory behavior of the interpreter
 #[derive(Clone, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum Mode {
     /// VIP emulation mode
     #[default]
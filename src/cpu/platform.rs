@@ -0,0 +1,165 @@
+//! Selects a named [Platform], which sets the [Mode], [Quirks] and [MemoryMap] of the CPU
+//!
+//! Each platform describes an interpreter a ROM might have been written for.
+//! The presets follow Timendus' quirks test, and Octo's own compatibility profiles.
+//! Individual quirks can be adjusted afterwards with [Quirks::apply].
+
+use super::{memory_map::MemoryMap, Mode, Quirks};
+use crate::error::Error;
+use std::{fmt::Display, str::FromStr};
+
+/// A named interpreter profile, selecting both a [Mode] and a set of [Quirks]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Platform {
+    /// The original Chip-8 interpreter, on the COSMAC VIP
+    #[default]
+    Vip,
+    /// Chip-48, on the HP-48 calculators
+    Chip48,
+    /// Super-Chip 1.1, on the HP-48 calculators
+    SChipLegacy,
+    /// Super-Chip as implemented by modern interpreters, like Octo and SCHIP-C
+    SChipModern,
+    /// XO-Chip
+    XOChip,
+    /// Octo's defaults, which run XO-Chip instructions in 4 KiB of memory
+    Octo,
+}
+
+impl Platform {
+    /// Every [Platform], in the order they're listed in help text
+    pub const ALL: [Platform; 6] = [
+        Platform::Vip,
+        Platform::Chip48,
+        Platform::SChipLegacy,
+        Platform::SChipModern,
+        Platform::XOChip,
+        Platform::Octo,
+    ];
+
+    /// Gets the [Mode] (and so the memory map) used by this platform
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, cpu::platform::Platform};
+    /// assert_eq!(Mode::SChip, Platform::SChipModern.mode());
+    /// ```
+    pub fn mode(&self) -> Mode {
+        match self {
+            Platform::Vip | Platform::Chip48 => Mode::Chip8,
+            Platform::SChipLegacy | Platform::SChipModern => Mode::SChip,
+            Platform::XOChip | Platform::Octo => Mode::XOChip,
+        }
+    }
+
+    /// Gets the [Quirks] of this platform
+    ///
+    /// Octo's quirks are the same as XO-Chip's; only its [MemoryMap] differs.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, cpu::platform::Platform};
+    /// assert_eq!(Quirks::default(), Platform::Vip.quirks());
+    /// assert!(Platform::Chip48.quirks().dma_inc_x);
+    /// assert!(Platform::SChipLegacy.quirks().half_scroll);
+    /// assert!(!Platform::SChipModern.quirks().half_scroll);
+    /// ```
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::Vip => Mode::Chip8.into(),
+            Platform::Chip48 => Quirks {
+                bin_ops: true,
+                shift: true,
+                draw_wait: true,
+                dma_inc_x: true,
+                stupid_jumps: true,
+                ..Quirks::from(false)
+            },
+            Platform::SChipLegacy => Mode::SChip.into(),
+            Platform::SChipModern => Quirks {
+                bin_ops: true,
+                shift: true,
+                draw_wait: true,
+                dma_inc: true,
+                stupid_jumps: true,
+                ..Quirks::from(false)
+            },
+            Platform::XOChip | Platform::Octo => Mode::XOChip.into(),
+        }
+    }
+
+    /// Gets the [MemoryMap] of this platform
+    ///
+    /// Octo keeps the screen and stack out of the way, like XO-Chip,
+    /// but only gives programs the 3584 bytes up to 0x1000.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// assert_eq!(MemoryMap::xochip(), Platform::XOChip.memory_map());
+    /// assert_eq!(0x200..0x1000, Platform::Octo.memory_map().program);
+    /// ```
+    pub fn memory_map(&self) -> MemoryMap {
+        match self {
+            Platform::Octo => MemoryMap {
+                program: 0x0200..0x1000,
+                ..MemoryMap::xochip()
+            },
+            _ => self.mode().into(),
+        }
+    }
+
+    /// Gets the maximum number of nested subroutine calls on this platform,
+    /// or [None] if it's only limited by the size of the stack region
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// assert_eq!(Some(12), Platform::Vip.stack_depth());
+    /// assert_eq!(Some(16), Platform::SChipLegacy.stack_depth());
+    /// ```
+    pub fn stack_depth(&self) -> Option<usize> {
+        match self {
+            Platform::Vip => Some(12),
+            Platform::Chip48 | Platform::SChipLegacy | Platform::SChipModern => Some(16),
+            Platform::XOChip | Platform::Octo => None,
+        }
+    }
+}
+
+impl From<Mode> for Platform {
+    fn from(value: Mode) -> Self {
+        match value {
+            Mode::Chip8 => Platform::Vip,
+            Mode::SChip => Platform::SChipLegacy,
+            Mode::XOChip => Platform::XOChip,
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "vip" | "chip8" | "chip-8" => Ok(Platform::Vip),
+            "chip48" | "chip-48" => Ok(Platform::Chip48),
+            "schip-legacy" | "schip" | "superchip" => Ok(Platform::SChipLegacy),
+            "schip-modern" => Ok(Platform::SChipModern),
+            "xo-chip" | "xochip" => Ok(Platform::XOChip),
+            "octo" => Ok(Platform::Octo),
+            _ => Err(Error::InvalidPlatform {
+                platform: s.to_string(),
+            }),
+        }
+    }
+}
+
+impl Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Platform::Vip => "vip",
+            Platform::Chip48 => "chip-48",
+            Platform::SChipLegacy => "schip-legacy",
+            Platform::SChipModern => "schip-modern",
+            Platform::XOChip => "xo-chip",
+            Platform::Octo => "octo",
+        })
+    }
+}
@@ -1,7 +1,14 @@
 //! Controls the [Quirks] behavior of the CPU on a granular level.
+//!
+//! Quirks can be written as a specification, like `shift=on,clip=off`.
+//! Each name in a specification describes the behavior of the original interpreter,
+//! or of the extension which introduced it, so `clip=off` turns on [Quirks::wrap].
 use super::Mode;
+use crate::error::{Error, Result};
+use std::{fmt::Display, str::FromStr};
 /// Controls the authenticity behavior of the CPU on a granular level.
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Quirks {
     /// Binary ops in `8xy`(`1`, `2`, `3`) shouldn't set vF to 0
     pub bin_ops: bool,
@@ -11,8 +18,145 @@ pub struct Quirks {
     pub draw_wait: bool,
     /// DMA instructions `Fx55`/`Fx65` shouldn't change I to I + x + 1
     pub dma_inc: bool,
+    /// DMA instructions `Fx55`/`Fx65` should change I to I + x, rather than I + x + 1
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub dma_inc_x: bool,
     /// Indexed jump instructions should go to `adr` + v`a` where `a` is high nibble of `adr`.
     pub stupid_jumps: bool,
+    /// Sprites should wrap around the edges of the screen, instead of being clipped
+    pub wrap: bool,
+    /// 16x16 sprites drawn with `Dxy0` should set vF to the number of rows that collide
+    pub count_collisions: bool,
+    /// Scroll instructions should move half as far in lores mode (in hires pixels)
+    pub half_scroll: bool,
+    /// Resolution changes with `00FE`/`00FF` shouldn't clear the screen
+    pub keep_screen: bool,
+    /// `Fx1E` should set vF to 1 when I overflows past 0xFFF, and 0 otherwise
+    pub i_overflow: bool,
+}
+
+impl Quirks {
+    /// The names of the quirks in a specification, in the order they're displayed
+    pub const NAMES: [&'static str; 11] = [
+        "vfreset",
+        "memory",
+        "memoryx",
+        "dispwait",
+        "clip",
+        "shift",
+        "jump",
+        "collisions",
+        "halfscroll",
+        "keepscreen",
+        "overflow",
+    ];
+
+    /// Gets a quirk by its name in a specification, and whether its meaning is inverted
+    fn field(&mut self, name: &str) -> Option<(&mut bool, bool)> {
+        Some(match name {
+            "vfreset" => (&mut self.bin_ops, true),
+            "memory" => (&mut self.dma_inc, true),
+            "memoryx" => (&mut self.dma_inc_x, false),
+            "dispwait" => (&mut self.draw_wait, true),
+            "clip" => (&mut self.wrap, true),
+            "shift" => (&mut self.shift, false),
+            "jump" => (&mut self.stupid_jumps, false),
+            "collisions" => (&mut self.count_collisions, false),
+            "halfscroll" => (&mut self.half_scroll, false),
+            "keepscreen" => (&mut self.keep_screen, false),
+            "overflow" => (&mut self.i_overflow, false),
+            _ => return None,
+        })
+    }
+
+    /// Gets whether the quirk `name` is on
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let quirks = Quirks::from(Mode::Chip8);
+    /// assert_eq!(Some(true), quirks.get("vfreset"));
+    /// assert_eq!(Some(false), quirks.get("shift"));
+    /// assert_eq!(None, quirks.get("bogus"));
+    /// ```
+    pub fn get(&self, name: &str) -> Option<bool> {
+        let mut quirks = *self;
+        quirks
+            .field(name)
+            .map(|(value, inverted)| *value ^ inverted)
+    }
+
+    /// Turns the quirk `name` on or off
+    pub fn set(&mut self, name: &str, on: bool) -> Result<()> {
+        let (value, inverted) = self.field(name).ok_or_else(|| Error::InvalidQuirk {
+            quirk: name.to_string(),
+        })?;
+        *value = on ^ inverted;
+        Ok(())
+    }
+
+    /// Applies a comma-separated quirk specification, like `shift=on,clip=off`
+    ///
+    /// A quirk without a value is turned on. Quirks which aren't mentioned are left alone.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// # fn main() -> Result<()> {
+    /// let mut quirks = Quirks::from(Mode::Chip8);
+    /// quirks.apply("shift=on, clip=off, overflow")?;
+    /// assert!(quirks.shift && quirks.wrap && quirks.i_overflow);
+    /// assert!(quirks.apply("shift=maybe").is_err());
+    /// #    Ok(())
+    /// # }
+    /// ```
+    pub fn apply(&mut self, spec: &str) -> Result<()> {
+        for quirk in spec
+            .split(',')
+            .map(str::trim)
+            .filter(|quirk| !quirk.is_empty())
+        {
+            let (name, value) = quirk.split_once('=').unwrap_or((quirk, "on"));
+            let on = match value.trim().to_lowercase().as_str() {
+                "on" | "true" | "yes" | "1" => true,
+                "off" | "false" | "no" | "0" => false,
+                _ => {
+                    return Err(Error::InvalidQuirk {
+                        quirk: quirk.to_string(),
+                    })
+                }
+            };
+            self.set(&name.trim().to_lowercase(), on)
+                .map_err(|_| Error::InvalidQuirk {
+                    quirk: quirk.to_string(),
+                })?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = Error;
+
+    /// Parses a quirk specification, starting from the original interpreter's quirks
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut quirks = Self::default();
+        quirks.apply(s)?;
+        Ok(quirks)
+    }
+}
+
+impl Display for Quirks {
+    /// Writes every quirk as a specification, which can be parsed back with [FromStr]
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, name) in Self::NAMES.into_iter().enumerate() {
+            let on = match self.get(name) {
+                Some(true) => "on",
+                _ => "off",
+            };
+            let sep = if index == 0 { "" } else { "," };
+            write!(f, "{sep}{name}={on}")?;
+        }
+        Ok(())
+    }
 }
 
 impl From<bool> for Quirks {
@@ -23,7 +167,13 @@ impl From<bool> for Quirks {
                 shift: true,
                 draw_wait: true,
                 dma_inc: true,
+                dma_inc_x: true,
                 stupid_jumps: true,
+                wrap: true,
+                count_collisions: true,
+                half_scroll: true,
+                keep_screen: true,
+                i_overflow: true,
             }
         } else {
             Quirks {
@@ -31,7 +181,13 @@ impl From<bool> for Quirks {
                 shift: false,
                 draw_wait: false,
                 dma_inc: false,
+                dma_inc_x: false,
                 stupid_jumps: false,
+                wrap: false,
+                count_collisions: false,
+                half_scroll: false,
+                keep_screen: false,
+                i_overflow: false,
             }
         }
     }
@@ -41,13 +197,32 @@ impl From<Mode> for Quirks {
     fn from(value: Mode) -> Self {
         match value {
             Mode::Chip8 => false.into(),
-            Mode::SChip => true.into(),
+            // Super-Chip 1.1
+            Mode::SChip => Self {
+                bin_ops: true,
+                shift: true,
+                draw_wait: true,
+                dma_inc: true,
+                dma_inc_x: false,
+                stupid_jumps: true,
+                wrap: false,
+                count_collisions: true,
+                half_scroll: true,
+                keep_screen: true,
+                i_overflow: false,
+            },
             Mode::XOChip => Self {
                 bin_ops: true,
                 shift: false,
                 draw_wait: true,
                 dma_inc: false,
+                dma_inc_x: false,
                 stupid_jumps: false,
+                wrap: true,
+                count_collisions: false,
+                half_scroll: false,
+                keep_screen: false,
+                i_overflow: false,
             },
         }
     }
@@ -0,0 +1,109 @@
+//! Provides the random numbers used by `Cxbb`
+//!
+//! The [CPU](super::CPU) owns an [Rng], which can be seeded for reproducible runs,
+//! or swapped out entirely with [CPU::set_rng](super::CPU::set_rng).
+
+use std::fmt::Debug;
+
+/// A source of random bytes for the [CPU](super::CPU)
+pub trait Rng: Debug + Send {
+    /// Gets the next random byte
+    fn next_u8(&mut self) -> u8;
+    /// Gets the internal state of the generator, so that it can be saved
+    fn state(&self) -> u64;
+    /// Restores the internal state of the generator from [Rng::state]
+    fn set_state(&mut self, state: u64);
+    /// Clones the generator into a new [Box]
+    fn clone_box(&self) -> Box<dyn Rng>;
+}
+
+/// A 64-bit xorshift* generator. This is the default [Rng].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Xorshift {
+    state: u64,
+}
+
+impl Xorshift {
+    /// Constructs a new [Xorshift] from a seed. Any seed, including zero, is valid.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::cpu::rng::*;
+    /// let (mut a, mut b) = (Xorshift::new(1234), Xorshift::new(1234));
+    /// assert_eq!(a.next_u8(), b.next_u8());
+    /// ```
+    pub fn new(seed: u64) -> Self {
+        let mut rng = Xorshift { state: 0 };
+        rng.set_state(seed);
+        rng
+    }
+}
+
+impl Rng for Xorshift {
+    fn next_u8(&mut self) -> u8 {
+        let mut x = self.state;
+        x ^= x >> 12;
+        x ^= x << 25;
+        x ^= x >> 27;
+        self.state = x;
+        (x.wrapping_mul(0x2545f4914f6cdd1d) >> 56) as u8
+    }
+    fn state(&self) -> u64 {
+        self.state
+    }
+    fn set_state(&mut self, state: u64) {
+        // xorshift gets stuck at zero, so mix the state with splitmix64's constant
+        self.state = match state {
+            0 => 0x9e3779b97f4a7c15,
+            state => state,
+        };
+    }
+    fn clone_box(&self) -> Box<dyn Rng> {
+        Box::new(*self)
+    }
+}
+
+impl Default for Xorshift {
+    /// Constructs a new [Xorshift] with a random seed
+    fn default() -> Self {
+        Self::new(rand::random())
+    }
+}
+
+/// Holds the [CPU](super::CPU)'s [Rng], so that the CPU can still be cloned and compared
+#[derive(Debug)]
+pub(crate) struct BoxedRng(pub(crate) Box<dyn Rng>);
+
+impl Clone for BoxedRng {
+    fn clone(&self) -> Self {
+        BoxedRng(self.0.clone_box())
+    }
+}
+
+impl PartialEq for BoxedRng {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.state() == other.0.state()
+    }
+}
+
+impl Default for BoxedRng {
+    fn default() -> Self {
+        BoxedRng(Box::<Xorshift>::default())
+    }
+}
+
+/// Serializes the [Rng] as its [state](Rng::state)
+#[cfg(feature = "serde")]
+impl serde::Serialize for BoxedRng {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0.state())
+    }
+}
+
+/// Deserializes an [Rng] state into a [Xorshift], since the implementation isn't recorded
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for BoxedRng {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = u64::deserialize(deserializer)?;
+        Ok(BoxedRng(Box::new(Xorshift::new(state))))
+    }
+}
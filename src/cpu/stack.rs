@@ -0,0 +1,37 @@
+//! Selects what happens when a program overflows or underflows the call stack
+//!
+//! The stack is checked against the [Stack](crate::bus::Region::Stack) region on the
+//! [Bus](crate::bus::Bus), and against [Flags::stack_depth](super::flags::Flags::stack_depth).
+//! If the bus has no stack region, calls and returns are never checked.
+
+use crate::error::Error;
+use std::str::FromStr;
+
+/// Selects what happens when `2nnn` overflows, or `00EE` underflows, the stack
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum StackPolicy {
+    /// Stop with [Error::StackOverflow] or [Error::StackUnderflow]
+    #[default]
+    Error,
+    /// Treat the stack as a ring buffer, overwriting the oldest return address
+    Wrap,
+    /// Don't check the stack at all, like the original interpreters,
+    /// so a deep enough recursion overwrites whatever lies below the stack
+    Emulate,
+}
+
+impl FromStr for StackPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "error" => Ok(StackPolicy::Error),
+            "wrap" => Ok(StackPolicy::Wrap),
+            "emulate" | "unchecked" => Ok(StackPolicy::Emulate),
+            _ => Err(Error::InvalidStackPolicy {
+                policy: s.to_string(),
+            }),
+        }
+    }
+}
@@ -0,0 +1,160 @@
+//! Saves and restores the [CPU] in a [save state](crate::state)
+//!
+//! Fields are written in a fixed order. When a field is added, bump
+//! [VERSION](crate::state::VERSION), and give it a default when loading older states.
+
+use super::{breakpoint::Breakpoint, flags::Flags, quirks::Quirks, stack::StackPolicy, Mode, CPU};
+use crate::{
+    error::Result,
+    state::{invalid, Decoder, Encoder},
+};
+
+impl CPU {
+    /// Writes the whole state of the CPU
+    pub(crate) fn save_state(&self, state: &mut Encoder) {
+        save_flags(&self.flags, state);
+        // memory map info
+        state.usize(self.screen);
+        state.u16(self.font);
+        // registers
+        state.u16(self.pc);
+        state.usize(self.sp);
+        state.u16(self.i);
+        state.bytes(&self.v);
+        state.f64(self.delay);
+        state.f64(self.sound);
+        // Super-Chip and XO-Chip state
+        state.bytes(&self.rpl);
+        state.u8(self.planes);
+        state.option(self.pattern.as_ref(), |state, pattern| state.bytes(pattern));
+        state.u8(self.pitch);
+        state.option(self.phase, |state, phase| state.f64(phase));
+        // I/O
+        self.keys.iter().for_each(|&key| state.bool(key));
+        state.u64(self.rng.0.state());
+        // Execution data
+        state.usize(self.cycle);
+        state.usize(self.frame_cycles);
+        state.usize(self.breakpoints.len());
+        self.breakpoints.iter().for_each(|&point| state.u16(point));
+        state.usize(self.conditional.len());
+        self.conditional.iter().for_each(|bp| bp.save_state(state));
+        state.usize(self.next_id);
+    }
+
+    /// Reads a CPU written by [CPU::save_state], keeping this CPU's RNG implementation
+    pub(crate) fn load_state(&self, state: &mut Decoder) -> Result<Self> {
+        let mut cpu = self.clone();
+        cpu.flags = load_flags(state)?;
+        cpu.screen = state.usize()?;
+        cpu.font = state.u16()?;
+        cpu.pc = state.u16()?;
+        cpu.sp = state.usize()?;
+        cpu.i = state.u16()?;
+        cpu.v = state.array()?;
+        cpu.delay = state.f64()?;
+        cpu.sound = state.f64()?;
+        cpu.rpl = state.array()?;
+        cpu.planes = state.u8()?;
+        cpu.pattern = state.option(|state| state.array())?;
+        cpu.pitch = state.u8()?;
+        cpu.phase = state.option(|state| state.f64())?;
+        for key in cpu.keys.iter_mut() {
+            *key = state.bool()?;
+        }
+        cpu.rng.0.set_state(state.u64()?);
+        cpu.cycle = state.usize()?;
+        cpu.frame_cycles = state.usize()?;
+        cpu.breakpoints = (0..state.usize()?)
+            .map(|_| state.u16())
+            .collect::<Result<_>>()?;
+        cpu.conditional = (0..state.usize()?)
+            .map(|_| Breakpoint::load_state(state))
+            .collect::<Result<_>>()?;
+        cpu.next_id = state.usize()?;
+        cpu.idle = None;
+        Ok(cpu)
+    }
+}
+
+fn save_flags(flags: &Flags, state: &mut Encoder) {
+    state.bool(flags.debug);
+    state.bool(flags.pause);
+    state.bool(flags.keypause);
+    state.bool(flags.draw_wait);
+    state.bool(flags.draw_mode);
+    state.option(flags.lastkey, Encoder::usize);
+    state.u8(match flags.mode {
+        Mode::Chip8 => 0,
+        Mode::SChip => 1,
+        Mode::XOChip => 2,
+    });
+    save_quirks(&flags.quirks, state);
+    state.u8(match flags.stack_policy {
+        StackPolicy::Error => 0,
+        StackPolicy::Wrap => 1,
+        StackPolicy::Emulate => 2,
+    });
+    state.option(flags.stack_depth, Encoder::usize);
+    state.usize(flags.speed);
+    state.bool(flags.vip_timing);
+    state.bool(flags.idle_skip);
+}
+
+fn load_flags(state: &mut Decoder) -> Result<Flags> {
+    Ok(Flags {
+        debug: state.bool()?,
+        pause: state.bool()?,
+        keypause: state.bool()?,
+        draw_wait: state.bool()?,
+        draw_mode: state.bool()?,
+        lastkey: state.option(Decoder::usize)?,
+        mode: match state.u8()? {
+            0 => Mode::Chip8,
+            1 => Mode::SChip,
+            2 => Mode::XOChip,
+            _ => return Err(invalid("unknown mode")),
+        },
+        quirks: load_quirks(state)?,
+        stack_policy: match state.u8()? {
+            0 => StackPolicy::Error,
+            1 => StackPolicy::Wrap,
+            2 => StackPolicy::Emulate,
+            _ => return Err(invalid("unknown stack policy")),
+        },
+        stack_depth: state.option(Decoder::usize)?,
+        speed: state.usize()?,
+        vip_timing: state.bool()?,
+        idle_skip: state.bool()?,
+    })
+}
+
+fn save_quirks(quirks: &Quirks, state: &mut Encoder) {
+    state.bool(quirks.bin_ops);
+    state.bool(quirks.shift);
+    state.bool(quirks.draw_wait);
+    state.bool(quirks.dma_inc);
+    state.bool(quirks.stupid_jumps);
+    state.bool(quirks.wrap);
+    state.bool(quirks.count_collisions);
+    state.bool(quirks.half_scroll);
+    state.bool(quirks.keep_screen);
+    state.bool(quirks.i_overflow);
+    state.bool(quirks.dma_inc_x);
+}
+
+fn load_quirks(state: &mut Decoder) -> Result<Quirks> {
+    Ok(Quirks {
+        bin_ops: state.bool()?,
+        shift: state.bool()?,
+        draw_wait: state.bool()?,
+        dma_inc: state.bool()?,
+        stupid_jumps: state.bool()?,
+        wrap: state.bool()?,
+        count_collisions: state.bool()?,
+        half_scroll: state.bool()?,
+        keep_screen: state.bool()?,
+        i_overflow: state.bool()?,
+        dma_inc_x: state.bool()?,
+    })
+}
@@ -12,11 +12,12 @@
 //!
 //! Some of these tests run >16M times, which is very silly
 
-use super::*;
-pub(self) use crate::{
+use super::{font::BigFont, memory_map::MemoryMap, *};
+use crate::{
     bus,
     bus::{Bus, Region::*},
 };
+use rand::random;
 
 mod decode;
 
@@ -26,14 +27,14 @@ fn setup_environment() -> (CPU, Bus) {
             flags: Flags {
                 debug: true,
                 pause: false,
-                monotonic: Some(8),
+                speed: 8,
                 ..Default::default()
             },
             ..CPU::default()
         },
         bus! {
             // Load the charset into ROM
-            Charset [0x0050..0x00A0] = include_bytes!("../mem/charset.bin"),
+            Charset [0x0050..0x0140] = include_bytes!("../mem/charset.bin"),
             // Load the ROM file into RAM (dummy binary which contains nothing but `jmp pc+2`)
             Program [0x0200..0x1000] = include_bytes!("tests/roms/jumptest.ch8"),
             // Create a screen
@@ -111,7 +112,8 @@ mod sys {
         // Place the address on the stack
         bus.write(cpu.sp.wrapping_add(2), test_addr);
 
-        cpu.ret(&bus);
+        cpu.ret(&bus)
+            .expect("Returning without a stack region should not fail");
 
         // Verify the current address is the address from the stack
         assert_eq!(test_addr, cpu.pc);
@@ -120,6 +122,104 @@ mod sys {
     }
 }
 
+/// Tests stack overflow and underflow handling
+mod stack {
+    use super::{stack::StackPolicy, *};
+
+    /// Sets up a CPU running `program`, with a stack region and the given [StackPolicy]
+    fn setup_stack(program: &[u8], policy: StackPolicy, depth: Option<usize>) -> (CPU, Bus) {
+        let (mut cpu, _) = setup_environment();
+        cpu.flags.stack_policy = policy;
+        cpu.flags.stack_depth = depth;
+        let bus = bus! {
+            Program [0x0200..0x0ea0] = program,
+            Stack   [0x0ea0..0x0f00],
+            Screen  [0x0f00..0x1000],
+        };
+        (cpu, bus)
+    }
+
+    /// 2aaa: Calling with a full stack is an error, which points at the call
+    #[test]
+    fn overflow() {
+        // call 200
+        let (mut cpu, mut bus) = setup_stack(&[0x22, 0x00], StackPolicy::Error, Some(12));
+        for _ in 0..12 {
+            cpu.tick(&mut bus).expect("The stack should hold 12 calls");
+        }
+        let error = cpu
+            .tick(&mut bus)
+            .expect_err("The 13th call should overflow");
+        assert!(matches!(
+            error,
+            Error::StackOverflow {
+                pc: 0x200,
+                depth: 12
+            }
+        ));
+        assert_eq!(0x200, cpu.pc);
+        assert!(cpu.flags.pause);
+    }
+
+    /// 2aaa: Without a depth limit, the stack is limited by the size of the stack region
+    #[test]
+    fn overflow_region() {
+        let (mut cpu, mut bus) = setup_stack(&[0x22, 0x00], StackPolicy::Error, None);
+        for _ in 0..48 {
+            cpu.tick(&mut bus)
+                .expect("The stack region should hold 48 calls");
+        }
+        let error = cpu
+            .tick(&mut bus)
+            .expect_err("The 49th call should overflow");
+        assert!(matches!(error, Error::StackOverflow { depth: 48, .. }));
+    }
+
+    /// 00ee: Returning with an empty stack is an error
+    #[test]
+    fn underflow() {
+        // ret
+        let (mut cpu, mut bus) = setup_stack(&[0x00, 0xee], StackPolicy::Error, Some(12));
+        let error = cpu.tick(&mut bus).expect_err("Returning should underflow");
+        assert!(matches!(
+            error,
+            Error::StackUnderflow {
+                pc: 0x200,
+                depth: 0
+            }
+        ));
+    }
+
+    /// 2aaa, 00ee: The stack wraps around, like a ring buffer
+    #[test]
+    fn wrap() {
+        // call 200; ret
+        let (mut cpu, mut bus) = setup_stack(&[0x22, 0x00, 0x00, 0xee], StackPolicy::Wrap, Some(2));
+        for _ in 0..5 {
+            cpu.tick(&mut bus)
+                .expect("Wrapping calls should never overflow");
+            assert!((0xefa..=0xefe).contains(&cpu.sp));
+        }
+        cpu.pc = 0x202;
+        for _ in 0..5 {
+            cpu.tick(&mut bus)
+                .expect("Wrapping returns should never underflow");
+            assert!((0xefa..=0xefe).contains(&cpu.sp));
+        }
+    }
+
+    /// 2aaa: Unchecked calls run off the bottom of the stack region
+    #[test]
+    fn emulate() {
+        let (mut cpu, mut bus) = setup_stack(&[0x22, 0x00], StackPolicy::Emulate, Some(12));
+        for _ in 0..64 {
+            cpu.tick(&mut bus)
+                .expect("Unchecked calls should never overflow");
+        }
+        assert!(cpu.sp < 0xea0);
+    }
+}
+
 /// Tests control-flow instructions
 ///
 /// Basically anything that touches the program counter
@@ -147,7 +247,8 @@ mod cf {
         // Save the current address
         let curr_addr = cpu.pc;
         // Call an address
-        cpu.call(test_addr, &mut bus);
+        cpu.call(test_addr, &mut bus)
+            .expect("Calling without a stack region should not fail");
         // Verify the current address is the called address
         assert_eq!(test_addr, cpu.pc);
         // Verify the previous address was stored on the stack (sp+2)
@@ -691,6 +792,28 @@ mod io {
         }
     }
 
+    /// Cxbb: Seeded CPUs generate the same numbers
+    #[test]
+    fn rand_seeded() {
+        let (mut a, _) = setup_environment();
+        let (mut b, _) = setup_environment();
+        a.seed(0xc5c5);
+        b.seed(0xc5c5);
+        for x in 0..0x1000 {
+            a.rand(x % 16, 0xff);
+            b.rand(x % 16, 0xff);
+            assert_eq!(a.v, b.v);
+        }
+        assert_eq!(a.rng().state(), b.rng().state());
+        // Restoring a saved state replays the same numbers
+        let state = a.rng().state();
+        a.rand(0, 0xff);
+        let expected = a.v[0];
+        a.seed(state);
+        a.rand(0, 0xff);
+        assert_eq!(expected, a.v[0]);
+    }
+
     mod display {
         use super::*;
         struct ScreenTest {
@@ -713,7 +836,13 @@ mod io {
                     shift: false,
                     draw_wait: false,
                     dma_inc: false,
+                    dma_inc_x: false,
                     stupid_jumps: false,
+                    wrap: false,
+                    count_collisions: false,
+                    half_scroll: false,
+                    keep_screen: false,
+                    i_overflow: false,
                 },
             },
             // Rule 22 cellular automata
@@ -728,7 +857,13 @@ mod io {
                     shift: false,
                     draw_wait: true,
                     dma_inc: false,
+                    dma_inc_x: false,
                     stupid_jumps: false,
+                    wrap: false,
+                    count_collisions: false,
+                    half_scroll: false,
+                    keep_screen: false,
+                    i_overflow: false,
                 },
             },
             // Rule 60 cellular automata
@@ -741,7 +876,13 @@ mod io {
                     shift: false,
                     draw_wait: true,
                     dma_inc: false,
+                    dma_inc_x: false,
                     stupid_jumps: false,
+                    wrap: false,
+                    count_collisions: false,
+                    half_scroll: false,
+                    keep_screen: false,
+                    i_overflow: false,
                 },
             },
         ];
@@ -757,7 +898,8 @@ mod io {
                 // Load the test program
                 bus = bus.load_region(Program, test.program);
                 // Run the test program for the specified number of steps
-                while cpu.cycle() < test.steps {
+                // A program which halts pauses the CPU, and paused CPUs don't count cycles
+                while cpu.cycle() < test.steps && !cpu.flags.pause {
                     cpu.multistep(&mut bus, test.steps - cpu.cycle())
                         .expect("Draw tests should not contain undefined instructions");
                 }
@@ -964,10 +1106,45 @@ mod io {
                 );
             }
         }
+
+        /// Fx30: Load big sprite for character vX into I
+        #[test]
+        fn load_big_sprite() {
+            for font in [BigFont::SChip, BigFont::Octo] {
+                let (mut cpu, _) = setup_environment();
+                let bus = bus! { Charset [0x0050..0x0140] = font.charset() };
+                for digit in 0..0x10 {
+                    cpu.v[0] = digit;
+                    cpu.load_big_sprite(0);
+                    let addr = cpu.i as usize;
+                    let glyph = 80 + 10 * digit as usize;
+                    // Glyphs missing from the charset are left blank
+                    let expected = font.charset().get(glyph..glyph + 10);
+                    assert_eq!(
+                        expected.unwrap_or(&[0; 10]),
+                        bus.get(addr..addr + 10)
+                            .expect("Region at addr should exist!"),
+                    );
+                }
+            }
+        }
+
+        /// Fx30: The Super-Chip big font's 0 is an 8x10 ring
+        #[test]
+        fn big_zero() {
+            let (mut cpu, bus) = setup_environment();
+            cpu.v[0] = 0;
+            cpu.load_big_sprite(0);
+            let addr = cpu.i as usize;
+            assert_eq!(
+                Some(&[0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c][..]),
+                bus.get(addr..addr + 10)
+            );
+        }
     }
 
     mod bcdtest {
-        pub(self) use super::*;
+        use super::*;
 
         struct BCDTest {
             // value to test
@@ -1058,66 +1235,181 @@ mod io {
             cpu.v.fill(0);
         }
     }
+
+    /// Fx55/Fx65: I advances by X + 1, X (Chip-48), or not at all
+    #[test]
+    fn dma_increment() {
+        let (mut cpu, mut bus) = setup_environment();
+        for (dma_inc, dma_inc_x, expected) in [
+            (false, false, 0x45a),
+            (false, true, 0x459),
+            (true, false, 0x456),
+        ] {
+            cpu.flags.quirks.dma_inc = dma_inc;
+            cpu.flags.quirks.dma_inc_x = dma_inc_x;
+            cpu.i = 0x456;
+            cpu.store_dma(3, &mut bus);
+            assert_eq!(expected, cpu.i);
+            cpu.i = 0x456;
+            cpu.load_dma(3, &mut bus);
+            assert_eq!(expected, cpu.i);
+        }
+    }
 }
 
 mod behavior {
     use super::*;
 
-    mod realtime {
+    mod frame {
         use super::*;
-        use std::time::Duration;
         #[test]
         fn delay() {
             let (mut cpu, mut bus) = setup_environment();
-            cpu.flags.monotonic = None;
             cpu.delay = 10.0;
             for _ in 0..2 {
-                cpu.multistep(&mut bus, 8)
+                cpu.run_frame(&mut bus)
                     .expect("Running valid instructions should always succeed");
-                std::thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
             }
-            // time is within 1 frame deviance over a theoretical 2 frame pause
-            assert!(7.0 <= cpu.delay && cpu.delay <= 9.0);
+            // the delay timer ticks exactly once per frame
+            assert_eq!(8, cpu.delay());
         }
         #[test]
         fn sound() {
             let (mut cpu, mut bus) = setup_environment();
-            cpu.flags.monotonic = None; // disable monotonic timing
             cpu.sound = 10.0;
             for _ in 0..2 {
-                cpu.multistep(&mut bus, 8)
+                cpu.run_frame(&mut bus)
                     .expect("Running valid instructions should always succeed");
-                std::thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
             }
-            // time is within 1 frame deviance over a theoretical 2 frame pause
-            assert!(7.0 <= cpu.sound && cpu.sound <= 9.0);
+            // the sound timer ticks exactly once per frame
+            assert_eq!(8, cpu.sound());
         }
         #[test]
         fn vbi_wait() {
             let (mut cpu, mut bus) = setup_environment();
-            cpu.flags.monotonic = None; // disable monotonic timing
             cpu.flags.draw_wait = true;
-            for _ in 0..2 {
-                cpu.multistep(&mut bus, 8)
-                    .expect("Running valid instructions should always succeed");
-                std::thread::sleep(Duration::from_secs_f64(1.0 / 60.0));
-            }
-            // Display wait is disabled after a 1 frame pause
+            cpu.run_frame(&mut bus)
+                .expect("Running valid instructions should always succeed");
+            // Display wait is disabled at the end of the frame
             assert!(!cpu.flags.draw_wait);
+            // ...and waiting still counts towards the frame's instructions
+            assert_eq!(cpu.flags.speed, cpu.cycle());
+        }
+        #[test]
+        fn paused() {
+            let (mut cpu, mut bus) = setup_environment();
+            cpu.flags.pause = true;
+            cpu.multistep(&mut bus, 10)
+                .expect("Running valid instructions should always succeed");
+            // No time passes while the CPU is paused
+            assert_eq!((0, 0), (cpu.cycle(), cpu.frame_cycles));
+        }
+        #[test]
+        fn multistep_matches_run_frame() {
+            let (mut a, mut bus_a) = setup_environment();
+            let (mut b, mut bus_b) = setup_environment();
+            a.delay = 10.0;
+            b.delay = 10.0;
+            for _ in 0..3 {
+                a.run_frame(&mut bus_a).unwrap();
+            }
+            b.multistep(&mut bus_b, 3 * b.flags.speed).unwrap();
+            assert_eq!(a.cycle(), b.cycle());
+            assert_eq!(a.delay(), b.delay());
+        }
+    }
+    mod idle {
+        use super::*;
+
+        /// Waits for the delay timer, then counts in memory until it's reset
+        const DELAY_LOOP: &[u8] = &[
+            0x60, 0x05, // 200: mov   #05, v0
+            0xf0, 0x15, // 202: mov   v0, DT
+            0xf1, 0x07, // 204: mov   DT, v1
+            0x31, 0x00, // 206: se    #00, v1
+            0x12, 0x04, // 208: jmp   204
+            0xa3, 0x00, // 20a: mov   $300, I
+            0x72, 0x01, // 20c: add   #01, v2
+            0xf2, 0x33, // 20e: bcd   v2, I
+            0x12, 0x00, // 210: jmp   200
+        ];
+
+        fn setup_idle(program: &[u8], idle_skip: bool) -> (CPU, Bus) {
+            let (mut cpu, mut bus) = setup_environment();
+            cpu.flags.debug = false;
+            cpu.flags.speed = 100;
+            cpu.flags.idle_skip = idle_skip;
+            bus = bus.load_region(Program, program);
+            (cpu, bus)
+        }
+
+        /// Skipping idle loops doesn't change anything the program can see
+        #[test]
+        fn same_outcome() {
+            let (mut a, mut bus_a) = setup_idle(DELAY_LOOP, false);
+            let (mut b, mut bus_b) = setup_idle(DELAY_LOOP, true);
+            for _ in 0..20 {
+                a.run_frame(&mut bus_a).unwrap();
+                b.run_frame(&mut bus_b).unwrap();
+                assert_eq!(
+                    (a.pc, a.v, a.i, a.delay(), a.cycle, a.frame_cycles),
+                    (b.pc, b.v, b.i, b.delay(), b.cycle, b.frame_cycles)
+                );
+                assert_eq!(bus_a, bus_b);
+            }
+        }
+
+        /// Busy-wait loops are fast-forwarded to the end of the frame
+        #[test]
+        fn skips_frame() {
+            let (mut cpu, mut bus) = setup_idle(DELAY_LOOP, true);
+            // This would take hours without skipping
+            cpu.flags.speed = 1 << 40;
+            cpu.run_frame(&mut bus).unwrap();
+            assert_eq!(1 << 40, cpu.cycle());
+            assert_eq!(4, cpu.delay());
+        }
+
+        /// Loops which write to memory are never skipped, even if the registers repeat
+        #[test]
+        fn impure_loop() {
+            let program = &[
+                0xa3, 0x00, // 200: mov   $300, I
+                0xf0, 0x65, // 202: dmai  v0
+                0x70, 0x01, // 204: add   #01, v0
+                0xf0, 0x55, // 206: dmao  v0
+                0x60, 0x00, // 208: mov   #00, v0
+                0x12, 0x02, // 20a: jmp   202
+            ];
+            let (mut a, mut bus_a) = setup_idle(program, false);
+            let (mut b, mut bus_b) = setup_idle(program, true);
+            a.flags.quirks.dma_inc = true;
+            b.flags.quirks.dma_inc = true;
+            a.run_frame(&mut bus_a).unwrap();
+            b.run_frame(&mut bus_b).unwrap();
+            assert_eq!(bus_a, bus_b);
+            assert_eq!(20u8, bus_b.read(0x300u16));
         }
     }
+
     mod breakpoint {
 
         use super::*;
+        use crate::cpu::breakpoint::*;
         #[test]
         #[cfg_attr(feature = "unstable", no_coverage)]
         fn hit_break() {
             let (mut cpu, mut bus) = setup_environment();
             cpu.set_break(0x202);
             match cpu.multistep(&mut bus, 10) {
-                Err(crate::error::Error::BreakpointHit { addr, next }) => {
+                Err(crate::error::Error::BreakpointHit {
+                    addr,
+                    next,
+                    trigger,
+                }) => {
                     assert_eq!(0x202, addr); // current address is 202
                     assert_eq!(0x1204, next); // next insn is `jmp 204`
+                    assert_eq!(Trigger::Breakpoint { id: None }, trigger);
                 }
                 other => unreachable!("{:?}", other),
             }
@@ -1130,15 +1422,168 @@ mod behavior {
             let (mut cpu, mut bus) = setup_environment();
             cpu.set_break(0x202);
             match cpu.singlestep(&mut bus) {
-                Err(crate::error::Error::BreakpointHit { addr, next }) => {
+                Err(crate::error::Error::BreakpointHit {
+                    addr,
+                    next,
+                    trigger,
+                }) => {
                     assert_eq!(0x202, addr); // current address is 202
                     assert_eq!(0x1204, next); // next insn is `jmp 204`
+                    assert_eq!(Trigger::Breakpoint { id: None }, trigger);
                 }
                 other => unreachable!("{:?}", other),
             }
             assert!(cpu.flags.pause);
             assert_eq!(0x202, cpu.pc);
         }
+
+        #[test]
+        fn conditional() {
+            let (mut cpu, mut bus) = setup_environment();
+            let condition = "cycle >= 3 && pc < $300".parse().unwrap();
+            let id = cpu.add_breakpoint(Breakpoint::new(0x208).when(condition));
+            cpu.add_breakpoint(Breakpoint::new(0x204).when("cycle > 2".parse().unwrap()));
+            match cpu.multistep(&mut bus, 10) {
+                Err(Error::BreakpointHit { addr, trigger, .. }) => {
+                    assert_eq!(0x208, addr);
+                    assert_eq!(Trigger::Breakpoint { id: Some(id) }, trigger);
+                }
+                other => unreachable!("{:?}", other),
+            }
+            assert_eq!(1, cpu.conditional_breakpoints()[0].hits());
+            assert_eq!(0, cpu.conditional_breakpoints()[1].hits());
+        }
+        #[test]
+        fn ignore_once() {
+            let (mut cpu, mut bus) = setup_environment();
+            let id = cpu.add_breakpoint(Breakpoint::new(0x204).ignoring(1).once());
+            // The first hit is ignored
+            cpu.multistep(&mut bus, 2).unwrap();
+            assert_eq!(1, cpu.conditional_breakpoints()[0].hits());
+            // The second stops the CPU, and removes the breakpoint
+            cpu.soft_reset();
+            assert!(cpu.multistep(&mut bus, 2).is_err());
+            assert!(cpu.conditional_breakpoints().is_empty());
+            assert!(cpu.remove_breakpoint(id).is_none());
+            cpu.soft_reset();
+            cpu.flags.pause = false;
+            cpu.multistep(&mut bus, 2).unwrap();
+        }
+        #[test]
+        fn remove() {
+            let (mut cpu, mut bus) = setup_environment();
+            let id = cpu.add_breakpoint(Breakpoint::new(0x204));
+            assert_eq!(0x204, cpu.remove_breakpoint(id).unwrap().addr());
+            cpu.multistep(&mut bus, 4).unwrap();
+        }
+        #[test]
+        fn condition_operands() {
+            let mut cpu = CPU::default();
+            cpu.v[3] = 0x10;
+            cpu.i = 0x401;
+            cpu.delay = 5.0;
+            for (condition, expected) in [
+                ("v3 == 0x10 && I > 0x400", true),
+                ("V3 == #10 && i > 1025", false),
+                ("vf != 0 || dt == 5", true),
+                ("st > 0 || sp < 0", false),
+                ("pc >= 512", true),
+                ("cycle <= 0", true),
+            ] {
+                let parsed: Condition = condition.parse().unwrap();
+                assert_eq!(expected, parsed.eval(&cpu), "{condition}");
+                // Conditions survive a round-trip through text
+                assert_eq!(parsed, parsed.to_string().parse().unwrap());
+            }
+            for condition in ["", "v3", "vg == 1", "v3 = 1", "v3 == 0xq", "a && b"] {
+                assert!(condition.parse::<Condition>().is_err(), "{condition}");
+            }
+        }
+    }
+
+    mod watchpoint {
+        use super::*;
+        use crate::cpu::breakpoint::*;
+
+        /// Stores v0..=v1 at 3f0, then reads them back
+        fn setup_watch(watchpoint: Watchpoint) -> (CPU, Bus, usize) {
+            let (mut cpu, bus) = setup_environment();
+            let mut bus = bus.load_region(
+                Program,
+                &[
+                    0xa3, 0xf0, // mov $3f0, I
+                    0x60, 0x2a, // mov #2a, v0
+                    0xf1, 0x55, // dma v1, I
+                    0xa3, 0xf0, // mov $3f0, I
+                    0xf1, 0x65, // dma I, v1
+                    0x12, 0x00, // jmp 200
+                ],
+            );
+            let id = cpu.add_watchpoint(&mut bus, watchpoint);
+            (cpu, bus, id)
+        }
+
+        fn trigger(cpu: &mut CPU, bus: &mut Bus, steps: usize) -> (u16, Trigger) {
+            match cpu.multistep(bus, steps) {
+                Err(Error::BreakpointHit { addr, trigger, .. }) => (addr, trigger),
+                other => unreachable!("{:?}", other),
+            }
+        }
+
+        #[test]
+        fn write() {
+            let (mut cpu, mut bus, id) = setup_watch(Watchpoint::new(0x3f1..0x3f2, Watch::Write));
+            let (addr, trigger) = trigger(&mut cpu, &mut bus, 6);
+            assert_eq!(0x206, addr);
+            // v1 is written over the zero already there
+            let expected = Trigger::Watchpoint {
+                id,
+                watch: Watch::Write,
+                addr: 0x3f1,
+                old: 0,
+                new: 0,
+            };
+            assert_eq!(expected, trigger);
+        }
+        #[test]
+        fn change() {
+            let (mut cpu, mut bus, id) =
+                setup_watch(Watchpoint::new(0x3f0..0x400, Watch::Change).once());
+            let (addr, trigger) = trigger(&mut cpu, &mut bus, 6);
+            assert_eq!(0x206, addr);
+            let expected = Trigger::Watchpoint {
+                id,
+                watch: Watch::Change,
+                addr: 0x3f0,
+                old: 0,
+                new: 0x2a,
+            };
+            assert_eq!(expected, trigger);
+            assert!(bus.watchpoints().is_empty());
+            // Writing the same value again isn't a change
+            let id = cpu.add_watchpoint(&mut bus, Watchpoint::new(0x3f0..0x400, Watch::Change));
+            cpu.flags.pause = false;
+            cpu.multistep(&mut bus, 8).unwrap();
+            assert_eq!(0, bus.remove_watchpoint(id).unwrap().hits());
+        }
+        #[test]
+        fn read() {
+            let (mut cpu, mut bus, id) =
+                setup_watch(Watchpoint::new(0x3f0..0x3f2, Watch::Read).ignoring(1));
+            // The first read is ignored, and the second iteration's read stops the CPU
+            let (addr, trigger) = trigger(&mut cpu, &mut bus, 12);
+            assert_eq!(0x20a, addr);
+            let expected = Trigger::Watchpoint {
+                id,
+                watch: Watch::Read,
+                addr: 0x3f0,
+                old: 0x2a,
+                new: 0x2a,
+            };
+            assert_eq!(expected, trigger);
+            assert_eq!(2, bus.watchpoints()[0].hits());
+            assert_eq!(11, cpu.cycle());
+        }
     }
 
     #[test]
@@ -1155,3 +1600,505 @@ mod behavior {
         }
     }
 }
+
+/// COSMAC VIP timing
+mod timing {
+    use super::*;
+    use crate::cpu::timing::{cycles, CYCLES_PER_FRAME, INTERPRETER_CYCLES};
+
+    fn setup_vip(program: &[u8]) -> (CPU, Bus) {
+        let (mut cpu, _) = setup_environment();
+        cpu.flags.vip_timing = true;
+        cpu.flags.debug = false;
+        let bus = bus! {
+            Charset [0x0050..0x0140] = include_bytes!("../mem/charset.bin"),
+            Program [0x0200..0x0F00] = program,
+            Screen  [0x0F00..0x1000],
+        };
+        (cpu, bus)
+    }
+
+    /// Dxyn: Taller and less-aligned sprites take longer to draw
+    #[test]
+    fn draw_cost() {
+        for n in 1..16 {
+            for x in 0..8 {
+                let insn = Insn::draw { x: 0, y: 0, n };
+                assert!(
+                    cycles(&insn, x)
+                        < cycles(
+                            &Insn::draw {
+                                x: 0,
+                                y: 0,
+                                n: n + 1
+                            },
+                            x
+                        )
+                );
+                if x < 7 {
+                    assert!(cycles(&insn, x) < cycles(&insn, x + 1));
+                }
+                // only the misalignment within a byte matters
+                assert_eq!(cycles(&insn, x), cycles(&insn, x + 8));
+            }
+        }
+    }
+
+    /// The timers tick once per frame's worth of cycles, regardless of instruction count
+    #[test]
+    fn timers_tick_per_frame() {
+        let (mut cpu, mut bus) = setup_vip(&[
+            0x60, 0x05, // mov #05, v0
+            0xf0, 0x15, // mov v0, DT
+            0x80, 0x10, // mov v1, v0
+            0x12, 0x04, // jmp 204
+        ]);
+        cpu.run_frame(&mut bus).unwrap();
+        assert_eq!(4, cpu.delay());
+        let first = cpu.cycle();
+        cpu.run_frame(&mut bus).unwrap();
+        assert_eq!(3, cpu.delay());
+        // Each loop costs 44 + 23 cycles
+        let per_frame = (cpu.cycle() - first) as f64;
+        assert!((per_frame - 2.0 * INTERPRETER_CYCLES as f64 / 67.0).abs() <= 2.0);
+    }
+
+    /// The display interrupt takes about half of each frame, so the loop only runs half as often
+    #[test]
+    fn interrupt_overhead() {
+        let (mut cpu, mut bus) = setup_vip(&[
+            0x60, 0x00, // mov #00, v0
+            0x12, 0x00, // jmp 200
+        ]);
+        cpu.run_frame(&mut bus).unwrap();
+        // Each loop costs 6 + 23 cycles
+        let loops = cpu.cycle() / 2;
+        assert_eq!(INTERPRETER_CYCLES / 29 + 1, loops);
+        assert!(loops < CYCLES_PER_FRAME / 29 / 2 + 2);
+    }
+
+    /// Dxyn: Drawing waits for the frame interrupt, so only one sprite is drawn per frame
+    #[test]
+    fn draw_waits_for_interrupt() {
+        let (mut cpu, mut bus) = setup_vip(&[
+            0xd0, 0x05, // draw #5, v0, v0
+            0x12, 0x00, // jmp 200
+        ]);
+        cpu.flags.quirks.draw_wait = false;
+        for frame in 1..=4 {
+            cpu.run_frame(&mut bus).unwrap();
+            // draw, then (jmp, draw) once per frame after that
+            assert_eq!(2 * frame - 1, cpu.cycle());
+        }
+    }
+
+    /// Skips cost extra when taken
+    #[test]
+    fn skip_taken() {
+        let (mut a, mut bus_a) = setup_vip(&[0x30, 0x00]); // se #00, v0 (taken)
+        let (mut b, mut bus_b) = setup_vip(&[0x30, 0x01]); // se #01, v0 (not taken)
+        a.tick(&mut bus_a).unwrap();
+        b.tick(&mut bus_b).unwrap();
+        assert_eq!(
+            a.frame_cycles,
+            b.frame_cycles + crate::cpu::timing::SKIP_TAKEN
+        );
+    }
+}
+
+mod quirks {
+    use super::*;
+
+    /// Sets up a CPU with the given quirks, and a blank lores screen
+    fn setup_quirks(quirks: Quirks) -> (CPU, Bus) {
+        let (mut cpu, mut bus) = setup_environment();
+        cpu.flags.quirks = quirks;
+        bus.clear_region(Screen);
+        (cpu, bus)
+    }
+
+    /// Dxyn: Sprites are clipped at the edges of the screen, or wrap around them
+    #[test]
+    fn wrap() {
+        for (wrap, expected) in [(false, [0x0f, 0, 0, 0]), (true, [0x0f, 0xf0, 0x0f, 0xf0])] {
+            let (mut cpu, mut bus) = setup_quirks(Quirks {
+                wrap,
+                ..Default::default()
+            });
+            bus.write(0x300u16, 0xffffu16);
+            cpu.i = 0x300;
+            (cpu.v[0], cpu.v[1]) = (60, 31);
+            cpu.draw_lores(0, 1, 2, &mut bus);
+            let screen = bus.get_region(Screen).unwrap();
+            // bottom right, bottom left, top right, top left
+            assert_eq!(
+                expected,
+                [screen[0xff], screen[0xf8], screen[0x07], screen[0x00]]
+            );
+        }
+    }
+
+    /// Dxy0: vF is set to the number of colliding rows, or to 1
+    #[test]
+    fn count_collisions() {
+        for (count_collisions, expected) in [(false, 1), (true, 16)] {
+            let (mut cpu, mut bus) = setup_quirks(Quirks {
+                count_collisions,
+                ..Default::default()
+            });
+            bus.get_region_mut(Screen).unwrap().fill(0xff);
+            bus.get_mut(0x300..0x320).unwrap().fill(0xff);
+            cpu.i = 0x300;
+            cpu.draw_hires(0, 0, 0, &mut bus);
+            assert_eq!(expected, cpu.v[0xf]);
+        }
+    }
+
+    /// 00cN, 00fb, 00fc: Scrolling in lores moves whole pixels, or half pixels
+    #[test]
+    fn half_scroll() {
+        for (half_scroll, expected) in [(false, [0x08u8, 0x80]), (true, [0x20, 0x80])] {
+            let (mut cpu, mut bus) = setup_quirks(Quirks {
+                half_scroll,
+                ..Default::default()
+            });
+            bus.write(0xf00u16, 0x80u8);
+            cpu.scroll_right(&mut bus);
+            cpu.scroll_down(2, &mut bus);
+            let line = if half_scroll { 0xf08u16 } else { 0xf10u16 };
+            assert_eq!(expected[0], bus.read(line));
+            cpu.scroll_left(&mut bus);
+            assert_eq!(expected[1], bus.read(line));
+        }
+    }
+
+    /// 00fe, 00ff: Changing resolution clears the screen, or leaves its memory alone
+    #[test]
+    fn keep_screen() {
+        for keep_screen in [false, true] {
+            let (mut cpu, mut bus) = setup_quirks(Quirks {
+                keep_screen,
+                ..Default::default()
+            });
+            bus.write(0xf00u16, 0x80u8);
+            bus.write(0xf08u16, 0x01u8);
+            let expected = if keep_screen { [0x80u8, 0x01] } else { [0; 2] };
+            cpu.init_hires(&mut bus);
+            assert_eq!(expected, [bus.read(0xf00u16), bus.read(0xf08u16)]);
+            cpu.init_lores(&mut bus);
+            assert_eq!(expected, [bus.read(0xf00u16), bus.read(0xf08u16)]);
+        }
+    }
+
+    /// Fx1E: vF is set when I overflows past 0xFFF, or left alone
+    #[test]
+    fn i_overflow() {
+        for (i_overflow, i, expected) in [(false, 0xfff, 0xaa), (true, 0xffe, 0), (true, 0xfff, 1)]
+        {
+            let (mut cpu, _) = setup_quirks(Quirks {
+                i_overflow,
+                ..Default::default()
+            });
+            (cpu.i, cpu.v[0], cpu.v[0xf]) = (i, 1, 0xaa);
+            cpu.add_i(0);
+            assert_eq!(i + 1, cpu.i);
+            assert_eq!(expected, cpu.v[0xf]);
+        }
+    }
+
+    /// The Super-Chip preset clips sprites and counts collisions
+    #[test]
+    fn schip_preset() {
+        let quirks = Quirks::from(Mode::SChip);
+        assert!(!quirks.wrap);
+        assert!(quirks.count_collisions && quirks.half_scroll && quirks.keep_screen);
+        assert!(Quirks::from(Mode::XOChip).wrap);
+    }
+}
+
+mod schip {
+    use super::*;
+
+    /// Fx75: Save v0..=vX to the RPL flag registers, without touching memory
+    #[test]
+    fn store_flags() {
+        for x in 0..16 {
+            let (mut cpu, bus) = setup_environment();
+            let before = bus.clone();
+            cpu.v.copy_from_slice(b"ABCDEFGHIJKLMNOP");
+            cpu.store_flags(x);
+            assert_eq!(cpu.rpl[..=x], cpu.v[..=x]);
+            assert!(cpu.rpl[x + 1..].iter().all(|&flag| flag == 0));
+            assert_eq!(before, bus);
+        }
+    }
+
+    /// Fx85: Load v0..=vX from the RPL flag registers
+    #[test]
+    fn load_flags() {
+        for x in 0..16 {
+            let (mut cpu, _) = setup_environment();
+            cpu.rpl.copy_from_slice(b"ABCDEFGHIJKLMNOP");
+            cpu.load_flags(x);
+            assert_eq!(cpu.v[..=x], cpu.rpl[..=x]);
+            assert!(cpu.v[x + 1..].iter().all(|&reg| reg == 0));
+        }
+    }
+}
+
+/// Tests the XO-Chip extensions
+mod xochip {
+    use super::*;
+
+    /// 5xy2: Save registers vX..=vY to memory at I
+    #[test]
+    fn store_range() {
+        let (mut cpu, mut bus) = setup_environment();
+        const DATA: &[u8] = b"ABCDEFGHIJKLMNOP";
+        let addr = 0x456;
+        cpu.v.copy_from_slice(DATA);
+        for x in 0..16 {
+            for y in 0..16 {
+                cpu.i = addr as u16;
+                cpu.store_range(x, y, &mut bus);
+                let expected: Vec<u8> = if x <= y {
+                    DATA[x..=y].to_vec()
+                } else {
+                    DATA[y..=x].iter().rev().copied().collect()
+                };
+                let mem = bus
+                    .get_mut(addr..addr + DATA.len())
+                    .expect("Getting a mutable slice at addr 0x0456 should not fail");
+                assert_eq!(mem[..expected.len()], expected);
+                // I is not modified
+                assert_eq!(cpu.i, addr as u16);
+                mem.fill(0);
+            }
+        }
+    }
+
+    /// 5xy3: Load registers vX..=vY from memory at I
+    #[test]
+    fn load_range() {
+        let (mut cpu, mut bus) = setup_environment();
+        const DATA: &[u8] = b"ABCDEFGHIJKLMNOP";
+        let addr = 0x456;
+        bus.get_mut(addr..addr + DATA.len())
+            .expect("Getting a mutable slice at addr 0x0456..0x0466 should not fail")
+            .copy_from_slice(DATA);
+        for x in 0..16 {
+            for y in 0..16 {
+                cpu.i = addr as u16;
+                cpu.v.fill(0);
+                cpu.load_range(x, y, &mut bus);
+                let (lo, hi) = (x.min(y), x.max(y));
+                for reg in lo..=hi {
+                    let offset = if x <= y { reg - x } else { x - reg };
+                    assert_eq!(cpu.v[reg], DATA[offset]);
+                }
+                // I is not modified
+                assert_eq!(cpu.i, addr as u16);
+            }
+        }
+    }
+
+    /// 00dN: Scroll the screen up N lines
+    #[test]
+    fn scroll_up() {
+        for n in 0..16 {
+            let (mut cpu, mut bus) = setup_environment();
+            let before = bus.get_region(Screen).unwrap().to_vec();
+            cpu.scroll_up(n, &mut bus);
+            let after = bus.get_region(Screen).unwrap();
+            let n = n as usize * 8;
+            assert_eq!(after[..after.len() - n], before[n..]);
+            assert!(after[after.len() - n..].iter().all(|&byte| byte == 0));
+        }
+    }
+
+    /// F000 aaaa: Skipping over a long load skips the whole instruction
+    #[test]
+    fn skip_long() {
+        let (mut cpu, mut bus) = setup_environment();
+        cpu.flags.mode = Mode::XOChip;
+        bus.write(0x200u16, 0x3000u16); // se #00, v0
+        bus.write(0x202u16, 0xf000u16); // mov $1234, I
+        bus.write(0x204u16, 0x1234u16);
+        cpu.tick(&mut bus)
+            .expect("0x3000 (se) should be a valid opcode.");
+        assert_eq!(0x206, cpu.pc);
+        // Other modes don't know about long loads, so they only skip two bytes
+        let (mut cpu, _) = setup_environment();
+        cpu.flags.mode = Mode::SChip;
+        cpu.tick(&mut bus)
+            .expect("0x3000 (se) should be a valid opcode.");
+        assert_eq!(0x204, cpu.pc);
+    }
+
+    /// Fx3A: Load vX into the pitch register
+    #[test]
+    fn load_pitch() {
+        let (mut cpu, _) = setup_environment();
+        for word in 0..=0xff {
+            for x in 0..=0xf {
+                cpu.v[x] = word;
+                cpu.load_pitch(x);
+                assert_eq!(cpu.pitch, word);
+            }
+        }
+    }
+
+    /// F002: Load the audio pattern buffer from I
+    #[test]
+    fn load_pattern() {
+        let (mut cpu, mut bus) = setup_environment();
+        const DATA: &[u8; 16] = b"ABCDEFGHIJKLMNOP";
+        bus.get_mut(0x456..0x466)
+            .expect("Getting a mutable slice at addr 0x0456..0x0466 should not fail")
+            .copy_from_slice(DATA);
+        cpu.i = 0x456;
+        cpu.load_pattern(&mut bus);
+        assert_eq!(cpu.pattern, Some(*DATA));
+    }
+
+    /// Sets up a CPU with a two-plane lores screen at 0x0F00..0x1100
+    fn setup_planes() -> (CPU, Bus) {
+        let (cpu, _) = setup_environment();
+        let bus = bus! {
+            Program [0x0200..0x0F00],
+            Screen  [0x0F00..0x1100],
+        };
+        (cpu, bus)
+    }
+
+    /// Dxyn: Only the selected planes are drawn to, each with its own sprite data
+    #[test]
+    fn draw_planes() {
+        for (planes, expected) in [
+            (0, [0u8, 0]),
+            (1, [0xaa, 0]),
+            (2, [0, 0xaa]),
+            (3, [0xaa, 0x55]),
+        ] {
+            let (mut cpu, mut bus) = setup_planes();
+            bus.write(0x300u16, 0xaa55u16);
+            cpu.i = 0x300;
+            cpu.planes = planes;
+            cpu.draw_lores(0, 0, 1, &mut bus);
+            assert_eq!(
+                expected[0],
+                bus.read(0xf00u16),
+                "plane 1 with mask {planes}"
+            );
+            assert_eq!(
+                expected[1],
+                bus.read(0x1000u16),
+                "plane 2 with mask {planes}"
+            );
+        }
+    }
+
+    /// 00e0: Only the selected planes are cleared
+    #[test]
+    fn clear_planes() {
+        let (mut cpu, mut bus) = setup_planes();
+        bus.get_region_mut(Screen).unwrap().fill(0xff);
+        cpu.planes = 2;
+        cpu.clear_screen(&mut bus);
+        let screen = bus.get_region(Screen).unwrap();
+        assert!(screen[..0x100].iter().all(|&byte| byte == 0xff));
+        assert!(screen[0x100..].iter().all(|&byte| byte == 0));
+    }
+
+    /// 00cN, 00dN, 00fb, 00fc: Only the selected planes are scrolled
+    #[test]
+    fn scroll_planes() {
+        let (mut cpu, mut bus) = setup_planes();
+        bus.write(0xf00u16, 0x80u8);
+        bus.write(0x1000u16, 0x80u8);
+        cpu.planes = 1;
+        cpu.scroll_down(1, &mut bus);
+        cpu.scroll_right(&mut bus);
+        assert_eq!(0x08u8, bus.read(0xf08u16));
+        assert_eq!(0x80u8, bus.read(0x1000u16));
+        cpu.scroll_left(&mut bus);
+        cpu.scroll_up(1, &mut bus);
+        assert_eq!(0x80u8, bus.read(0xf00u16));
+        assert_eq!(0x80u8, bus.read(0x1000u16));
+    }
+
+    /// 00fe, 00ff: Changing resolution keeps, and clears, every plane
+    #[test]
+    fn resize_planes() {
+        let (mut cpu, mut bus) = setup_planes();
+        bus.get_region_mut(Screen).unwrap().fill(0xff);
+        cpu.planes = 1;
+        cpu.init_hires(&mut bus);
+        assert_eq!(0x800, bus.get_region(Screen).unwrap().len());
+        assert!(bus
+            .get_region(Screen)
+            .unwrap()
+            .iter()
+            .all(|&byte| byte == 0));
+        cpu.init_lores(&mut bus);
+        assert_eq!(0x200, bus.get_region(Screen).unwrap().len());
+    }
+
+    /// Sets up a CPU with the 64 KiB XO-Chip memory map
+    fn setup_long() -> (CPU, Bus) {
+        let map = MemoryMap::xochip();
+        let (cpu, _) = setup_environment();
+        (
+            CPU {
+                screen: map.screen.start,
+                sp: map.stack_top(),
+                ..cpu
+            },
+            map.bus(),
+        )
+    }
+
+    /// Fx1E: I wraps around at 16 bits
+    #[test]
+    fn add_i_wraps() {
+        let (mut cpu, _) = setup_long();
+        cpu.i = 0xfffe;
+        cpu.v[0] = 4;
+        cpu.add_i(0);
+        assert_eq!(0x0002, cpu.i);
+    }
+
+    /// Fx55, Fx65: Memory accesses and I wrap around at 16 bits
+    #[test]
+    fn dma_wraps() {
+        let (mut cpu, mut bus) = setup_long();
+        cpu.v[..4].copy_from_slice(b"ABCD");
+        cpu.i = 0xfffe;
+        cpu.store_dma(3, &mut bus);
+        assert_eq!(0x0002, cpu.i);
+        assert_eq!(Some(&b"AB"[..]), bus.get(0xfffe..0x10000));
+        assert_eq!(Some(&b"CD"[..]), bus.get(0x0000..0x0002));
+        cpu.v.fill(0);
+        cpu.i = 0xfffe;
+        cpu.load_dma(3, &mut bus);
+        assert_eq!(b"ABCD", &cpu.v[..4]);
+        // the screen, outside of the 16-bit address space, is untouched
+        assert!(bus
+            .get_region(Screen)
+            .unwrap()
+            .iter()
+            .all(|&byte| byte == 0));
+    }
+
+    /// Dxyn: Sprite data is fetched with 16-bit wrapping
+    #[test]
+    fn draw_wraps() {
+        let (mut cpu, mut bus) = setup_long();
+        bus.write(0xffffu16, 0xf0u8);
+        bus.write(0x0000u16, 0x0fu8);
+        cpu.i = 0xffff;
+        cpu.draw_lores(0, 0, 2, &mut bus);
+        let screen = bus.get_region(Screen).unwrap();
+        assert_eq!([0xf0, 0x0f], [screen[0], screen[8]]);
+    }
+}
@@ -6,6 +6,12 @@ const INDX: &[u8; 16] = b"\0\x01\x02\x03\x04\x05\x06\x07\x08\x09\x0a\x0b\x0c\x0d
 /// runs one arbitrary operation on a brand new CPU
 /// returns the CPU for inspection
 fn run_single_op(op: &[u8]) -> CPU {
+    run_single_op_in(op, Mode::Chip8)
+}
+
+/// runs one arbitrary operation on a brand new CPU, in the given [Mode]
+/// returns the CPU for inspection
+fn run_single_op_in(op: &[u8], mode: Mode) -> CPU {
     let (mut cpu, mut bus) = (
         CPU::default(),
         bus! {
@@ -14,6 +20,7 @@ fn run_single_op(op: &[u8]) -> CPU {
     );
     cpu.v = *INDX;
     cpu.flags.quirks = Quirks::from(false);
+    cpu.flags.mode = mode;
     cpu.tick(&mut bus).unwrap(); // will panic if unimplemented
     cpu
 }
@@ -55,8 +62,6 @@ mod se {
     #[test] fn skip()   { assert_eq!(0x204, run_single_op(b"\x50\x00").pc); }
     #[test] fn noskip() { assert_eq!(0x202, run_single_op(b"\x50\x10").pc); }
     #[test] #[should_panic] fn u5ff1() { run_single_op(b"\x5f\xf1"); }
-    #[test] #[should_panic] fn u5ff2() { run_single_op(b"\x5f\xf2"); }
-    #[test] #[should_panic] fn u5ff3() { run_single_op(b"\x5f\xf3"); }
     #[test] #[should_panic] fn u5ff4() { run_single_op(b"\x5f\xf4"); }
     #[test] #[should_panic] fn u5ff5() { run_single_op(b"\x5f\xf5"); }
     #[test] #[should_panic] fn u5ff6() { run_single_op(b"\x5f\xf6"); }
@@ -165,9 +170,40 @@ mod io {
     #[test] fn store_sound_timer() { assert_eq!(0xf, run_single_op(b"\xff\x18").sound()); }
     #[test] fn add_i()             { assert_eq!(0x0, run_single_op(b"\xf0\x1e").i);       }
     #[test] fn load_sprite()       { assert_eq!(0x50, run_single_op(b"\xf0\x29").i);      }
+    #[test] fn load_big_sprite()   { assert_eq!(0xaa, run_single_op(b"\xf1\x30").i);      }
     #[test] fn bcd_convert()       { run_single_op(b"\xf0\x33"); /* nothing to check */   }
     #[test] fn store_dma()         { assert_eq!(INDX, run_single_op(b"\xff\x55").v());    }
     #[test] fn load_dma()          { assert_eq!([0;16], run_single_op(b"\xff\x65").v());  }
     // unimplemented
     #[test] #[should_panic] fn uffff() { run_single_op(b"\xff\xff"); }
 }
+#[rustfmt::skip]
+mod xochip {
+    use super::*;
+    fn run_single_op(op: &[u8]) -> CPU { run_single_op_in(op, Mode::XOChip) }
+    #[test] fn scroll_up()     { run_single_op(b"\x00\xd1"); /* no screen to check */     }
+    #[test] fn store_range()   { assert_eq!(INDX, run_single_op(b"\x50\xf2").v());          }
+    #[test] fn load_range()    { assert_eq!([0;16], run_single_op(b"\x50\xf3").v());        }
+    #[test] fn load_i_long()   { assert_eq!(0x1234, run_single_op(b"\xf0\x00\x12\x34").i()); }
+    #[test] fn long_length()   { assert_eq!(0x204, run_single_op(b"\xf0\x00\x12\x34").pc()); }
+    #[test] fn select_planes() { assert_eq!(0x2, run_single_op(b"\xf2\x01").planes());     }
+    #[test] fn load_pattern()  { assert_eq!(Some(&[0;16]), run_single_op(b"\xf0\x02").pattern()); }
+    #[test] fn load_pitch()    { assert_eq!(0x7, run_single_op(b"\xf7\x3a").pitch());      }
+    /// Outside of XO-Chip mode, the extensions are unimplemented
+    #[test] #[should_panic] fn chip8_long()  { super::run_single_op(b"\xf0\x00\x12\x34"); }
+    #[test] #[should_panic] fn schip_range() { run_single_op_in(b"\x50\xf2", Mode::SChip); }
+}
+
+/// The lookup table should decode every opcode exactly like the instruction set does
+#[test]
+fn lookup_table() {
+    use imperative_rs::InstructionSet;
+    for word in 0..=u16::MAX {
+        let bytes = [word.to_be_bytes(), [0x12, 0x34]].concat();
+        assert_eq!(
+            Insn::decode(&bytes).ok(),
+            decoder::decode(&bytes),
+            "{word:04x}"
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! Approximates the time the COSMAC VIP's Chip-8 interpreter spends on each [Insn]
+//!
+//! When [Flags::vip_timing](super::flags::Flags::vip_timing) is set, every instruction
+//! is charged its cost in VIP machine cycles, and the frame interrupt lands once the
+//! interpreter's share of the frame ([INTERPRETER_CYCLES]) has been spent.
+//!
+//! Costs include fetching and decoding the instruction, and are rounded to the
+//! nearest machine cycle (8 clocks of the VIP's 1.76 MHz CDP1802, about 4.54µs).
+//! They're converted from the per-instruction times measured in Jackson S.'s
+//! *Chip-8 Instruction Scheduling and Frequency* (2019), which follow Laurence
+//! Scotford's walk through the interpreter's code in *Chip-8 on the COSMAC VIP*.
+//!
+//! The rest of each frame belongs to the display. Per the CDP1861 datasheet, a frame
+//! is 262 lines of 14 machine cycles, and the display interrupt arrives 29 cycles
+//! before the first of the 128 lines it shows. The interrupt routine then spends every
+//! one of those lines feeding the display's DMA, so the interpreter can't run until
+//! the picture is done ([INTERRUPT_CYCLES]).
+
+use super::disassembler::Insn;
+
+/// Number of VIP machine cycles in one line of the CDP1861's picture
+pub const CYCLES_PER_LINE: usize = 14;
+
+/// Number of VIP machine cycles between frame interrupts (262 lines, or 1.76 MHz / 8 / 60 Hz)
+pub const CYCLES_PER_FRAME: usize = 262 * CYCLES_PER_LINE;
+
+/// Number of VIP machine cycles each frame spends in the display interrupt routine,
+/// from the interrupt to the end of the 128 lines of DMA
+pub const INTERRUPT_CYCLES: usize = 29 + 128 * CYCLES_PER_LINE;
+
+/// Number of VIP machine cycles left for the interpreter in each frame
+pub const INTERPRETER_CYCLES: usize = CYCLES_PER_FRAME - INTERRUPT_CYCLES;
+
+/// Extra cycles spent by a skip instruction when the skip is taken
+pub const SKIP_TAKEN: usize = 2;
+
+/// Gets the number of VIP machine cycles spent executing `insn`
+///
+/// `x` is the horizontal coordinate of the sprite, for `Dxyn`,
+/// since shifting the sprite into place takes time for every pixel of misalignment.
+/// # Examples
+/// ```rust
+/// # use chirp::cpu::{disassembler::Insn, timing::cycles};
+/// assert_eq!(6, cycles(&Insn::movb { x: 0, B: 0 }, 0));
+/// // Unaligned sprites take longer to draw
+/// let draw = Insn::draw { x: 0, y: 0, n: 5 };
+/// assert!(cycles(&draw, 0) < cycles(&draw, 3));
+/// ```
+#[rustfmt::skip]
+pub fn cycles(insn: &Insn, x: u8) -> usize {
+    match insn {
+        Insn::cls               => 24,
+        Insn::ret               => 23,
+        Insn::jmp   { .. }      => 23,
+        Insn::call  { .. }      => 23,
+        Insn::seb   { .. }      => 12,
+        Insn::sneb  { .. }      => 12,
+        Insn::se    { .. }      => 16,
+        Insn::movb  { .. }      => 6,
+        Insn::addb  { .. }      => 10,
+        Insn::mov   { .. }
+        | Insn::or  { .. }
+        | Insn::and { .. }
+        | Insn::xor { .. }
+        | Insn::add { .. }
+        | Insn::sub { .. }
+        | Insn::shr { .. }
+        | Insn::bsub{ .. }
+        | Insn::shl { .. }      => 44,
+        Insn::sne   { .. }      => 16,
+        Insn::movI  { .. }      => 12,
+        Insn::jmpr  { .. }      => 23,
+        Insn::rand  { .. }      => 36,
+        // Each row is shifted into place one bit at a time, then XORed onto the screen
+        Insn::draw  { n, .. }   => 68 + *n as usize * (46 + 20 * (x as usize & 7)),
+        Insn::sek   { .. }      => 16,
+        Insn::snek  { .. }      => 16,
+        Insn::getdt { .. }      => 10,
+        Insn::waitk { .. }      => 10,
+        Insn::setdt { .. }      => 10,
+        Insn::movst { .. }      => 10,
+        Insn::addI  { .. }      => 19,
+        Insn::font  { .. }      => 20,
+        Insn::bcd   { .. }      => 204,
+        Insn::dmao  { x }
+        | Insn::dmai{ x }       => 13 + 8 * (*x + 1),
+        // The VIP doesn't implement the Super-Chip and XO-Chip extensions
+        _                       => 12,
+    }
+}
@@ -0,0 +1,679 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! An interactive debugger for a [Chip8], driven by one line of text per command
+//!
+//! The [Debugger] never runs the machine on its own. Commands like `continue`, `next`
+//! and `finish` unpause the CPU, and the frontend runs frames until it stops again,
+//! handing whatever stopped it to [Debugger::stopped]. Commands from a script are only
+//! handed out by [Debugger::next_scripted] while the CPU is paused, so a script
+//! replays the same way every time.
+//!
+//! Type `help` for the list of commands. An empty line repeats the last command.
+
+use crate::{
+    bus::Region,
+    cpu::{
+        breakpoint::{Breakpoint, Condition, Trigger, Watch, Watchpoint},
+        decoder::decode,
+        disassembler::Insn,
+    },
+    error::{Error, Result},
+    Chip8,
+};
+use std::{collections::VecDeque, io::Write, ops::Range, path::Path};
+
+/// The help text, printed by `help`
+const HELP: &str = "\
+break ADDR [if COND]     Stop at ADDR, when COND (like `v3 == 0x10 && I > 0x400`) holds
+tbreak ADDR [if COND]    Stop at ADDR once, then delete the breakpoint
+watch [read|write|change] ADDR [LEN]
+                         Stop after memory at ADDR changes (or is read, or written)
+delete [ID]              Delete breakpoint or watchpoint ID, or every one of them
+unwatch ID               Delete watchpoint ID
+info                     List breakpoints and watchpoints
+continue                 Run until something stops the CPU
+interrupt                Stop the CPU where it is (Ctrl-C at the console)
+step [N]                 Run N instructions (default 1)
+next                     Run one instruction, stepping over calls
+finish                   Run until the current subroutine returns
+frame [N]                Run N frames (default 1)
+regs                     Print the registers
+set REG VALUE            Set v0-vF, i, pc, dt or st
+x/NF ADDR                Examine N units of memory, as (b)ytes, (h)alfwords or (i)nstructions
+poke ADDR BYTE...        Write bytes to memory
+disas [ADDR] [N]         Disassemble N instructions at ADDR (default: around the pc)
+backtrace                Print the return addresses on the stack
+source FILE              Run the commands in FILE
+history                  Print the command history
+quit                     Leave the debugger
+";
+
+/// Debugs a [Chip8], one command at a time
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Debugger {
+    history: Vec<String>,
+    // Commands from scripts, waiting for the CPU to stop
+    script: VecDeque<String>,
+    // The temporary breakpoint set by `next` or `finish`, if it's still waiting to be hit
+    stepping: Option<usize>,
+}
+
+impl Debugger {
+    /// Constructs a [Debugger] with an empty history
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets every command run so far, oldest first
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    /// Queues every command in the file at `path`, to be run by the frontend
+    ///
+    /// Blank lines, and lines starting with `#`, are skipped.
+    pub fn source(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let script = std::fs::read_to_string(path)?;
+        let lines = script
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'));
+        // Commands sourced by a script run before the rest of that script
+        for (index, line) in lines.enumerate() {
+            self.script.insert(index, line.to_string());
+        }
+        Ok(())
+    }
+
+    /// Takes the next command from a script, while the CPU is paused
+    pub fn next_scripted(&mut self, ch8: &Chip8) -> Option<String> {
+        match ch8.cpu.flags.pause {
+            true => self.script.pop_front(),
+            false => None,
+        }
+    }
+
+    /// Runs one command, writing its output to `out`
+    ///
+    /// An empty line repeats the last command. Returns false when asked to quit.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, debugger::Debugger};
+    /// # fn main() -> Result<()> {
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x60, 0x2a, 0x70, 0x01] },
+    /// };
+    /// let (mut debugger, mut out) = (Debugger::new(), vec![]);
+    /// debugger.run("step", &mut ch8, &mut out)?;
+    /// assert_eq!(0x2a, ch8.cpu.v()[0]);
+    /// debugger.run("", &mut ch8, &mut out)?;
+    /// assert_eq!(0x2b, ch8.cpu.v()[0]);
+    /// assert_eq!(&["step"], debugger.history());
+    /// assert!(!debugger.run("quit", &mut ch8, &mut out)?);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn run(&mut self, line: &str, ch8: &mut Chip8, out: &mut impl Write) -> Result<bool> {
+        let line = match line.trim() {
+            "" => match self.history.last() {
+                Some(last) => last.clone(),
+                None => return Ok(true),
+            },
+            line => {
+                self.history.push(line.to_string());
+                line.to_string()
+            }
+        };
+        let command: Command = line.parse()?;
+        self.execute(command, ch8, out)
+    }
+
+    /// Stops a running CPU where it is, and reports where
+    ///
+    /// Frontends call this when the user interrupts the machine, like with Ctrl-C.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, debugger::Debugger};
+    /// # fn main() -> Result<()> {
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x70, 0x01, 0x12, 0x00] },
+    /// };
+    /// let (mut debugger, mut out) = (Debugger::new(), vec![]);
+    /// debugger.run("continue", &mut ch8, &mut out)?;
+    /// assert!(!ch8.cpu.flags.pause);
+    /// debugger.interrupt(&mut ch8, &mut out)?;
+    /// assert!(ch8.cpu.flags.pause);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn interrupt(&mut self, ch8: &mut Chip8, out: &mut impl Write) -> Result<()> {
+        if ch8.cpu.flags.pause {
+            writeln!(out, "The CPU isn't running")?;
+            return Ok(());
+        }
+        writeln!(out, "Interrupted")?;
+        self.stopped(ch8, None, out)
+    }
+
+    /// Reports why the CPU stopped, and where
+    ///
+    /// Frontends call this whenever running the machine fails, or when it pauses by
+    /// itself. Errors are printed, not returned, so the machine can still be inspected.
+    pub fn stopped(
+        &mut self,
+        ch8: &mut Chip8,
+        error: Option<Error>,
+        out: &mut impl Write,
+    ) -> Result<()> {
+        ch8.cpu.flags.pause = true;
+        let stepping = self.stepping.take();
+        if let Some(id) = stepping {
+            ch8.cpu.remove_breakpoint(id);
+        }
+        match error {
+            // `next` and `finish` stopping where they should isn't worth mentioning
+            Some(Error::BreakpointHit {
+                trigger: Trigger::Breakpoint { id: Some(id) },
+                ..
+            }) if Some(id) == stepping => {}
+            Some(e) => writeln!(out, "{e}")?,
+            None => {}
+        }
+        location(ch8, out)
+    }
+
+    fn execute(&mut self, command: Command, ch8: &mut Chip8, out: &mut impl Write) -> Result<bool> {
+        let Chip8 { cpu, bus } = ch8;
+        match command {
+            Command::Break {
+                addr,
+                condition,
+                once,
+            } => {
+                let mut breakpoint = Breakpoint::new(addr);
+                if let Some(condition) = condition {
+                    breakpoint = breakpoint.when(condition);
+                }
+                if once {
+                    breakpoint = breakpoint.once();
+                }
+                let id = cpu.add_breakpoint(breakpoint);
+                writeln!(out, "Breakpoint {id} at {addr:03x}")?;
+            }
+            Command::Watch { range, watch } => {
+                let id = cpu.add_watchpoint(bus, Watchpoint::new(range.clone(), watch));
+                writeln!(out, "Watchpoint {id} on {watch} at {range:03x?}")?;
+            }
+            Command::Delete(Some(id)) => {
+                if cpu.remove_breakpoint(id).is_some() {
+                    writeln!(out, "Deleted breakpoint {id}")?
+                } else if bus.remove_watchpoint(id).is_some() {
+                    writeln!(out, "Deleted watchpoint {id}")?
+                } else {
+                    writeln!(out, "No breakpoint or watchpoint {id}")?
+                }
+            }
+            Command::Delete(None) => {
+                for point in cpu.breakpoints().to_vec() {
+                    cpu.unset_break(point);
+                }
+                while let Some(id) = cpu.conditional_breakpoints().first().map(|bp| bp.id()) {
+                    cpu.remove_breakpoint(id);
+                }
+                while let Some(id) = bus.watchpoints().first().map(|wp| wp.id()) {
+                    bus.remove_watchpoint(id);
+                }
+                self.stepping = None;
+                writeln!(out, "Deleted every breakpoint and watchpoint")?;
+            }
+            Command::Unwatch(id) => match bus.remove_watchpoint(id) {
+                Some(_) => writeln!(out, "Deleted watchpoint {id}")?,
+                None => writeln!(out, "No watchpoint {id}")?,
+            },
+            Command::Info => {
+                for point in cpu.breakpoints() {
+                    writeln!(out, "Breakpoint at {point:03x}")?;
+                }
+                for bp in cpu.conditional_breakpoints() {
+                    write!(out, "Breakpoint {} at {:03x}", bp.id(), bp.addr())?;
+                    if let Some(condition) = bp.condition() {
+                        write!(out, " if {condition}")?;
+                    }
+                    writeln!(out, ", hit {} time(s)", bp.hits())?;
+                }
+                for wp in bus.watchpoints() {
+                    writeln!(
+                        out,
+                        "Watchpoint {} on {} at {:03x?}, hit {} time(s)",
+                        wp.id(),
+                        wp.watch(),
+                        wp.range(),
+                        wp.hits()
+                    )?;
+                }
+            }
+            Command::Continue => {
+                cpu.flags.pause = false;
+                writeln!(out, "Continuing")?;
+            }
+            Command::Interrupt => self.interrupt(ch8, out)?,
+            Command::Step(steps) => return self.step(steps, ch8, out).map(|_| true),
+            Command::Next => {
+                let pc = cpu.pc();
+                match bus.get(pc as usize..pc as usize + 2).and_then(decode) {
+                    Some((_, Insn::call { .. })) => {
+                        let sp = cpu.sp();
+                        self.resume_at(pc.wrapping_add(2), sp, ch8)?;
+                    }
+                    _ => return self.step(1, ch8, out).map(|_| true),
+                }
+            }
+            Command::Finish => {
+                let sp = cpu.sp();
+                let top = stack_top(ch8).unwrap_or_default();
+                let Some(&[hi, lo]) = ch8.bus.get(sp + 2..sp + 4).filter(|_| sp < top) else {
+                    writeln!(out, "Not in a subroutine")?;
+                    return Ok(true);
+                };
+                self.resume_at(u16::from_be_bytes([hi, lo]), sp + 2, ch8)?;
+            }
+            Command::Frame(frames) => {
+                for _ in 0..frames {
+                    cpu.flags.pause = false;
+                    let result = cpu.run_frame(bus).map(|_| ());
+                    if let Err(e) = result {
+                        return self.stopped(ch8, Some(e), out).map(|_| true);
+                    }
+                }
+                self.stopped(ch8, None, out)?;
+            }
+            Command::Regs => {
+                writeln!(
+                    out,
+                    "pc: {:03x}  i: {:03x}  sp: {:03x}",
+                    cpu.pc(),
+                    cpu.i(),
+                    cpu.sp()
+                )?;
+                for (row, regs) in cpu.v().chunks(4).enumerate() {
+                    let regs: Vec<_> = regs
+                        .iter()
+                        .enumerate()
+                        .map(|(col, value)| format!("v{:X}: {value:02x}", row * 4 + col))
+                        .collect();
+                    writeln!(out, "{}", regs.join("  "))?;
+                }
+                writeln!(
+                    out,
+                    "dt: {}  st: {}  cycle: {}",
+                    cpu.delay(),
+                    cpu.sound(),
+                    cpu.cycle()
+                )?;
+            }
+            Command::Set { register, value } => match register {
+                Register::V(reg) => cpu.set_v(reg, value as u8)?,
+                Register::I => _ = cpu.set_i(value as u16),
+                Register::Pc => _ = cpu.set_pc(value as u16),
+                Register::Delay => _ = cpu.set_delay(value as u8),
+                Register::Sound => _ = cpu.set_sound(value as u8),
+            },
+            Command::Examine {
+                count,
+                format: Format::Insn,
+                addr,
+            } => listing(ch8, addr, count, out)?,
+            Command::Examine {
+                count,
+                format,
+                addr,
+            } => {
+                let size = format.size();
+                let Some(memory) = bus.get(addr..bus.len().min(addr + count * size)) else {
+                    writeln!(out, "Can't read memory at {addr:03x}")?;
+                    return Ok(true);
+                };
+                for (row, line) in memory.chunks(8 * size).enumerate() {
+                    write!(out, "{:03x}:", addr + row * 8 * size)?;
+                    for unit in line.chunks(size) {
+                        write!(out, " ")?;
+                        unit.iter().try_for_each(|byte| write!(out, "{byte:02x}"))?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+            Command::Poke { addr, data } => match bus.get_mut(addr..addr + data.len()) {
+                Some(memory) => {
+                    memory.copy_from_slice(&data);
+                    writeln!(out, "Wrote {} byte(s) at {addr:03x}", data.len())?;
+                }
+                None => writeln!(out, "Can't write memory at {addr:03x}")?,
+            },
+            Command::Disas { addr, count } => {
+                let start = addr.unwrap_or_else(|| (cpu.pc() as usize).saturating_sub(8));
+                listing(ch8, start, count.unwrap_or(9), out)?;
+            }
+            Command::Backtrace => {
+                write!(out, "#0  ")?;
+                location(ch8, out)?;
+                let (sp, top) = (ch8.cpu.sp(), stack_top(ch8).unwrap_or_default());
+                for (frame, slot) in (sp + 2..=top).step_by(2).enumerate() {
+                    let Some(&[hi, lo]) = ch8.bus.get(slot..slot + 2) else {
+                        break;
+                    };
+                    let call = u16::from_be_bytes([hi, lo]).wrapping_sub(2);
+                    writeln!(
+                        out,
+                        "#{}  {call:03x}: {}",
+                        frame + 1,
+                        disassemble(ch8, call as usize).1
+                    )?;
+                }
+            }
+            Command::Source(path) => self.source(path)?,
+            Command::History => {
+                for (index, line) in self.history.iter().enumerate() {
+                    writeln!(out, "{index:4}  {line}")?;
+                }
+            }
+            Command::Help => write!(out, "{HELP}")?,
+            Command::Quit => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    /// Runs `steps` instructions, then shows where the CPU is
+    fn step(&mut self, steps: usize, ch8: &mut Chip8, out: &mut impl Write) -> Result<()> {
+        for _ in 0..steps {
+            if let Err(e) = ch8.cpu.singlestep(&mut ch8.bus) {
+                return self.stopped(ch8, Some(e), out);
+            }
+        }
+        self.stopped(ch8, None, out)
+    }
+
+    /// Lets the CPU run until it comes back to `addr` with the stack pointer at `sp`
+    fn resume_at(&mut self, addr: u16, sp: usize, ch8: &mut Chip8) -> Result<()> {
+        let condition = format!("sp == {sp:#x}").parse()?;
+        let breakpoint = Breakpoint::new(addr).when(condition).once();
+        self.stepping = Some(ch8.cpu.add_breakpoint(breakpoint));
+        ch8.cpu.flags.pause = false;
+        Ok(())
+    }
+}
+
+/// Gets the address of the oldest return address on the stack, if there's a stack
+fn stack_top(ch8: &Chip8) -> Option<usize> {
+    ch8.bus.get_region_range(Region::Stack)?.end.checked_sub(2)
+}
+
+/// Disassembles the instruction at `addr`, without touching the bus' access checks
+///
+/// Returns the length of the instruction, and its disassembly.
+/// # Examples
+/// ```rust
+/// # use chirp::{*, debugger::disassemble};
+/// let ch8 = Chip8 {
+///     cpu: CPU::default(),
+///     bus: bus! { Program [0x200..0x1000] = &[0x60, 0x2a, 0xff, 0xff] },
+/// };
+/// assert_eq!((2, "mov    #2a, v0".to_string()), disassemble(&ch8, 0x200));
+/// assert_eq!((2, "inval  ffff".to_string()), disassemble(&ch8, 0x202));
+/// ```
+pub fn disassemble(ch8: &Chip8, addr: usize) -> (usize, String) {
+    let bus = &ch8.bus;
+    let bytes = bus.get(addr..bus.len().min(addr + 4)).unwrap_or_default();
+    match decode(bytes) {
+        Some((len, insn)) => (len, insn.to_string().trim_end().to_string()),
+        None => match bytes {
+            [hi, lo, ..] => (2, format!("inval  {hi:02x}{lo:02x}")),
+            _ => (2, "??".to_string()),
+        },
+    }
+}
+
+/// Parses a number, in decimal, or in hex with a `0x`, `#` or `$` prefix
+/// # Examples
+/// ```rust
+/// # use chirp::debugger::number;
+/// assert_eq!(Some(0x20a), number("0x20a"));
+/// assert_eq!(Some(0x20a), number("$20A"));
+/// assert_eq!(Some(522), number("522"));
+/// assert_eq!(None, number("v0"));
+/// ```
+pub fn number(s: &str) -> Option<usize> {
+    let s = s.trim().to_lowercase();
+    match ["0x", "#", "$"]
+        .into_iter()
+        .find_map(|prefix| s.strip_prefix(prefix))
+    {
+        Some(digits) => usize::from_str_radix(digits, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+/// Prints the next instruction
+fn location(ch8: &Chip8, out: &mut impl Write) -> Result<()> {
+    let pc = ch8.cpu.pc();
+    writeln!(out, "=> {pc:03x}: {}", disassemble(ch8, pc as usize).1)?;
+    Ok(())
+}
+
+/// Prints `count` instructions, starting at `addr`, and marks the pc
+fn listing(ch8: &Chip8, mut addr: usize, count: usize, out: &mut impl Write) -> Result<()> {
+    for _ in 0..count {
+        if addr >= ch8.bus.len() {
+            break;
+        }
+        let (len, insn) = disassemble(ch8, addr);
+        let marker = if addr == ch8.cpu.pc() as usize {
+            "=>"
+        } else {
+            "  "
+        };
+        writeln!(out, "{marker} {addr:03x}: {insn}")?;
+        addr += len;
+    }
+    Ok(())
+}
+
+/// A register which `set` can change
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Register {
+    V(usize),
+    I,
+    Pc,
+    Delay,
+    Sound,
+}
+
+/// How `x` prints memory
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Format {
+    Byte,
+    Half,
+    Insn,
+}
+
+impl Format {
+    /// Gets the size of one unit of memory, in bytes
+    fn size(&self) -> usize {
+        match self {
+            Format::Half => 2,
+            _ => 1,
+        }
+    }
+}
+
+/// A parsed debugger command
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Command {
+    Break {
+        addr: u16,
+        condition: Option<Condition>,
+        once: bool,
+    },
+    Watch {
+        range: Range<usize>,
+        watch: Watch,
+    },
+    Delete(Option<usize>),
+    Unwatch(usize),
+    Info,
+    Continue,
+    Interrupt,
+    Step(usize),
+    Next,
+    Finish,
+    Frame(usize),
+    Regs,
+    Set {
+        register: Register,
+        value: usize,
+    },
+    Examine {
+        count: usize,
+        format: Format,
+        addr: usize,
+    },
+    Poke {
+        addr: usize,
+        data: Vec<u8>,
+    },
+    Disas {
+        addr: Option<usize>,
+        count: Option<usize>,
+    },
+    Backtrace,
+    Source(String),
+    History,
+    Help,
+    Quit,
+}
+
+impl std::str::FromStr for Command {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let invalid = || Error::InvalidCommand {
+            command: s.to_string(),
+        };
+        let (name, rest) = s.trim().split_once(' ').unwrap_or((s.trim(), ""));
+        let args: Vec<_> = rest.split_whitespace().collect();
+        let num = |arg: Option<&&str>| arg.and_then(|arg| number(arg)).ok_or_else(invalid);
+        let optional =
+            |arg: Option<&&str>| arg.map(|arg| number(arg).ok_or_else(invalid)).transpose();
+        Ok(match (name, args.as_slice()) {
+            ("break" | "b" | "tbreak", [addr, ..]) => {
+                let condition = match rest.trim().split_once(char::is_whitespace) {
+                    Some((_, condition)) => match condition.trim().strip_prefix("if ") {
+                        Some(condition) => Some(condition.parse()?),
+                        None => return Err(invalid()),
+                    },
+                    None => None,
+                };
+                Command::Break {
+                    addr: u16::try_from(num(Some(addr))?).map_err(|_| invalid())?,
+                    condition,
+                    once: name == "tbreak",
+                }
+            }
+            ("watch" | "w", args) => {
+                let (watch, args) = match args {
+                    ["read", args @ ..] => (Watch::Read, args),
+                    ["write", args @ ..] => (Watch::Write, args),
+                    ["change", args @ ..] => (Watch::Change, args),
+                    args => (Watch::Change, args),
+                };
+                let (start, len) = match args {
+                    [addr] => (num(Some(addr))?, 1),
+                    [addr, len] => (num(Some(addr))?, num(Some(len))?),
+                    _ => return Err(invalid()),
+                };
+                Command::Watch {
+                    range: start..start.checked_add(len).ok_or_else(invalid)?,
+                    watch,
+                }
+            }
+            ("delete" | "d", [id]) => Command::Delete(Some(num(Some(id))?)),
+            ("delete" | "d", []) => Command::Delete(None),
+            ("unwatch", [id]) => Command::Unwatch(num(Some(id))?),
+            ("info" | "i", _) => Command::Info,
+            ("continue" | "c", []) => Command::Continue,
+            ("interrupt", []) => Command::Interrupt,
+            ("step" | "s", args) if args.len() < 2 => {
+                Command::Step(optional(args.first())?.unwrap_or(1))
+            }
+            ("next" | "n", []) => Command::Next,
+            ("finish" | "fin", []) => Command::Finish,
+            ("frame" | "f", args) if args.len() < 2 => {
+                Command::Frame(optional(args.first())?.unwrap_or(1))
+            }
+            ("regs" | "r", []) => Command::Regs,
+            ("set", [register, value]) => Command::Set {
+                register: match register.to_lowercase().as_str() {
+                    "i" => Register::I,
+                    "pc" => Register::Pc,
+                    "dt" => Register::Delay,
+                    "st" => Register::Sound,
+                    reg => match reg.strip_prefix('v').filter(|reg| reg.len() == 1) {
+                        Some(reg) => {
+                            Register::V(usize::from_str_radix(reg, 16).map_err(|_| invalid())?)
+                        }
+                        None => return Err(invalid()),
+                    },
+                },
+                value: num(Some(value))?,
+            },
+            (examine, [addr]) if examine == "x" || examine.starts_with("x/") => {
+                let spec = examine.strip_prefix("x/").unwrap_or("");
+                let digits = spec.trim_end_matches(char::is_alphabetic);
+                let format = match &spec[digits.len()..] {
+                    "" | "b" => Format::Byte,
+                    "h" => Format::Half,
+                    "i" => Format::Insn,
+                    _ => return Err(invalid()),
+                };
+                let count: usize = match digits {
+                    "" => 1,
+                    digits => digits.parse().map_err(|_| invalid())?,
+                };
+                let addr = num(Some(addr))?;
+                // The end of the range must fit, too
+                count
+                    .checked_mul(format.size())
+                    .and_then(|len| addr.checked_add(len))
+                    .ok_or_else(invalid)?;
+                Command::Examine {
+                    count,
+                    format,
+                    addr,
+                }
+            }
+            ("poke", [addr, data @ ..]) if !data.is_empty() => {
+                let addr = num(Some(addr))?;
+                addr.checked_add(data.len()).ok_or_else(invalid)?;
+                Command::Poke {
+                    addr,
+                    data: data
+                        .iter()
+                        .map(|byte| u8::try_from(num(Some(byte))?).map_err(|_| invalid()))
+                        .collect::<Result<_>>()?,
+                }
+            }
+            ("disas", args) if args.len() < 3 => Command::Disas {
+                addr: optional(args.first())?,
+                count: optional(args.get(1))?,
+            },
+            ("backtrace" | "bt", []) => Command::Backtrace,
+            ("source", [_, ..]) => Command::Source(rest.trim().to_string()),
+            ("history", []) => Command::History,
+            ("help" | "h" | "?", _) => Command::Help,
+            ("quit" | "q" | "exit", []) => Command::Quit,
+            _ => return Err(invalid()),
+        })
+    }
+}
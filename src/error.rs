@@ -5,7 +5,10 @@
 
 use std::ops::Range;
 
-use crate::bus::Region;
+use crate::{
+    bus::{Access, Region},
+    cpu::breakpoint::Trigger,
+};
 use thiserror::Error;
 
 /// Result type, equivalent to [std::result::Result]<T, [enum@Error]>
@@ -14,13 +17,15 @@ pub type Result<T> = std::result::Result<T, Error>;
 /// Error type for Chirp.
 #[derive(Debug, Error)]
 pub enum Error {
-    /// Represents a breakpoint being hit
-    #[error("Breakpoint hit: {addr:03x} ({next:04x})")]
+    /// Represents a breakpoint or watchpoint being hit
+    #[error("Hit {trigger}: {addr:03x} ({next:04x})")]
     BreakpointHit {
-        /// The address of the breakpoint
+        /// The address the CPU stopped at
         addr: u16,
         /// The instruction after the breakpoint
         next: u16,
+        /// What stopped the CPU
+        trigger: Trigger,
     },
     /// Represents an unimplemented operation
     #[error("Unrecognized opcode: {word:04x}")]
@@ -34,6 +39,24 @@ pub enum Error {
         /// The offending [Region]
         region: Region,
     },
+    /// Tried to access a [Region] in a way it doesn't permit, in strict mode
+    #[error("Illegal {access} at {addr:04x} in {region}")]
+    AccessViolation {
+        /// The kind of [Access] which was denied
+        access: Access,
+        /// The offending address
+        addr: usize,
+        /// The [Region] containing the offending address
+        region: Region,
+    },
+    /// Tried to access memory outside of every [Region], in strict mode
+    #[error("Illegal {access} at {addr:04x}, which is unmapped")]
+    UnmappedAccess {
+        /// The kind of [Access] which was denied
+        access: Access,
+        /// The offending address
+        addr: usize,
+    },
     /// Tried to fetch [Range] from bus, received nothing
     #[error("Invalid range {range:04x?} for bus")]
     InvalidBusRange {
@@ -58,6 +81,84 @@ pub enum Error {
         /// The string which failed to become a mode
         mode: String,
     },
+    /// Tried to call a subroutine with the stack already full
+    #[error("Stack overflow at {pc:03x}: {depth} calls deep")]
+    StackOverflow {
+        /// The address of the offending `2nnn`
+        pc: u16,
+        /// The number of return addresses on the stack
+        depth: usize,
+    },
+    /// Tried to return from a subroutine with the stack empty
+    #[error("Stack underflow at {pc:03x}: {depth} calls deep")]
+    StackUnderflow {
+        /// The address of the offending `00EE`
+        pc: u16,
+        /// The number of return addresses on the stack
+        depth: usize,
+    },
+    /// Tried to convert string into stack policy, but it did not match.
+    #[error("Invalid stack policy: {policy}")]
+    InvalidStackPolicy {
+        /// The string which failed to become a stack policy
+        policy: String,
+    },
+    /// Tried to convert string into platform, but it did not match.
+    #[error("Invalid platform: {platform}")]
+    InvalidPlatform {
+        /// The string which failed to become a platform
+        platform: String,
+    },
+    /// Tried to apply a quirk specification, but a quirk or its value did not match.
+    #[error("Invalid quirk: {quirk}")]
+    InvalidQuirk {
+        /// The part of the specification which failed to become a quirk
+        quirk: String,
+    },
+    /// Tried to convert string into font, but it did not match.
+    #[error("Invalid font: {font}")]
+    InvalidFont {
+        /// The string which failed to become a font
+        font: String,
+    },
+    /// Tried to convert string into waveform, but it did not match.
+    #[error("Invalid waveform: {waveform}")]
+    InvalidWaveform {
+        /// The string which failed to become a waveform
+        waveform: String,
+    },
+    /// Tried to convert string into a breakpoint condition, but it did not parse.
+    #[error("Invalid condition: {condition}")]
+    InvalidCondition {
+        /// The string which failed to become a condition
+        condition: String,
+    },
+    /// Tried to run a debugger command, but it did not parse.
+    #[error("Invalid command: {command} (try `help`)")]
+    InvalidCommand {
+        /// The line which failed to become a command
+        command: String,
+    },
+    /// Tried to convert string into a trace format, but it did not match.
+    #[error("Invalid trace format: {format}")]
+    InvalidTraceFormat {
+        /// The string which failed to become a trace format
+        format: String,
+    },
+    /// Tried to load a save state, but it was malformed
+    #[error("Invalid save state: {reason}")]
+    InvalidState {
+        /// What was wrong with the save state
+        reason: String,
+    },
+    /// Tried to load a save state written by a newer version of Chirp
+    #[error("Save state version {version} is newer than this build supports ({supported})")]
+    StateVersion {
+        /// The version of the save state
+        version: u16,
+        /// The newest version this build can load
+        supported: u16,
+    },
     /// Error originated in [std::io]
     #[error(transparent)]
     IoError(#[from] std::io::Error),
@@ -0,0 +1,514 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Lets gdb, or anything else which speaks the GDB Remote Serial Protocol, debug a [Chip8]
+//!
+//! Like the [Debugger](crate::debugger::Debugger), a [GdbStub] never runs the machine on
+//! its own. The frontend [polls](GdbStub::poll) it for packets, runs frames while the CPU
+//! isn't paused, and hands whatever stopped it to [GdbStub::stopped].
+//!
+//! Registers are numbered as follows, and sent in little-endian byte order:
+//!
+//! | number | register | size    |
+//! |--------|----------|---------|
+//! | 0..=15 | v0..=vF  | 8 bits  |
+//! | 16     | I        | 16 bits |
+//! | 17     | pc       | 16 bits |
+//! | 18     | sp       | 16 bits (read only) |
+//! | 19     | dt       | 8 bits  |
+//! | 20     | st       | 8 bits  |
+//!
+//! The same layout is described to the client by `target.xml`.
+//!
+//! Breakpoints (`Z0`/`Z1`) are [plain breakpoints](crate::cpu::CPU::set_break).
+//! Watchpoints (`Z2`/`Z3`/`Z4`) are [Watchpoints](crate::cpu::breakpoint::Watchpoint)
+//! on the bus. Memory is read and written without checking [Permissions](crate::bus::Permissions).
+
+use crate::{
+    cpu::breakpoint::{Trigger, Watch, Watchpoint},
+    error::{Error, Result},
+    Chip8,
+};
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs},
+};
+
+/// The number of registers, in the order of the `g` packet
+const REGISTERS: usize = 21;
+
+/// Describes the registers to the client
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+  <feature name="org.chirp.chip8">
+    <reg name="v0" bitsize="8" type="uint8" regnum="0"/>
+    <reg name="v1" bitsize="8" type="uint8"/>
+    <reg name="v2" bitsize="8" type="uint8"/>
+    <reg name="v3" bitsize="8" type="uint8"/>
+    <reg name="v4" bitsize="8" type="uint8"/>
+    <reg name="v5" bitsize="8" type="uint8"/>
+    <reg name="v6" bitsize="8" type="uint8"/>
+    <reg name="v7" bitsize="8" type="uint8"/>
+    <reg name="v8" bitsize="8" type="uint8"/>
+    <reg name="v9" bitsize="8" type="uint8"/>
+    <reg name="va" bitsize="8" type="uint8"/>
+    <reg name="vb" bitsize="8" type="uint8"/>
+    <reg name="vc" bitsize="8" type="uint8"/>
+    <reg name="vd" bitsize="8" type="uint8"/>
+    <reg name="ve" bitsize="8" type="uint8"/>
+    <reg name="vf" bitsize="8" type="uint8"/>
+    <reg name="i" bitsize="16" type="data_ptr"/>
+    <reg name="pc" bitsize="16" type="code_ptr"/>
+    <reg name="sp" bitsize="16" type="data_ptr"/>
+    <reg name="dt" bitsize="8" type="uint8"/>
+    <reg name="st" bitsize="8" type="uint8"/>
+  </feature>
+</target>
+"#;
+
+/// Serves one GDB client at a time
+#[derive(Debug)]
+pub struct GdbStub {
+    listener: TcpListener,
+    client: Option<TcpStream>,
+    // Bytes received, but not yet handled
+    buffer: Vec<u8>,
+    // Whether to acknowledge packets (until the client asks for no-ack mode)
+    ack: bool,
+    // Whether the client is waiting for the machine to stop
+    running: bool,
+    // The watchpoints set for each `Z` packet's type, address and length
+    watchpoints: HashMap<(u8, usize, usize), Vec<usize>>,
+}
+
+impl GdbStub {
+    /// Listens for a client on `addr`, like `127.0.0.1:1234`
+    pub fn bind(addr: impl ToSocketAddrs) -> Result<Self> {
+        let listener = TcpListener::bind(addr)?;
+        listener.set_nonblocking(true)?;
+        Ok(Self {
+            listener,
+            client: None,
+            buffer: vec![],
+            ack: true,
+            running: false,
+            watchpoints: HashMap::new(),
+        })
+    }
+
+    /// Gets the address the stub is listening on
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Returns true if a client is connected
+    pub fn is_connected(&self) -> bool {
+        self.client.is_some()
+    }
+
+    /// Accepts a client, and handles every packet received since the last call,
+    /// without waiting for more
+    ///
+    /// The CPU is paused when a client connects. Returns false when the client asks
+    /// to kill the machine.
+    pub fn poll(&mut self, ch8: &mut Chip8) -> Result<bool> {
+        if self.client.is_none() {
+            match self.listener.accept() {
+                Ok((client, _)) => {
+                    client.set_nodelay(true)?;
+                    self.client = Some(client);
+                    self.buffer.clear();
+                    self.ack = true;
+                    self.running = false;
+                    ch8.cpu.flags.pause = true;
+                }
+                Err(e) if e.kind() == ErrorKind::WouldBlock => return Ok(true),
+                Err(e) => return Err(e.into()),
+            }
+        }
+        if !self.receive()? {
+            self.disconnect();
+            return Ok(true);
+        }
+        while let Some(packet) = self.next_packet()? {
+            let reply = match packet {
+                // Ctrl-C
+                Packet::Interrupt => {
+                    ch8.cpu.flags.pause = true;
+                    match std::mem::take(&mut self.running) {
+                        true => Some("S02".to_string()),
+                        false => None,
+                    }
+                }
+                Packet::Data(data) => match data.as_str() {
+                    "k" => return Ok(false),
+                    "D" => {
+                        self.send("OK")?;
+                        ch8.cpu.flags.pause = false;
+                        self.disconnect();
+                        return Ok(true);
+                    }
+                    _ => self.handle(&data, ch8)?,
+                },
+            };
+            if let Some(reply) = reply {
+                self.send(&reply)?;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Tells the client that the machine stopped, if it was waiting for that
+    ///
+    /// Frontends call this whenever running the machine fails, or when it pauses by itself.
+    pub fn stopped(&mut self, ch8: &mut Chip8, error: Option<&Error>) -> Result<()> {
+        ch8.cpu.flags.pause = true;
+        if !std::mem::take(&mut self.running) {
+            return Ok(());
+        }
+        self.send(&stop_reply(error))
+    }
+
+    /// Handles one packet, and gets the reply, if there is one
+    fn handle(&mut self, data: &str, ch8: &mut Chip8) -> Result<Option<String>> {
+        // Every command gdb sends is ASCII, so anything else is malformed
+        if !data.is_ascii() {
+            return Ok(Some("E01".to_string()));
+        }
+        let (command, args) = data.split_at(data.len().min(1));
+        let reply = match command {
+            "?" => "S05".to_string(),
+            "g" => (0..REGISTERS).map(|reg| read_register(ch8, reg)).collect(),
+            "G" => {
+                let mut args = args;
+                for reg in 0..REGISTERS {
+                    let len = 2 * register_size(reg);
+                    let Some(value) = args.get(..len) else {
+                        return Ok(Some("E01".to_string()));
+                    };
+                    args = &args[len..];
+                    // The stack pointer can't be written, so it's skipped
+                    if reg != 18 {
+                        write_register(ch8, reg, value);
+                    }
+                }
+                "OK".to_string()
+            }
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REGISTERS => read_register(ch8, reg),
+                _ => "E01".to_string(),
+            },
+            "P" => match args.split_once('=').and_then(|(reg, value)| {
+                let reg = usize::from_str_radix(reg, 16).ok()?;
+                (reg < REGISTERS && reg != 18 && value.len() == 2 * register_size(reg))
+                    .then_some((reg, value))
+            }) {
+                Some((reg, value)) => {
+                    write_register(ch8, reg, value);
+                    "OK".to_string()
+                }
+                None => "E01".to_string(),
+            },
+            "m" => match parse_range(args).and_then(|range| ch8.bus.get(range)) {
+                Some(memory) => hex(memory),
+                None => "E01".to_string(),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let data = unhex(data)?;
+                    let memory = ch8.bus.get_mut(parse_range(range)?)?;
+                    (memory.len() == data.len()).then(|| memory.copy_from_slice(&data))
+                });
+                match written {
+                    Some(()) => "OK".to_string(),
+                    None => "E01".to_string(),
+                }
+            }
+            "c" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    ch8.cpu.set_pc(addr);
+                }
+                ch8.cpu.flags.pause = false;
+                self.running = true;
+                return Ok(None);
+            }
+            "s" => {
+                if let Ok(addr) = u16::from_str_radix(args, 16) {
+                    ch8.cpu.set_pc(addr);
+                }
+                match ch8.cpu.singlestep(&mut ch8.bus) {
+                    Ok(_) => "S05".to_string(),
+                    Err(e) => stop_reply(Some(&e)),
+                }
+            }
+            "Z" | "z" => self.breakpoint(command == "Z", args, ch8),
+            "H" => "OK".to_string(),
+            "T" => "OK".to_string(),
+            _ => match data {
+                "qAttached" => "1".to_string(),
+                "qC" => "QC1".to_string(),
+                "qfThreadInfo" => "m1".to_string(),
+                "qsThreadInfo" => "l".to_string(),
+                "QStartNoAckMode" => {
+                    self.send("OK")?;
+                    self.ack = false;
+                    return Ok(None);
+                }
+                data if data.starts_with("qSupported") => {
+                    "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string()
+                }
+                data if data.starts_with("qXfer:features:read:target.xml:") => {
+                    let args = &data["qXfer:features:read:target.xml:".len()..];
+                    match parse_range(args) {
+                        Some(range) => {
+                            let start = range.start.min(TARGET_XML.len());
+                            let end = range.end.min(TARGET_XML.len());
+                            let more = if end < TARGET_XML.len() { 'm' } else { 'l' };
+                            format!("{more}{}", &TARGET_XML[start..end])
+                        }
+                        None => "E01".to_string(),
+                    }
+                }
+                // Anything else isn't supported
+                _ => String::new(),
+            },
+        };
+        Ok(Some(reply))
+    }
+
+    /// Handles a `Z` (insert) or `z` (remove) packet, like `Z0,204,2`
+    fn breakpoint(&mut self, insert: bool, args: &str, ch8: &mut Chip8) -> String {
+        let mut args = args.split(',');
+        let (Some(kind), Some(addr), Some(len)) = (args.next(), args.next(), args.next()) else {
+            return "E01".to_string();
+        };
+        let (Ok(kind), Ok(addr), Ok(len)) = (
+            kind.parse::<u8>(),
+            usize::from_str_radix(addr, 16),
+            usize::from_str_radix(len, 16),
+        ) else {
+            return "E01".to_string();
+        };
+        let watches: &[Watch] = match kind {
+            // Software and hardware breakpoints are the same thing here
+            0 | 1 => {
+                let Ok(addr) = u16::try_from(addr) else {
+                    return "E01".to_string();
+                };
+                match insert {
+                    true => ch8.cpu.set_break(addr),
+                    false => ch8.cpu.unset_break(addr),
+                };
+                return "OK".to_string();
+            }
+            2 => &[Watch::Write],
+            3 => &[Watch::Read],
+            4 => &[Watch::Read, Watch::Write],
+            _ => return String::new(),
+        };
+        let key = (kind, addr, len.max(1));
+        let Some(end) = addr.checked_add(key.2) else {
+            return "E01".to_string();
+        };
+        if insert {
+            let ids = watches
+                .iter()
+                .map(|&watch| {
+                    ch8.cpu
+                        .add_watchpoint(&mut ch8.bus, Watchpoint::new(addr..end, watch))
+                })
+                .collect::<Vec<_>>();
+            self.watchpoints.entry(key).or_default().extend(ids);
+        } else {
+            for id in self.watchpoints.remove(&key).unwrap_or_default() {
+                ch8.bus.remove_watchpoint(id);
+            }
+        }
+        "OK".to_string()
+    }
+
+    /// Reads whatever the client has sent. Returns false if the client hung up.
+    fn receive(&mut self) -> Result<bool> {
+        let Some(client) = &mut self.client else {
+            return Ok(true);
+        };
+        client.set_nonblocking(true)?;
+        let mut chunk = [0; 1024];
+        let connected = loop {
+            match client.read(&mut chunk) {
+                Ok(0) => break false,
+                Ok(len) => self.buffer.extend_from_slice(&chunk[..len]),
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break true,
+                Err(e) if e.kind() == ErrorKind::Interrupted => {}
+                Err(_) => break false,
+            }
+        };
+        // Replies are small, so they're sent without waiting on the event loop
+        if connected {
+            client.set_nonblocking(false)?;
+        }
+        Ok(connected)
+    }
+
+    /// Takes the next whole packet out of the buffer, acknowledging it
+    fn next_packet(&mut self) -> Result<Option<Packet>> {
+        loop {
+            match self.buffer.first() {
+                None => return Ok(None),
+                Some(0x03) => {
+                    self.buffer.remove(0);
+                    return Ok(Some(Packet::Interrupt));
+                }
+                Some(b'$') => break,
+                // Acknowledgements, and noise between packets
+                Some(_) => {
+                    self.buffer.remove(0);
+                }
+            }
+        }
+        let Some(end) = self.buffer.iter().position(|&byte| byte == b'#') else {
+            return Ok(None);
+        };
+        if self.buffer.len() < end + 3 {
+            return Ok(None);
+        }
+        let packet: Vec<u8> = self.buffer.drain(..end + 3).collect();
+        let data = &packet[1..end];
+        let checksum = std::str::from_utf8(&packet[end + 1..])
+            .ok()
+            .and_then(|sum| u8::from_str_radix(sum, 16).ok());
+        if self.ack {
+            let valid = checksum == Some(sum(data));
+            self.write(if valid { b"+" } else { b"-" })?;
+            if !valid {
+                return self.next_packet();
+            }
+        }
+        Ok(Some(Packet::Data(unescape(data))))
+    }
+
+    /// Sends a reply packet
+    fn send(&mut self, data: &str) -> Result<()> {
+        let packet = format!("${data}#{:02x}", sum(data.as_bytes()));
+        self.write(packet.as_bytes())
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        if let Some(client) = &mut self.client {
+            if client.write_all(bytes).is_err() {
+                self.disconnect();
+            }
+        }
+        Ok(())
+    }
+
+    fn disconnect(&mut self) {
+        self.client = None;
+        self.running = false;
+    }
+}
+
+/// Something received from the client
+enum Packet {
+    Interrupt,
+    Data(String),
+}
+
+/// Describes why the machine stopped, as a stop reply packet
+fn stop_reply(error: Option<&Error>) -> String {
+    match error {
+        Some(Error::BreakpointHit {
+            trigger: Trigger::Watchpoint { watch, addr, .. },
+            ..
+        }) => {
+            let kind = match watch {
+                Watch::Read => "rwatch",
+                Watch::Write | Watch::Change => "watch",
+            };
+            format!("T05{kind}:{addr:x};")
+        }
+        None | Some(Error::BreakpointHit { .. }) => "S05".to_string(),
+        // SIGILL
+        Some(Error::UnimplementedInstruction { .. }) => "S04".to_string(),
+        // SIGSEGV
+        Some(Error::AccessViolation { .. } | Error::InvalidBusRange { .. }) => "S0b".to_string(),
+        // SIGABRT
+        Some(_) => "S06".to_string(),
+    }
+}
+
+/// Gets the size of a register, in bytes
+fn register_size(reg: usize) -> usize {
+    match reg {
+        16..=18 => 2,
+        _ => 1,
+    }
+}
+
+/// Reads a register, as little-endian hex
+fn read_register(ch8: &Chip8, reg: usize) -> String {
+    let cpu = &ch8.cpu;
+    match reg {
+        0..=15 => hex(&[cpu.v()[reg]]),
+        16 => hex(&cpu.i().to_le_bytes()),
+        17 => hex(&cpu.pc().to_le_bytes()),
+        18 => hex(&(cpu.sp() as u16).to_le_bytes()),
+        19 => hex(&[cpu.delay()]),
+        _ => hex(&[cpu.sound()]),
+    }
+}
+
+/// Writes a register from little-endian hex, which must be the right length
+fn write_register(ch8: &mut Chip8, reg: usize, value: &str) {
+    let Some(bytes) = unhex(value) else {
+        return;
+    };
+    let cpu = &mut ch8.cpu;
+    let word = || u16::from_le_bytes([bytes[0], bytes.get(1).copied().unwrap_or_default()]);
+    match reg {
+        0..=15 => _ = cpu.set_v(reg, bytes[0]),
+        16 => _ = cpu.set_i(word()),
+        17 => _ = cpu.set_pc(word()),
+        19 => _ = cpu.set_delay(bytes[0]),
+        20 => _ = cpu.set_sound(bytes[0]),
+        _ => {}
+    }
+}
+
+/// Parses an `addr,length` pair, in hex
+fn parse_range(args: &str) -> Option<std::ops::Range<usize>> {
+    let (addr, len) = args.split_once(',')?;
+    let addr = usize::from_str_radix(addr, 16).ok()?;
+    Some(addr..addr.checked_add(usize::from_str_radix(len, 16).ok()?)?)
+}
+
+/// Computes the checksum of a packet
+fn sum(data: &[u8]) -> u8 {
+    data.iter().fold(0, |sum, &byte| sum.wrapping_add(byte))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn unhex(hex: &str) -> Option<Vec<u8>> {
+    (0..hex.len())
+        .step_by(2)
+        .map(|at| u8::from_str_radix(hex.get(at..at + 2)?, 16).ok())
+        .collect()
+}
+
+/// Undoes the escaping of `#`, `$`, `}` and `*` in packets
+fn unescape(data: &[u8]) -> String {
+    let mut bytes = data.iter();
+    let mut out = vec![];
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'}' => out.extend(bytes.next().map(|byte| byte ^ 0x20)),
+            byte => out.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
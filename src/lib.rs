@@ -8,23 +8,42 @@
 //!
 //! Hopefully, though, you'll find some use in it.
 
+pub mod audio;
 pub mod bus;
+pub mod clock;
 pub mod cpu;
+pub mod debugger;
 pub mod error;
+pub mod gdb;
+pub mod rewind;
+pub mod rpl;
+pub mod sanitizer;
+pub mod state;
+pub mod trace;
 
 // Common imports for Chirp
 pub use bus::{Bus, Read, Region::*, Write};
 pub use cpu::{
     disassembler::{Dis, Disassembler},
     flags::Flags,
+    font::BigFont,
+    memory_map::MemoryMap,
     mode::Mode,
+    platform::Platform,
     quirks::Quirks,
     CPU,
 };
 pub use error::{Error, Result};
 
 /// Holds the state of a Chip-8
+///
+/// With the `serde` feature, the whole machine can be serialized, including its
+/// breakpoints and watchpoints. The disassembler, [Tracer](trace::Tracer) and
+/// [Sanitizer](sanitizer::Sanitizer) aren't serialized, so a deserialized machine has
+/// none, and the [Rng](cpu::rng::Rng) is restored as a [Xorshift](cpu::rng::Xorshift)
+/// with the same state.
 #[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Chip8 {
     /// Contains the registers, flags, and operating state for a single Chip-8
     pub cpu: cpu::CPU,
@@ -0,0 +1,196 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Steps a [Chip8] backwards, by keeping a bounded history of [save states](crate::state)
+//!
+//! A frontend [records](Rewind::record) the machine before each frame it runs, and before
+//! each single-step. Going back a [frame](Rewind::frame_back) restores the newest recording.
+//! Going back one [instruction](Rewind::step_back) restores the recording made before it,
+//! then runs forward again, one instruction at a time, to just before where it was.
+//!
+//! Replaying is exact, since the [CPU](crate::cpu::CPU) never reads the wall clock, and the
+//! state of its random number generator is part of each recording. Keys pressed between
+//! recordings can't be replayed, which is why frontends record between handling input and
+//! running the CPU.
+//!
+//! The [Sanitizer](crate::sanitizer::Sanitizer), if there is one, isn't rewound.
+
+use crate::{error::Result, Chip8};
+use std::collections::VecDeque;
+
+/// Limits how much history a [Rewind] keeps. The oldest recordings are dropped first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Budget {
+    /// Keeps at most this many recordings (about one per frame)
+    Frames(usize),
+    /// Keeps at most this many bytes of recordings
+    Bytes(usize),
+}
+
+impl Default for Budget {
+    /// Keeps ten seconds of history, at 60 frames per second
+    fn default() -> Self {
+        Budget::Frames(600)
+    }
+}
+
+/// A recording of the machine, and the [cycle](crate::cpu::CPU::cycle) it was made on
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Recording {
+    cycle: usize,
+    state: Vec<u8>,
+}
+
+/// Holds the execution history of a [Chip8]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Rewind {
+    history: VecDeque<Recording>,
+    budget: Budget,
+    // The total size of the recorded states, in bytes
+    size: usize,
+}
+
+impl Rewind {
+    /// Constructs an empty [Rewind], which keeps up to `budget` worth of history
+    pub fn new(budget: Budget) -> Self {
+        Self {
+            budget,
+            ..Default::default()
+        }
+    }
+
+    /// Gets the number of recordings in the history
+    pub fn len(&self) -> usize {
+        self.history.len()
+    }
+
+    /// Returns true if there's nothing to rewind to
+    pub fn is_empty(&self) -> bool {
+        self.history.is_empty()
+    }
+
+    /// Forgets the whole history
+    ///
+    /// Call this when the machine is changed in a way which can't be replayed,
+    /// like a reset, or loading a save state.
+    pub fn clear(&mut self) {
+        self.history.clear();
+        self.size = 0;
+    }
+
+    /// Records the machine, before running it
+    ///
+    /// A recording made on the same cycle as the last one replaces it.
+    /// If the machine has gone back in time (like after loading a save state), the history
+    /// starts over.
+    pub fn record(&mut self, ch8: &Chip8) {
+        let cycle = ch8.cpu.cycle();
+        match self.history.back() {
+            Some(last) if last.cycle == cycle => self.pop(),
+            Some(last) if last.cycle > cycle => self.clear(),
+            _ => {}
+        }
+        let state = ch8.save_state();
+        self.size += state.len();
+        self.history.push_back(Recording { cycle, state });
+        while match self.budget {
+            Budget::Frames(frames) => self.history.len() > frames,
+            Budget::Bytes(bytes) => self.size > bytes,
+        } {
+            if let Some(oldest) = self.history.pop_front() {
+                self.size -= oldest.state.len();
+            }
+        }
+    }
+
+    /// Rewinds the machine to the last recording made before the current cycle
+    ///
+    /// Returns false, leaving the machine untouched, if there's nothing to rewind to.
+    /// Breakpoints and watchpoints don't stop the replay, and don't count its hits.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, rewind::*};
+    /// # fn main() -> Result<()> {
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x70, 0x01, 0x12, 0x00] },
+    /// };
+    /// let mut rewind = Rewind::default();
+    /// rewind.record(&ch8);
+    /// ch8.cpu.run_frame(&mut ch8.bus)?;
+    /// assert_eq!(8, ch8.cpu.cycle());
+    /// assert!(rewind.frame_back(&mut ch8)?);
+    /// assert_eq!(0, ch8.cpu.cycle());
+    /// assert!(!rewind.frame_back(&mut ch8)?);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn frame_back(&mut self, ch8: &mut Chip8) -> Result<bool> {
+        let cycle = ch8.cpu.cycle();
+        while self.history.back().is_some_and(|last| last.cycle >= cycle) {
+            self.pop();
+        }
+        match self.history.back() {
+            Some(last) => ch8.load_state(&last.state).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// Rewinds the machine by one instruction
+    ///
+    /// Returns false, leaving the machine untouched, if there's nothing to rewind to.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, rewind::*};
+    /// # fn main() -> Result<()> {
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x70, 0x01, 0x12, 0x00] },
+    /// };
+    /// let mut rewind = Rewind::default();
+    /// rewind.record(&ch8);
+    /// ch8.cpu.run_frame(&mut ch8.bus)?;
+    /// assert_eq!(4, ch8.cpu.v()[0]);
+    /// // Undo the `jmp`, then the `add`
+    /// assert!(rewind.step_back(&mut ch8)?);
+    /// assert!(rewind.step_back(&mut ch8)?);
+    /// assert_eq!(6, ch8.cpu.cycle());
+    /// assert_eq!(3, ch8.cpu.v()[0]);
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn step_back(&mut self, ch8: &mut Chip8) -> Result<bool> {
+        let Some(target) = ch8.cpu.cycle().checked_sub(1) else {
+            return Ok(false);
+        };
+        while self.history.back().is_some_and(|last| last.cycle > target) {
+            self.pop();
+        }
+        let Some(last) = self.history.back() else {
+            return Ok(false);
+        };
+        ch8.load_state(&last.state)?;
+        // Replay up to the target, without printing the disassembly again, and without
+        // stopping at (or counting hits on) any breakpoints or watchpoints
+        let debug = std::mem::replace(&mut ch8.cpu.flags.debug, false);
+        let points = ch8.cpu.take_points(&mut ch8.bus);
+        let mut replay = Ok(());
+        while ch8.cpu.cycle() < target && !ch8.cpu.flags.pause && replay.is_ok() {
+            replay = ch8.cpu.multistep(&mut ch8.bus, 1).map(|_| ());
+        }
+        if replay.is_err() {
+            // Don't leave the machine partway through the replay
+            ch8.load_state(&last.state)?;
+        }
+        points.restore(&mut ch8.cpu, &mut ch8.bus);
+        ch8.cpu.flags.debug = debug;
+        replay.map(|_| true)
+    }
+
+    /// Drops the newest recording
+    fn pop(&mut self) {
+        if let Some(newest) = self.history.pop_back() {
+            self.size -= newest.state.len();
+        }
+    }
+}
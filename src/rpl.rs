@@ -0,0 +1,77 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Persists the Super-Chip RPL flag registers between runs
+//!
+//! Each ROM's flags are kept in their own file, named after a hash of the ROM,
+//! so games can keep their high scores without stepping on each other.
+
+use crate::error::Result;
+use std::{
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+/// Hashes a ROM with 64-bit FNV-1a, which is stable across builds and platforms
+/// # Examples
+/// ```rust
+/// # use chirp::rpl::rom_hash;
+/// assert_eq!(0xcbf29ce484222325, rom_hash(&[]));
+/// assert_ne!(rom_hash(b"\x12\x00"), rom_hash(b"\x00\x12"));
+/// ```
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    const OFFSET: u64 = 0xcbf29ce484222325;
+    const PRIME: u64 = 0x100000001b3;
+    rom.iter().fold(OFFSET, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
+
+/// Gets the path of the file which holds the flags for `rom`, inside `dir`
+/// # Examples
+/// ```rust
+/// # use chirp::rpl::path;
+/// assert_eq!(
+///     std::path::Path::new("flags/cbf29ce484222325.rpl"),
+///     path("flags", &[]),
+/// );
+/// ```
+pub fn path(dir: impl AsRef<Path>, rom: &[u8]) -> PathBuf {
+    dir.as_ref().join(format!("{:016x}.rpl", rom_hash(rom)))
+}
+
+/// Gets the default directory for RPL flag files
+///
+/// This is `$XDG_DATA_HOME/chirp/rpl`, falling back to `~/.local/share/chirp/rpl`,
+/// or `rpl` in the working directory if neither is set.
+pub fn default_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".local/share")))
+        .map(|data| data.join("chirp"))
+        .unwrap_or_default()
+        .join("rpl")
+}
+
+/// Loads the flags for `rom` from `dir`
+///
+/// If no flags have been saved for `rom`, they all start at zero.
+pub fn load(dir: impl AsRef<Path>, rom: &[u8]) -> Result<[u8; 16]> {
+    let mut rpl = [0; 16];
+    match std::fs::read(path(dir, rom)) {
+        Ok(data) => {
+            let len = data.len().min(rpl.len());
+            rpl[..len].copy_from_slice(&data[..len]);
+            Ok(rpl)
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(rpl),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Saves the flags for `rom` to `dir`, creating `dir` if it doesn't exist
+pub fn save(dir: impl AsRef<Path>, rom: &[u8], rpl: &[u8; 16]) -> Result<()> {
+    std::fs::create_dir_all(&dir)?;
+    std::fs::write(path(dir, rom), rpl)?;
+    Ok(())
+}
@@ -0,0 +1,194 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Shadows [Bus](crate::bus::Bus) memory with metadata, to find bugs in ROMs
+//!
+//! When a [Sanitizer] is attached to the bus with [Bus::sanitize](crate::bus::Bus::sanitize),
+//! every byte remembers whether it's been loaded or written, and whether it's been executed.
+//! The sanitizer then reports:
+//! - Reads and execution of bytes which were never loaded or written
+//! - Writes to bytes which have already been executed (self-modifying code)
+//! - I pointing past the end of memory during `Dxyn`, `Fx55` and `Fx65`
+//!
+//! Each [Finding] is reported once per instruction, along with the pc and cycle
+//! where it was first seen.
+
+use std::{
+    collections::HashSet,
+    fmt::{Display, Formatter},
+    ops::Range,
+};
+
+/// Set when a byte has been loaded or written
+const INIT: u8 = 1 << 0;
+/// Set when a byte has been fetched as part of an instruction
+const EXEC: u8 = 1 << 1;
+
+/// Something suspicious a program did
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Finding {
+    /// Read a byte which was never loaded or written
+    UninitRead {
+        /// The address of the byte
+        addr: usize,
+    },
+    /// Executed a byte which was never loaded or written
+    UninitExecute {
+        /// The address of the byte
+        addr: usize,
+    },
+    /// Wrote to a byte which had already been executed
+    SelfModifying {
+        /// The address of the byte
+        addr: usize,
+    },
+    /// Accessed `len` bytes at I, running past the end of memory
+    IndexOutOfBounds {
+        /// The value of I
+        i: u16,
+        /// The number of bytes accessed
+        len: usize,
+        /// The end of memory
+        end: usize,
+    },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Finding::UninitRead { addr } => write!(f, "read of uninitialized memory at {addr:04x}"),
+            Finding::UninitExecute { addr } => {
+                write!(f, "execution of uninitialized memory at {addr:04x}")
+            }
+            Finding::SelfModifying { addr } => write!(f, "write to executed code at {addr:04x}"),
+            Finding::IndexOutOfBounds { i, len, end } => {
+                write!(
+                    f,
+                    "{len} bytes at I ({i:04x}) run past the end of memory ({end:04x})"
+                )
+            }
+        }
+    }
+}
+
+/// A [Finding], and where it was first seen
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Report {
+    /// The address of the offending instruction
+    pub pc: u16,
+    /// The cycle on which the finding was first seen
+    pub cycle: usize,
+    /// What the instruction did
+    pub finding: Finding,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:>8} {:03x}: {}", self.cycle, self.pc, self.finding)
+    }
+}
+
+/// Tracks the state of every byte of memory, and collects [Report]s
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Sanitizer {
+    shadow: Vec<u8>,
+    pc: u16,
+    cycle: usize,
+    reports: Vec<Report>,
+    seen: HashSet<(u16, Finding)>,
+}
+
+impl Sanitizer {
+    /// Constructs a new [Sanitizer], in which no memory is initialized
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gets the reports collected so far, in the order they were first seen
+    pub fn reports(&self) -> &[Report] {
+        &self.reports
+    }
+
+    /// Sets the pc and cycle of the instruction being executed, for future reports
+    pub fn set_context(&mut self, pc: u16, cycle: usize) {
+        (self.pc, self.cycle) = (pc, cycle);
+    }
+
+    /// Marks the bytes in `range` as initialized, without checking them
+    /// # Examples
+    /// ```rust
+    /// # use chirp::sanitizer::Sanitizer;
+    /// let mut sanitizer = Sanitizer::new();
+    /// sanitizer.load(0x200..0x202);
+    /// sanitizer.read(0x200..0x203);
+    /// assert_eq!(1, sanitizer.reports().len());
+    /// ```
+    pub fn load(&mut self, range: Range<usize>) {
+        for addr in range {
+            *self.shadow_mut(addr) |= INIT;
+        }
+    }
+
+    /// Checks that the bytes in `range` have been initialized, before they're read
+    pub fn read(&mut self, range: Range<usize>) {
+        for addr in range {
+            if self.shadow(addr) & INIT == 0 {
+                self.report(Finding::UninitRead { addr });
+            }
+        }
+    }
+
+    /// Checks that the bytes in `range` have been initialized, and marks them as executed
+    pub fn execute(&mut self, range: Range<usize>) {
+        for addr in range {
+            if self.shadow(addr) & INIT == 0 {
+                self.report(Finding::UninitExecute { addr });
+            }
+            *self.shadow_mut(addr) |= EXEC;
+        }
+    }
+
+    /// Checks that the bytes in `range` haven't been executed, and marks them as initialized
+    /// # Examples
+    /// ```rust
+    /// # use chirp::sanitizer::{Finding, Sanitizer};
+    /// let mut sanitizer = Sanitizer::new();
+    /// sanitizer.load(0x200..0x202);
+    /// sanitizer.execute(0x200..0x202);
+    /// sanitizer.write(0x201..0x202);
+    /// assert_eq!(
+    ///     Finding::SelfModifying { addr: 0x201 },
+    ///     sanitizer.reports()[0].finding
+    /// );
+    /// ```
+    pub fn write(&mut self, range: Range<usize>) {
+        for addr in range {
+            if self.shadow(addr) & EXEC != 0 {
+                self.report(Finding::SelfModifying { addr });
+            }
+            *self.shadow_mut(addr) |= INIT;
+        }
+    }
+
+    /// Reports a [Finding] at the current pc, unless it's already been reported there
+    pub fn report(&mut self, finding: Finding) {
+        if self.seen.insert((self.pc, finding)) {
+            self.reports.push(Report {
+                pc: self.pc,
+                cycle: self.cycle,
+                finding,
+            });
+        }
+    }
+
+    fn shadow(&self, addr: usize) -> u8 {
+        self.shadow.get(addr).copied().unwrap_or_default()
+    }
+
+    fn shadow_mut(&mut self, addr: usize) -> &mut u8 {
+        if addr >= self.shadow.len() {
+            self.shadow.resize(addr + 1, 0);
+        }
+        &mut self.shadow[addr]
+    }
+}
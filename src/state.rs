@@ -0,0 +1,226 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Saves and restores the whole [Chip8], in a compact, versioned binary format
+//!
+//! A save state starts with [MAGIC] and the [VERSION] of the format, followed by
+//! the [CPU](crate::cpu::CPU) (registers, timers, flags, quirks, keys, RNG state and
+//! breakpoints), then the [Bus](crate::bus::Bus) (memory, region map, permissions and
+//! watchpoints).
+//! All numbers are little-endian.
+//!
+//! States written by older versions of the format can still be loaded.
+//! States from newer versions are rejected with [Error::StateVersion].
+
+use crate::{
+    error::{Error, Result},
+    rpl, Chip8,
+};
+use std::path::{Path, PathBuf};
+
+/// Identifies a file as a Chirp save state
+pub const MAGIC: &[u8; 8] = b"chirpsav";
+/// The newest version of the save state format. Bump this whenever the format changes!
+pub const VERSION: u16 = 1;
+
+impl Chip8 {
+    /// Saves the whole machine into a save state
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x60, 0x2a] },
+    /// };
+    /// let state = ch8.save_state();
+    /// ch8.cpu.multistep(&mut ch8.bus, 1).unwrap();
+    /// assert_eq!(0x2a, ch8.cpu.v()[0]);
+    /// ch8.load_state(&state).unwrap();
+    /// assert_eq!(0x00, ch8.cpu.v()[0]);
+    /// ```
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = Encoder::new();
+        self.cpu.save_state(&mut state);
+        self.bus.save_state(&mut state);
+        state.data
+    }
+
+    /// Restores the whole machine from a save state
+    ///
+    /// If the state can't be loaded, the machine is left untouched.
+    /// The [Rng](crate::cpu::rng::Rng) implementation and the disassembler are kept,
+    /// as is the [Sanitizer](crate::sanitizer::Sanitizer), if there is one.
+    /// # Examples
+    /// ```rust
+    /// # use chirp::*;
+    /// let mut ch8 = Chip8::default();
+    /// assert!(ch8.load_state(b"not a save state").is_err());
+    /// ```
+    pub fn load_state(&mut self, state: &[u8]) -> Result<()> {
+        let mut state = Decoder::new(state)?;
+        let cpu = self.cpu.load_state(&mut state)?;
+        let bus = self.bus.load_state(&mut state)?;
+        state.finish()?;
+        (self.cpu, self.bus) = (cpu, bus);
+        Ok(())
+    }
+}
+
+/// Gets the path of the file which holds save `slot` for `rom`, inside `dir`
+/// # Examples
+/// ```rust
+/// # use chirp::state::path;
+/// assert_eq!(
+///     std::path::Path::new("states/cbf29ce484222325.3.state"),
+///     path("states", &[], 3),
+/// );
+/// ```
+pub fn path(dir: impl AsRef<Path>, rom: &[u8], slot: usize) -> PathBuf {
+    dir.as_ref()
+        .join(format!("{:016x}.{slot}.state", rpl::rom_hash(rom)))
+}
+
+/// Gets the default directory for save states, next to the [RPL flags](rpl::default_dir)
+pub fn default_dir() -> PathBuf {
+    rpl::default_dir().with_file_name("states")
+}
+
+/// Writes the fields of a save state
+#[derive(Clone, Debug)]
+pub(crate) struct Encoder {
+    data: Vec<u8>,
+}
+
+impl Encoder {
+    /// Starts a new save state, with the current [VERSION]
+    fn new() -> Self {
+        let mut encoder = Self {
+            data: MAGIC.to_vec(),
+        };
+        encoder.u16(VERSION);
+        encoder
+    }
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.u8(value as u8)
+    }
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.data.push(value)
+    }
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.data.extend(value.to_le_bytes())
+    }
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.data.extend(value.to_le_bytes())
+    }
+    pub(crate) fn usize(&mut self, value: usize) {
+        self.u64(value as u64)
+    }
+    pub(crate) fn f64(&mut self, value: f64) {
+        self.data.extend(value.to_le_bytes())
+    }
+    /// Writes a length, followed by the bytes themselves
+    pub(crate) fn bytes(&mut self, value: &[u8]) {
+        self.usize(value.len());
+        self.data.extend(value)
+    }
+    /// Writes whether `value` is present, followed by the value
+    pub(crate) fn option<T>(&mut self, value: Option<T>, f: impl FnOnce(&mut Self, T)) {
+        self.bool(value.is_some());
+        if let Some(value) = value {
+            f(self, value)
+        }
+    }
+}
+
+/// Reads back the fields written by an [Encoder]
+#[derive(Clone, Debug)]
+pub(crate) struct Decoder<'a> {
+    data: &'a [u8],
+    /// The version of the format the state was written in
+    pub(crate) version: u16,
+}
+
+impl<'a> Decoder<'a> {
+    /// Checks the header of a save state, and starts reading it
+    fn new(data: &'a [u8]) -> Result<Self> {
+        let Some(data) = data.strip_prefix(MAGIC) else {
+            return Err(invalid("not a save state"));
+        };
+        let mut decoder = Self { data, version: 0 };
+        decoder.version = decoder.u16()?;
+        match decoder.version {
+            1..=VERSION => Ok(decoder),
+            version => Err(Error::StateVersion {
+                version,
+                supported: VERSION,
+            }),
+        }
+    }
+    /// Checks that the whole state has been read
+    fn finish(self) -> Result<()> {
+        match self.data.is_empty() {
+            true => Ok(()),
+            false => Err(invalid("trailing data")),
+        }
+    }
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        if self.data.len() < N {
+            return Err(invalid("unexpected end of state"));
+        }
+        let (value, rest) = self.data.split_at(N);
+        self.data = rest;
+        Ok(value.try_into()?)
+    }
+    pub(crate) fn bool(&mut self) -> Result<bool> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid("bad boolean")),
+        }
+    }
+    pub(crate) fn u8(&mut self) -> Result<u8> {
+        Ok(self.take::<1>()?[0])
+    }
+    pub(crate) fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn usize(&mut self) -> Result<usize> {
+        usize::try_from(self.u64()?).map_err(|_| invalid("number too large"))
+    }
+    pub(crate) fn f64(&mut self) -> Result<f64> {
+        Ok(f64::from_le_bytes(self.take()?))
+    }
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8]> {
+        let len = self.usize()?;
+        if self.data.len() < len {
+            return Err(invalid("unexpected end of state"));
+        }
+        let (value, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(value)
+    }
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        self.bytes()?
+            .try_into()
+            .map_err(|_| invalid("wrong array length"))
+    }
+    pub(crate) fn option<T>(
+        &mut self,
+        f: impl FnOnce(&mut Self) -> Result<T>,
+    ) -> Result<Option<T>> {
+        match self.bool()? {
+            true => f(self).map(Some),
+            false => Ok(None),
+        }
+    }
+}
+
+/// Constructs an [Error::InvalidState]
+pub(crate) fn invalid(reason: &str) -> Error {
+    Error::InvalidState {
+        reason: reason.to_string(),
+    }
+}
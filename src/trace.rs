@@ -0,0 +1,317 @@
+// (c) 2023 John A. Breaux
+// This code is licensed under MIT license (see LICENSE.txt for details)
+
+//! Writes one machine-readable [Record] per instruction, as plain text or JSON lines
+//!
+//! A [Tracer] is attached to the CPU with [CPU::set_tracer](crate::cpu::CPU::set_tracer).
+//! Before each instruction runs, it records the cycle, pc, opcode, registers, I, stack
+//! pointer and timers, unless the instruction falls outside its [pc range](Tracer::within)
+//! or [cycle window](Tracer::during).
+//!
+//! As a [flight recorder](Tracer::flight_recorder), a [Tracer] only keeps the last few
+//! records in memory, and writes them out when an [Error](crate::error::Error) stops the CPU.
+//! Breakpoints and watchpoints aren't errors in that sense, so they don't dump the records.
+//!
+//! Idle loops aren't skipped while tracing, so every instruction is recorded.
+
+use crate::error::{Error, Result};
+use std::{
+    collections::VecDeque,
+    fmt::{Debug, Display, Formatter},
+    fs::File,
+    io::{BufWriter, Write},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+/// How a [Tracer] writes its [Records](Record)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Format {
+    /// One line of space-separated fields per record
+    #[default]
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" | "txt" => Ok(Format::Text),
+            "json" | "jsonl" => Ok(Format::Json),
+            _ => Err(Error::InvalidTraceFormat {
+                format: s.to_string(),
+            }),
+        }
+    }
+}
+
+/// The state of the CPU just before it runs an instruction
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Record {
+    /// The cycle the instruction runs on
+    pub cycle: usize,
+    /// The address of the instruction
+    pub pc: u16,
+    /// The bytes of the instruction (four for XO-Chip's `f000 nnnn`, otherwise two)
+    pub opcode: Vec<u8>,
+    /// The general purpose registers, v0 through vF
+    pub v: [u8; 16],
+    /// The I register
+    pub i: u16,
+    /// The stack pointer
+    pub sp: usize,
+    /// The delay timer
+    pub dt: u8,
+    /// The sound timer
+    pub st: u8,
+}
+
+impl Record {
+    /// Writes the record as one JSON object, without a newline
+    /// # Examples
+    /// ```rust
+    /// # use chirp::trace::Record;
+    /// let record = Record {
+    ///     cycle: 1, pc: 0x200, opcode: vec![0x60, 0x2a], v: [0; 16], i: 0, sp: 0xefe, dt: 0, st: 0,
+    /// };
+    /// assert_eq!(
+    ///     r#"{"cycle":1,"pc":512,"opcode":"602a","v":[0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":0,"sp":3838,"dt":0,"st":0}"#,
+    ///     record.json(),
+    /// );
+    /// ```
+    pub fn json(&self) -> String {
+        let v: Vec<String> = self.v.iter().map(u8::to_string).collect();
+        format!(
+            r#"{{"cycle":{},"pc":{},"opcode":"{}","v":[{}],"i":{},"sp":{},"dt":{},"st":{}}}"#,
+            self.cycle,
+            self.pc,
+            hex(&self.opcode),
+            v.join(","),
+            self.i,
+            self.sp,
+            self.dt,
+            self.st,
+        )
+    }
+}
+
+impl Display for Record {
+    /// Writes the record as one line of text, in hex (except for the cycle)
+    /// # Examples
+    /// ```rust
+    /// # use chirp::trace::Record;
+    /// let record = Record {
+    ///     cycle: 1, pc: 0x200, opcode: vec![0x60, 0x2a], v: [0; 16], i: 0, sp: 0xefe, dt: 0, st: 0,
+    /// };
+    /// assert_eq!(
+    ///     "1 0200 602a v:00000000000000000000000000000000 i:0000 sp:0efe dt:00 st:00",
+    ///     record.to_string(),
+    /// );
+    /// ```
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:04x} {} v:{} i:{:04x} sp:{:04x} dt:{:02x} st:{:02x}",
+            self.cycle,
+            self.pc,
+            hex(&self.opcode),
+            hex(&self.v),
+            self.i,
+            self.sp,
+            self.dt,
+            self.st
+        )
+    }
+}
+
+/// Where a [Tracer] writes its records, shared between clones of the CPU
+#[derive(Clone)]
+struct Sink(Arc<Mutex<dyn Write + Send>>);
+
+impl Debug for Sink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Sink")
+    }
+}
+
+impl PartialEq for Sink {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Writes a [Record] for each instruction the CPU runs
+#[derive(Clone, Debug, PartialEq)]
+pub struct Tracer {
+    sink: Sink,
+    format: Format,
+    // Only instructions in these ranges are recorded, or every instruction if unset
+    pcs: Option<Range<u16>>,
+    cycles: Option<Range<usize>>,
+    // The most recent records, when running as a flight recorder
+    flight: Option<(usize, VecDeque<Record>)>,
+}
+
+impl Tracer {
+    /// Constructs a [Tracer] which writes every instruction to `sink`
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, trace::*};
+    /// # fn main() -> Result<()> {
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x60, 0x2a, 0x12, 0x02] },
+    /// };
+    /// ch8.cpu.set_tracer(Some(Tracer::new(std::io::sink(), Format::Json)));
+    /// ch8.cpu.multistep(&mut ch8.bus, 2)?;
+    /// assert!(ch8.cpu.tracer().is_some());
+    /// #   Ok(())
+    /// # }
+    /// ```
+    pub fn new(sink: impl Write + Send + 'static, format: Format) -> Self {
+        Self {
+            sink: Sink(Arc::new(Mutex::new(sink))),
+            format,
+            pcs: None,
+            cycles: None,
+            flight: None,
+        }
+    }
+
+    /// Constructs a [Tracer] which writes every instruction to a new file at `path`
+    pub fn create(path: impl AsRef<Path>, format: Format) -> Result<Self> {
+        Ok(Self::new(BufWriter::new(File::create(path)?), format))
+    }
+
+    /// Only records instructions whose address is in `pcs`
+    pub fn within(mut self, pcs: Range<u16>) -> Self {
+        self.pcs = Some(pcs);
+        self
+    }
+
+    /// Only records instructions which run on a cycle in `cycles`
+    pub fn during(mut self, cycles: Range<usize>) -> Self {
+        self.cycles = Some(cycles);
+        self
+    }
+
+    /// Keeps only the last `len` records in memory, and writes them when an error stops the CPU
+    /// # Examples
+    /// ```rust
+    /// # use chirp::{*, trace::*};
+    /// # use std::{io::Write, sync::{Arc, Mutex}};
+    /// #[derive(Clone, Default)]
+    /// struct Shared(Arc<Mutex<Vec<u8>>>);
+    /// impl Write for Shared {
+    ///     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    ///         self.0.lock().unwrap().write(buf)
+    ///     }
+    ///     fn flush(&mut self) -> std::io::Result<()> {
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// let mut ch8 = Chip8 {
+    ///     cpu: CPU::default(),
+    ///     // Counts in v0, then runs into an invalid instruction
+    ///     bus: bus! { Program [0x200..0x1000] = &[0x70, 0x01, 0x70, 0x01, 0x70, 0x01, 0xff, 0xff] },
+    /// };
+    /// let log = Shared::default();
+    /// let tracer = Tracer::new(log.clone(), Format::Text).flight_recorder(2);
+    /// ch8.cpu.set_tracer(Some(tracer));
+    /// ch8.cpu.multistep(&mut ch8.bus, 3).unwrap();
+    /// assert!(log.0.lock().unwrap().is_empty());
+    /// assert!(ch8.cpu.singlestep(&mut ch8.bus).is_err());
+    /// // The last add, and the invalid instruction
+    /// let log = String::from_utf8(log.0.lock().unwrap().clone()).unwrap();
+    /// assert_eq!(2, log.lines().count());
+    /// assert!(log.starts_with("3 0204 7001"));
+    /// ```
+    pub fn flight_recorder(mut self, len: usize) -> Self {
+        self.flight = Some((len, VecDeque::with_capacity(len)));
+        self
+    }
+
+    /// Gets the format records are written in
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Checks whether the instruction at `pc`, on `cycle`, passes the filters
+    /// # Examples
+    /// ```rust
+    /// # use chirp::trace::*;
+    /// let tracer = Tracer::new(std::io::sink(), Format::Text);
+    /// assert!(tracer.wants(0xffff, usize::MAX));
+    /// let tracer = tracer.within(0x200..0x300).during(10..20);
+    /// assert!(tracer.wants(0x2fe, 10));
+    /// assert!(!tracer.wants(0x300, 10));
+    /// assert!(!tracer.wants(0x200, 20));
+    /// ```
+    pub fn wants(&self, pc: u16, cycle: usize) -> bool {
+        let pcs = self.pcs.as_ref().is_none_or(|pcs| pcs.contains(&pc));
+        pcs && self
+            .cycles
+            .as_ref()
+            .is_none_or(|cycles| cycles.contains(&cycle))
+    }
+
+    /// Records one instruction, which has already passed the [filters](Tracer::wants)
+    pub(crate) fn record(&mut self, record: Record) -> Result<()> {
+        match &mut self.flight {
+            Some((len, records)) => {
+                if records.len() >= *len {
+                    records.pop_front();
+                }
+                if *len > 0 {
+                    records.push_back(record);
+                }
+                Ok(())
+            }
+            None => self.write(&[record]),
+        }
+    }
+
+    /// Writes out the flight recorder's records, if it has any, then flushes the sink
+    ///
+    /// The CPU calls this whenever an error, other than a breakpoint or watchpoint, stops it.
+    pub fn dump(&mut self) -> Result<()> {
+        let records: Vec<Record> = match &mut self.flight {
+            Some((_, records)) => records.drain(..).collect(),
+            None => vec![],
+        };
+        self.write(&records)?;
+        self.flush()
+    }
+
+    /// Flushes any records written, but still buffered
+    pub fn flush(&mut self) -> Result<()> {
+        self.sink.0.lock().map_err(|_| poisoned())?.flush()?;
+        Ok(())
+    }
+
+    fn write(&mut self, records: &[Record]) -> Result<()> {
+        let mut sink = self.sink.0.lock().map_err(|_| poisoned())?;
+        for record in records {
+            match self.format {
+                Format::Text => writeln!(sink, "{record}")?,
+                Format::Json => writeln!(sink, "{}", record.json())?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Another thread panicked while writing to the sink
+fn poisoned() -> Error {
+    std::io::Error::other("the trace sink was poisoned").into()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
@@ -0,0 +1,274 @@
+//! Renders the sound of small test ROMs, and compares them against golden WAV files
+//!
+//! The goldens were rendered by Chirp itself, so on their own they only catch changes.
+//! The `golden_*` tests check what's in them against the ROMs: the beep's pitch and
+//! length, and how often the pattern repeats, measured straight from the files.
+//!
+//! Timendus' test suite only has a beep test (`7-beep.ch8`) from version 4 on, and the
+//! submodule holds the older, combined ROM, so `suite_beep` is ignored unless asked for.
+//!
+//! Every test runs each frame, then renders it, like the frontends do.
+
+use chirp::{
+    audio::{Sink, Synth, WavSink, Waveform},
+    *,
+};
+
+const SAMPLE_RATE: u32 = 8000;
+
+fn setup_environment(rom: &[u8]) -> (CPU, Bus) {
+    let mut cpu = CPU::default();
+    cpu.flags = Flags {
+        debug: false,
+        pause: false,
+        speed: 8,
+        // The audio pattern and pitch are XO-Chip extensions
+        mode: Mode::XOChip,
+        ..Default::default()
+    };
+    (
+        cpu,
+        bus! {
+            // Load the charset into ROM
+            Charset [0x0050..0x00A0] = include_bytes!("../src/mem/charset.bin"),
+            // Load the ROM file into RAM
+            Program [0x0200..0x1000] = rom,
+            // Create a screen
+            Screen  [0x0F00..0x1000],
+        },
+    )
+}
+
+/// Reads the samples out of a mono, 16-bit PCM WAV file
+fn samples(wav: &[u8]) -> Vec<i16> {
+    wav[44..]
+        .chunks_exact(2)
+        .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+        .collect()
+}
+
+/// Counts the times the samples go from low to high
+fn rising_edges<T: Copy + Default + PartialOrd>(samples: &[T]) -> usize {
+    samples
+        .windows(2)
+        .filter(|pair| pair[0] < T::default() && pair[1] > T::default())
+        .count()
+}
+
+/// Runs `rom` for `frames` frames, rendering one frame of audio per frame
+fn render(rom: &[u8], frames: usize, synth: &mut Synth, sink: &mut impl Sink) {
+    let (mut cpu, mut bus) = setup_environment(rom);
+    for _ in 0..frames {
+        cpu.multistep(&mut bus, 8).unwrap();
+        synth.render(&cpu, 1, sink).unwrap();
+    }
+}
+
+/// Sets the sound timer to 16 frames, then waits in silence
+#[test]
+fn beep() {
+    let mut sink = WavSink::new(SAMPLE_RATE);
+    render(
+        include_bytes!("audio/beep.ch8"),
+        40,
+        &mut Synth::new(SAMPLE_RATE),
+        &mut sink,
+    );
+    assert_eq!(include_bytes!("audio/beep.wav").as_slice(), sink.to_bytes());
+}
+
+/// The golden beep is a 440Hz tone, which plays for the 16 frames the sound timer is set to,
+/// starting with the frame it's set in
+#[test]
+fn golden_beep() {
+    let samples = samples(include_bytes!("audio/beep.wav"));
+    let first = samples.iter().position(|&s| s != 0).unwrap();
+    let last = samples.iter().rposition(|&s| s != 0).unwrap();
+    let frames = (last + 1 - first) as f64 * 60.0 / SAMPLE_RATE as f64;
+    assert!((frames - 16.0).abs() < 0.01, "{frames}");
+    let hz = rising_edges(&samples) as f64 * SAMPLE_RATE as f64 / (last - first) as f64;
+    assert!((hz - 440.0).abs() < 5.0, "{hz}");
+}
+
+/// The golden pattern plays the ROM's 128 bits over and over, at pitch 80's playback rate,
+/// for the 16 frames the sound timer is set to
+#[test]
+fn golden_pattern() {
+    let pattern = &include_bytes!("audio/pattern.ch8")[0x20..0x30];
+    let samples = samples(include_bytes!("audio/pattern.wav"));
+    let first = samples.iter().position(|&s| s != 0).unwrap();
+    let last = samples.iter().rposition(|&s| s != 0).unwrap();
+    let playing = &samples[first..=last];
+    let frames = playing.len() as f64 * 60.0 / SAMPLE_RATE as f64;
+    assert!((frames - 16.0).abs() < 0.01, "{frames}");
+    // 4000 * 2^((80 - 64) / 48) bits per second
+    let bits_per_sample = 5039.684 / SAMPLE_RATE as f64;
+    let wrong = playing
+        .iter()
+        .enumerate()
+        .filter(|&(n, &sample)| {
+            let bit = (n as f64 * bits_per_sample) as usize % 128;
+            (pattern[bit / 8] >> (7 - bit % 8) & 1 == 1) != (sample > 0)
+        })
+        .count();
+    // Each frame starts on a whole sample, so only samples at the edges of bits can be off
+    assert!(wrong * 10 < playing.len(), "{wrong} of {}", playing.len());
+}
+
+/// Timendus' beep test beeps for as long as B is held
+#[test]
+#[ignore = "needs chip8-test-suite v4"]
+fn suite_beep() {
+    let rom = std::fs::read("chip8-test-suite/bin/7-beep.ch8").unwrap();
+    let (mut cpu, mut bus) = setup_environment(&rom);
+    cpu.flags.mode = Mode::Chip8;
+    let (mut synth, mut samples) = (Synth::new(SAMPLE_RATE), vec![]);
+    let mut frame = |cpu: &mut CPU, samples: &mut Vec<f32>| {
+        cpu.multistep(&mut bus, 8).unwrap();
+        synth.render(cpu, 1, samples).unwrap();
+    };
+    (0..30).for_each(|_| frame(&mut cpu, &mut samples));
+    assert!(samples.iter().all(|&s| s == 0.0), "beeped without a key");
+    cpu.press(0xb).unwrap();
+    (0..30).for_each(|_| frame(&mut cpu, &mut samples));
+    assert!(samples[samples.len() / 2..].iter().any(|&s| s != 0.0));
+    cpu.release(0xb).unwrap();
+    (0..30).for_each(|_| frame(&mut cpu, &mut samples));
+    let len = samples.len();
+    assert!(
+        samples[len - len / 6..].iter().all(|&s| s == 0.0),
+        "kept beeping"
+    );
+}
+
+/// Loads an XO-Chip audio pattern, sets the pitch to 80, and plays it for 16 frames
+#[test]
+fn pattern() {
+    let mut sink = WavSink::new(SAMPLE_RATE);
+    render(
+        include_bytes!("audio/pattern.ch8"),
+        30,
+        &mut Synth::new(SAMPLE_RATE),
+        &mut sink,
+    );
+    assert_eq!(
+        include_bytes!("audio/pattern.wav").as_slice(),
+        sink.to_bytes()
+    );
+}
+
+/// A pattern of alternating bits plays a square wave at half the playback rate
+#[test]
+fn pattern_rate() {
+    let (mut cpu, mut bus) = setup_environment(&[
+        0xa2, 0x10, // mov $210, I
+        0xf0, 0x02, // audio
+        0x60, 0x70, // mov #70, v0
+        0xf0, 0x3a, // pitch v0
+        0x60, 0xff, // mov #ff, v0
+        0xf0, 0x18, // mov v0, ST
+        0x71, 0x01, // add #01, v1
+        0x12, 0x0c, // jmp $20c
+        0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, // pattern
+        0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55,
+    ]);
+    cpu.multistep(&mut bus, 8).unwrap();
+    assert_eq!(8000.0, cpu.playback_rate());
+
+    let mut samples = vec![];
+    Synth::new(48000).render(&cpu, 60, &mut samples).unwrap();
+    let rising_edges = rising_edges(&samples);
+    assert!((3999..=4000).contains(&rising_edges), "{rising_edges}");
+}
+
+/// Rendering frame-by-frame, as the CPU runs, picks the pattern up where it left off
+#[test]
+fn pattern_continuity() {
+    let (mut cpu, mut bus) = setup_environment(include_bytes!("audio/pattern.ch8"));
+    let (mut at_once, mut by_frame) = (vec![], vec![]);
+    let mut synth = Synth::new(44100);
+    for frame in 0..10 {
+        cpu.multistep(&mut bus, 8).unwrap();
+        if frame == 0 {
+            Synth::new(44100).render(&cpu, 10, &mut at_once).unwrap();
+        }
+        synth.render(&cpu, 1, &mut by_frame).unwrap();
+    }
+    assert_eq!(at_once, by_frame);
+}
+
+/// The pattern's position is part of the CPU, so save states keep it
+#[test]
+fn pattern_phase() {
+    let (mut cpu, mut bus) = setup_environment(include_bytes!("audio/pattern.ch8"));
+    cpu.multistep(&mut bus, 8 * 3).unwrap();
+    assert_ne!(Some(0.0), cpu.pattern_phase());
+    let ch8 = Chip8 { cpu, bus };
+    let mut loaded = Chip8::default();
+    loaded.load_state(&ch8.save_state()).unwrap();
+    assert_eq!(ch8.cpu.pattern_phase(), loaded.cpu.pattern_phase());
+}
+
+/// The beep lasts as long as the sound timer, and no longer
+#[test]
+fn beep_length() {
+    let mut samples = vec![];
+    render(
+        include_bytes!("audio/beep.ch8"),
+        40,
+        &mut Synth::new(6000),
+        &mut samples,
+    );
+    assert_eq!(40 * 100, samples.len());
+    let first = samples.iter().position(|&s| s != 0.0).unwrap();
+    let last = samples.iter().rposition(|&s| s != 0.0).unwrap();
+    // The timer is set during the first frame, and counts down for 16 frames
+    assert_eq!((0, 16 * 100 - 1), (first, last));
+}
+
+/// Setting the sound timer to 1 beeps for a single frame
+#[test]
+fn shortest_beep() {
+    let mut samples = vec![];
+    render(
+        &[
+            0x60, 0x01, // mov #01, v0
+            0xf0, 0x18, // mov v0, ST
+            0x71, 0x01, // add #01, v1
+            0x12, 0x04, // jmp $204
+        ],
+        3,
+        &mut Synth::new(6000),
+        &mut samples,
+    );
+    assert!(samples[..100].iter().all(|&s| s != 0.0));
+    assert!(samples[100..].iter().all(|&s| s == 0.0));
+}
+
+/// Fractional samples per frame are carried over, so the rate doesn't drift
+#[test]
+fn sample_rate() {
+    let mut samples = vec![];
+    let mut synth = Synth::new(44100);
+    for _ in 0..60 {
+        synth.render(&CPU::default(), 1, &mut samples).unwrap();
+    }
+    assert_eq!(44100, samples.len());
+}
+
+#[test]
+fn waveforms() {
+    for waveform in [
+        Waveform::Square,
+        Waveform::Triangle,
+        Waveform::Sawtooth,
+        Waveform::Sine,
+    ] {
+        for phase in 0..100 {
+            let sample = waveform.sample(phase as f64 / 100.0);
+            assert!((-1.0..=1.0).contains(&sample), "{waveform:?}: {sample}");
+        }
+    }
+    assert_eq!(Ok(Waveform::Sine), "sine".parse().map_err(|_| ()));
+    assert!("noise".parse::<Waveform>().is_err());
+}
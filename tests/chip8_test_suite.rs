@@ -7,7 +7,7 @@ fn setup_environment() -> (CPU, Bus) {
     cpu.flags = Flags {
         debug: true,
         pause: false,
-        monotonic: Some(8),
+        speed: 8,
         ..Default::default()
     };
     (
@@ -0,0 +1,38 @@
+//! Programs shared between the tests, and the debugger adapter's tests
+#![allow(dead_code)]
+use chirp::*;
+
+/// Counts in v0, draws, and rolls random numbers into v1, on a strict bus
+///
+/// There's a breakpoint at 300, which is never reached, and the random numbers are seeded.
+pub fn counter() -> Chip8 {
+    let mut cpu = CPU::default();
+    cpu.flags.debug = false;
+    cpu.set_break(0x300).seed(1234);
+    let mut bus = MemoryMap::classic().bus().load_region(
+        Program,
+        &[
+            0x70, 0x01, // add   #01, v0
+            0xc1, 0xff, // rand  #ff, v1
+            0xd0, 0x15, // draw  #5, v0, v1
+            0xf0, 0x15, // mov   v0, DT
+            0x12, 0x00, // jmp   200
+        ],
+    );
+    bus.set_strict(true);
+    Chip8 { cpu, bus }
+}
+
+/// Calls a subroutine which counts in v0 and writes it to 300, then halts
+pub const SUBROUTINE: &[u8] = &[
+    0x60, 0x05, // 200: mov   #05, v0
+    0x22, 0x0a, // 202: call  20a
+    0x71, 0x01, // 204: add   #01, v1
+    0xa3, 0x00, // 206: mov   $300, I
+    0x12, 0x12, // 208: jmp   212
+    0x70, 0x01, // 20a: add   #01, v0
+    0xa3, 0x00, // 20c: mov   $300, I
+    0xf0, 0x55, // 20e: dma   v0, I
+    0x00, 0xee, // 210: ret
+    0x12, 0x12, // 212: jmp   212
+];
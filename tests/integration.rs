@@ -2,6 +2,8 @@
 use chirp::*;
 use std::{collections::hash_map::DefaultHasher, hash::Hash};
 
+mod common;
+
 #[test]
 fn chip8() {
     let ch8 = Chip8::default(); // Default
@@ -60,6 +62,142 @@ mod bus {
         // Print the screen of a bus with no screen
         bus! {}.print_screen().unwrap()
     }
+
+    mod strict {
+        use super::*;
+        use chirp::bus::{Access, Permissions};
+
+        /// Sets up a strict bus with the classic memory map, running `program`
+        fn setup(program: &[u8]) -> Chip8 {
+            let mut ch8 = Chip8 {
+                cpu: CPU::default(),
+                bus: MemoryMap::classic().bus().load_region(Program, program),
+            };
+            ch8.cpu.flags.debug = false;
+            ch8.bus.set_strict(true);
+            ch8
+        }
+
+        #[test]
+        fn lenient_by_default() {
+            let mut bus = MemoryMap::classic().bus();
+            bus.write(0x50u16, 0xffu8);
+            assert_eq!(0xffu8, bus.read(0x50u16));
+            assert_eq!(0xc5u8, bus.read(0x20000usize));
+            assert!(bus.take_fault().is_none());
+        }
+
+        #[test]
+        fn write_to_charset() {
+            // mov 50, I; mov (I), v0
+            let mut ch8 = setup(&[0xa0, 0x50, 0xf0, 0x55]);
+            ch8.cpu.tick(&mut ch8.bus).unwrap();
+            let error = ch8.cpu.tick(&mut ch8.bus).unwrap_err();
+            assert!(matches!(
+                error,
+                Error::AccessViolation {
+                    access: Access::Write,
+                    addr: 0x50,
+                    region: Charset
+                }
+            ));
+            // The write was dropped, and the offending instruction was undone
+            assert_eq!(Some(&0), ch8.bus.get(0x50));
+            assert_eq!(0x202, ch8.cpu.pc());
+            assert_eq!(0x50, ch8.cpu.i());
+            assert_eq!(0, ch8.cpu.v()[0]);
+        }
+
+        #[test]
+        fn partial_write() {
+            // mov #ff, v0; mov 4e, I; bcd v0, with only its ones digit in the charset
+            let mut ch8 = setup(&[0x60, 0xff, 0xa0, 0x4e, 0xf0, 0x33]);
+            ch8.cpu.multistep(&mut ch8.bus, 2).unwrap();
+            let error = ch8.cpu.tick(&mut ch8.bus).unwrap_err();
+            assert!(matches!(
+                error,
+                Error::AccessViolation {
+                    access: Access::Write,
+                    addr: 0x50,
+                    region: Charset
+                }
+            ));
+            // The digits written before the fault were put back, too
+            assert_eq!(Some(&[0, 0, 0][..]), ch8.bus.get(0x4e..0x51));
+            assert_eq!(0x204, ch8.cpu.pc());
+        }
+
+        #[test]
+        fn read_into_registers() {
+            // mov #2a, v0; mov 300, I; mov (I), v0
+            let mut ch8 = setup(&[0x60, 0x2a, 0xa3, 0x00, 0xf0, 0x65]);
+            ch8.bus.set_region(Screen, 0x300..0x400);
+            let write_only = Permissions {
+                read: false,
+                write: true,
+                execute: false,
+            };
+            ch8.bus.set_permissions(Screen, write_only);
+            ch8.cpu.multistep(&mut ch8.bus, 2).unwrap();
+            assert!(ch8.cpu.tick(&mut ch8.bus).is_err());
+            // Neither v0 nor I took on the results of the read
+            assert_eq!(0x204, ch8.cpu.pc());
+            assert_eq!(0x300, ch8.cpu.i());
+            assert_eq!(0x2a, ch8.cpu.v()[0]);
+        }
+
+        #[test]
+        fn execute_from_screen() {
+            // jmp f00
+            let mut ch8 = setup(&[0x1f, 0x00]);
+            ch8.bus.set_region(Screen, 0xf00..0x1000);
+            ch8.cpu.tick(&mut ch8.bus).unwrap();
+            let error = ch8.cpu.tick(&mut ch8.bus).unwrap_err();
+            assert!(matches!(
+                error,
+                Error::AccessViolation {
+                    access: Access::Execute,
+                    region: Screen,
+                    ..
+                }
+            ));
+        }
+
+        #[test]
+        fn execute_long_operand_from_screen() {
+            // jmp 2fe; 2fe: mov $xxxx, I, with its operand in the screen
+            let mut ch8 = setup(&[0x12, 0xfe]);
+            ch8.cpu.flags.mode = Mode::XOChip;
+            ch8.bus.set_region(Screen, 0x300..0x400);
+            ch8.bus.write(0x2feu16, 0xf000u16);
+            ch8.cpu.tick(&mut ch8.bus).unwrap();
+            let error = ch8.cpu.tick(&mut ch8.bus).unwrap_err();
+            assert!(matches!(
+                error,
+                Error::AccessViolation {
+                    access: Access::Execute,
+                    addr: 0x300,
+                    region: Screen
+                }
+            ));
+            assert_eq!(0x2fe, ch8.cpu.pc());
+        }
+
+        #[test]
+        fn read_out_of_bounds() {
+            let mut bus = MemoryMap::classic().bus();
+            bus.set_strict(true);
+            assert_eq!(0xc5c5u16, bus.read(bus.len()));
+            assert!(matches!(
+                bus.take_fault(),
+                Some(Error::UnmappedAccess {
+                    access: Access::Read,
+                    ..
+                })
+            ));
+            assert!(bus.check(Access::Read, 0x200..0x202).is_ok());
+        }
+    }
 }
 
 mod cpu {
@@ -132,7 +270,7 @@ mod cpu {
                 keypause: false,
                 draw_wait: false,
                 lastkey: None,
-                monotonic: None,
+                speed: 8,
                 ..Default::default()
             };
             let cf2 = cf1.clone();
@@ -227,7 +365,13 @@ mod quirks {
                 shift: true,
                 draw_wait: true,
                 dma_inc: true,
+                dma_inc_x: true,
                 stupid_jumps: true,
+                wrap: true,
+                count_collisions: true,
+                half_scroll: true,
+                keep_screen: true,
+                i_overflow: true,
             }
         )
     }
@@ -242,19 +386,32 @@ mod quirks {
                 shift: false,
                 draw_wait: false,
                 dma_inc: false,
+                dma_inc_x: false,
                 stupid_jumps: false,
+                wrap: false,
+                count_collisions: false,
+                half_scroll: false,
+                keep_screen: false,
+                i_overflow: false,
             }
         )
     }
 
     #[test]
+    #[allow(clippy::clone_on_copy)]
     fn clone() {
         let q1 = Quirks {
             bin_ops: false,
             shift: true,
             draw_wait: false,
             dma_inc: true,
+            dma_inc_x: false,
             stupid_jumps: false,
+            wrap: false,
+            count_collisions: false,
+            half_scroll: false,
+            keep_screen: false,
+            i_overflow: false,
         };
         let q2 = q1.clone();
         assert_eq!(q1, q2);
@@ -282,4 +439,1008 @@ mod quirks {
         Quirks::from(true).hash(&mut hasher);
         println!("{hasher:?}");
     }
+
+    #[test]
+    fn spec_round_trip() {
+        for platform in Platform::ALL {
+            let quirks = platform.quirks();
+            assert_eq!(quirks, quirks.to_string().parse().unwrap());
+        }
+    }
+
+    #[test]
+    fn spec_leaves_others_alone() {
+        let mut quirks = Quirks::from(true);
+        quirks.apply("vfreset").unwrap();
+        assert_eq!(
+            Quirks {
+                bin_ops: false,
+                ..Quirks::from(true)
+            },
+            quirks
+        );
+    }
+
+    #[test]
+    fn spec_invalid() {
+        assert!("bogus=on".parse::<Quirks>().is_err());
+        assert!("shift=sideways".parse::<Quirks>().is_err());
+    }
+}
+
+mod platform {
+    use super::*;
+
+    #[test]
+    fn name_round_trip() {
+        for platform in Platform::ALL {
+            assert_eq!(platform, platform.to_string().parse().unwrap());
+        }
+        assert!("pdp-11".parse::<Platform>().is_err());
+    }
+
+    #[test]
+    fn from_mode() {
+        for mode in [Mode::Chip8, Mode::SChip, Mode::XOChip] {
+            let platform = Platform::from(mode.clone());
+            assert_eq!(mode, platform.mode());
+            assert_eq!(Quirks::from(mode), platform.quirks());
+        }
+    }
+}
+
+mod memory_map {
+    use super::*;
+
+    #[test]
+    fn from_mode() {
+        assert_eq!(MemoryMap::classic(), MemoryMap::from(Mode::Chip8));
+        assert_eq!(MemoryMap::classic(), MemoryMap::from(Mode::SChip));
+        assert_eq!(MemoryMap::xochip(), MemoryMap::from(Mode::XOChip));
+        assert_eq!(MemoryMap::classic(), MemoryMap::default());
+    }
+
+    #[test]
+    fn xochip_program_space() {
+        let map = MemoryMap::xochip();
+        assert_eq!(0x200..0x10000, map.program);
+        // The screen and stack aren't visible to 16-bit addresses
+        assert!(map.screen.start >= 0x10000);
+        assert!(map.stack.start >= 0x10000);
+    }
+
+    #[test]
+    fn bus() {
+        let bus = MemoryMap::xochip().bus();
+        for (region, range) in [
+            (Charset, MemoryMap::xochip().charset),
+            (Program, MemoryMap::xochip().program),
+            (Screen, MemoryMap::xochip().screen),
+            (Stack, MemoryMap::xochip().stack),
+        ] {
+            assert_eq!(range.len(), bus.get_region(region).unwrap().len());
+        }
+    }
+
+    #[test]
+    fn long_load_reaches_top_of_memory() {
+        let map = MemoryMap::xochip();
+        let mut ch8 = Chip8 {
+            bus: map
+                .bus()
+                // mov $fffe, I; mov (I), v0
+                .load_region(Program, &[0xf0, 0x00, 0xff, 0xfe, 0xf0, 0x65]),
+            cpu: CPU::new(
+                map.screen.start,
+                map.charset.start as u16,
+                map.program.start as u16,
+                map.stack_top(),
+                Dis::default(),
+                vec![],
+                Flags {
+                    speed: 8,
+                    mode: Mode::XOChip,
+                    ..Default::default()
+                },
+            ),
+        };
+        ch8.bus.write(0xfffeu16, 0xa5u8);
+        ch8.cpu.multistep(&mut ch8.bus, 2).unwrap();
+        assert_eq!(0xa5, ch8.cpu.v()[0]);
+        assert_eq!(0xffff, ch8.cpu.i());
+    }
+}
+
+mod rpl {
+    use chirp::rpl;
+
+    #[test]
+    fn save_and_load() -> chirp::Result<()> {
+        let dir = std::env::temp_dir().join(format!("chirp-rpl-{}", std::process::id()));
+        let rom = b"\x12\x00";
+        // Nothing saved yet
+        assert_eq!([0; 16], rpl::load(&dir, rom)?);
+        rpl::save(&dir, rom, b"high score: 9999")?;
+        assert_eq!(*b"high score: 9999", rpl::load(&dir, rom)?);
+        // Other ROMs get their own flags
+        assert_eq!([0; 16], rpl::load(&dir, b"\x12\x02")?);
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+}
+
+mod rng {
+    use super::*;
+    use chirp::cpu::rng::{Rng, Xorshift};
+
+    /// Counts up from zero, so tests know exactly what `Cxbb` will produce
+    #[derive(Clone, Debug)]
+    struct Counter(u64);
+
+    impl Rng for Counter {
+        fn next_u8(&mut self) -> u8 {
+            self.0 = self.0.wrapping_add(1);
+            self.0 as u8
+        }
+        fn state(&self) -> u64 {
+            self.0
+        }
+        fn set_state(&mut self, state: u64) {
+            self.0 = state;
+        }
+        fn clone_box(&self) -> Box<dyn Rng> {
+            Box::new(self.clone())
+        }
+    }
+
+    fn run_rand(cpu: &mut CPU) -> u8 {
+        let mut bus = bus! {
+            // rand #ff, v0
+            Program [0x0200..0x0210] = &[0xc0, 0xff],
+        };
+        cpu.soft_reset();
+        cpu.tick(&mut bus).unwrap();
+        cpu.v()[0]
+    }
+
+    #[test]
+    fn set_rng() {
+        let mut cpu = CPU::default();
+        cpu.set_rng(Counter(41));
+        assert_eq!(42, run_rand(&mut cpu));
+        assert_eq!(42, cpu.rng().state());
+    }
+
+    #[test]
+    fn seed() {
+        let (mut a, mut b) = (CPU::default(), CPU::default());
+        a.seed(1);
+        b.seed(1);
+        assert_eq!(run_rand(&mut a), run_rand(&mut b));
+        // Cloned CPUs keep generating the same numbers
+        let mut c = a.clone();
+        assert_eq!(run_rand(&mut a), run_rand(&mut c));
+    }
+
+    #[test]
+    fn xorshift_zero_seed() {
+        let mut rng = Xorshift::new(0);
+        assert_ne!(0, rng.state());
+        let bytes: Vec<u8> = (0..16).map(|_| rng.next_u8()).collect();
+        assert!(bytes.iter().any(|&byte| byte != bytes[0]));
+    }
+}
+
+mod sanitizer {
+    use super::*;
+    use chirp::sanitizer::{Finding, Report};
+
+    /// Runs `steps` instructions of `program` on a sanitized bus, and gets the reports
+    fn run(program: &[u8], steps: usize) -> Vec<Report> {
+        let map = MemoryMap::classic();
+        let mut ch8 = Chip8 {
+            cpu: CPU::new(
+                map.screen.start,
+                map.charset.start as u16,
+                map.program.start as u16,
+                map.stack_top(),
+                Dis::default(),
+                vec![],
+                Flags::default(),
+            ),
+            bus: map
+                .bus()
+                .sanitize()
+                .load_region(Charset, BigFont::SChip.charset())
+                .load_region(Program, program),
+        };
+        for _ in 0..steps {
+            // Uninitialized memory doesn't hold valid instructions
+            let _ = ch8.cpu.tick(&mut ch8.bus);
+        }
+        let reports = ch8.bus.sanitizer().unwrap().reports().to_vec();
+        reports
+    }
+
+    #[test]
+    fn clean() {
+        // mov #0, v0; font v0; draw #5, v0, v0
+        assert_eq!(
+            vec![] as Vec<Report>,
+            run(&[0x60, 0x00, 0xf0, 0x29, 0xd0, 0x05], 3)
+        );
+    }
+
+    #[test]
+    fn uninit_read() {
+        // mov $300, I; mov (I), v0; jmp 200
+        let reports = run(&[0xa3, 0x00, 0xf0, 0x65, 0x12, 0x00], 9);
+        // Reported once, at the first offending instruction
+        assert_eq!(
+            vec![Report {
+                pc: 0x202,
+                cycle: 2,
+                finding: Finding::UninitRead { addr: 0x300 }
+            }],
+            reports
+        );
+    }
+
+    #[test]
+    fn uninit_execute() {
+        // jmp 300
+        let reports = run(&[0x13, 0x00], 2);
+        assert_eq!(
+            [
+                Finding::UninitExecute { addr: 0x300 },
+                Finding::UninitExecute { addr: 0x301 }
+            ],
+            [reports[0].finding, reports[1].finding]
+        );
+    }
+
+    #[test]
+    fn self_modifying() {
+        // mov $200, I; mov v0, (I)
+        let reports = run(&[0xa2, 0x00, 0xf0, 0x55], 2);
+        assert_eq!(
+            vec![Finding::SelfModifying { addr: 0x200 }],
+            reports.iter().map(|r| r.finding).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn index_out_of_bounds() {
+        // mov $fff, I; mov (I), v1
+        let reports = run(&[0xaf, 0xff, 0xf1, 0x65], 2);
+        assert_eq!(
+            Finding::IndexOutOfBounds {
+                i: 0xfff,
+                len: 2,
+                end: 0x1000
+            },
+            reports[0].finding
+        );
+    }
+}
+
+mod state {
+    use super::*;
+    use chirp::{error::Error, state::VERSION};
+    use common::counter;
+
+    #[test]
+    fn round_trip() {
+        let mut ch8 = counter();
+        ch8.cpu.multistep(&mut ch8.bus, 37).unwrap();
+        ch8.cpu.press(0xa).unwrap();
+        let saved = ch8.clone();
+        let state = ch8.save_state();
+        ch8.cpu.multistep(&mut ch8.bus, 100).unwrap();
+        assert_ne!(saved, ch8);
+        ch8.load_state(&state).unwrap();
+        assert_eq!(saved, ch8);
+    }
+
+    /// The random number generator picks up where it left off
+    #[test]
+    fn same_future() {
+        let mut ch8 = counter();
+        ch8.cpu.multistep(&mut ch8.bus, 37).unwrap();
+        let state = ch8.save_state();
+        let mut other = Chip8::default();
+        other.load_state(&state).unwrap();
+        ch8.cpu.multistep(&mut ch8.bus, 100).unwrap();
+        other.cpu.multistep(&mut other.bus, 100).unwrap();
+        assert_eq!(ch8.save_state(), other.save_state());
+    }
+
+    /// Breakpoints, watchpoints, and their hit counts, are saved too
+    #[test]
+    fn breakpoints() {
+        use chirp::cpu::breakpoint::*;
+        let mut ch8 = counter();
+        let condition = "v0 >= 2".parse().unwrap();
+        ch8.cpu
+            .add_breakpoint(Breakpoint::new(0x202).when(condition).ignoring(10));
+        ch8.cpu.add_watchpoint(
+            &mut ch8.bus,
+            Watchpoint::new(0x300..0x400, Watch::Read).once(),
+        );
+        ch8.cpu.multistep(&mut ch8.bus, 37).unwrap();
+        let saved = ch8.clone();
+        let state = ch8.save_state();
+        ch8.cpu.remove_breakpoint(1);
+        ch8.bus.remove_watchpoint(2);
+        ch8.cpu.add_breakpoint(Breakpoint::new(0x204));
+        ch8.load_state(&state).unwrap();
+        assert_eq!(saved, ch8);
+        assert_ne!(0, ch8.cpu.conditional_breakpoints()[0].hits());
+    }
+
+    #[test]
+    fn newer_version() {
+        let mut state = counter().save_state();
+        state[8..10].copy_from_slice(&(VERSION + 1).to_le_bytes());
+        assert!(matches!(
+            Chip8::default().load_state(&state),
+            Err(Error::StateVersion { version, supported: VERSION }) if version == VERSION + 1
+        ));
+    }
+
+    #[test]
+    fn truncated() {
+        let mut ch8 = counter();
+        let before = ch8.clone();
+        let state = ch8.save_state();
+        for len in [0, 4, 10, state.len() / 2, state.len() - 1] {
+            assert!(ch8.load_state(&state[..len]).is_err());
+            // Failing to load doesn't change anything
+            assert_eq!(before, ch8);
+        }
+    }
+}
+
+mod rewind {
+    use super::*;
+    use chirp::{
+        error::Error,
+        rewind::{Budget, Rewind},
+    };
+    use common::counter;
+
+    /// Runs `frames` frames, recording each, and gets the state after every cycle
+    fn run(ch8: &mut Chip8, rewind: &mut Rewind, frames: usize) -> Vec<Vec<u8>> {
+        let mut reference = counter();
+        let mut states = vec![reference.save_state()];
+        for _ in 0..frames {
+            rewind.record(ch8);
+            ch8.cpu.run_frame(&mut ch8.bus).unwrap();
+        }
+        while reference.cpu.cycle() < ch8.cpu.cycle() {
+            reference.cpu.multistep(&mut reference.bus, 1).unwrap();
+            states.push(reference.save_state());
+        }
+        states
+    }
+
+    #[test]
+    fn step_back() {
+        let (mut ch8, mut rewind) = (counter(), Rewind::default());
+        let states = run(&mut ch8, &mut rewind, 10);
+        assert_eq!(states.last(), Some(&ch8.save_state()));
+        for cycle in (0..states.len() - 1).rev() {
+            assert!(rewind.step_back(&mut ch8).unwrap());
+            assert_eq!(cycle, ch8.cpu.cycle());
+            assert_eq!(states[cycle], ch8.save_state());
+        }
+        assert!(!rewind.step_back(&mut ch8).unwrap());
+    }
+
+    #[test]
+    fn frame_back() {
+        let (mut ch8, mut rewind) = (counter(), Rewind::default());
+        run(&mut ch8, &mut rewind, 10);
+        let mut frames = 0;
+        while rewind.frame_back(&mut ch8).unwrap() {
+            frames += 1;
+        }
+        assert_eq!(10, frames);
+        assert_eq!(counter().save_state(), ch8.save_state());
+    }
+
+    /// Running again after rewinding replaces the old future
+    #[test]
+    fn branch() {
+        let (mut ch8, mut rewind) = (counter(), Rewind::default());
+        run(&mut ch8, &mut rewind, 4);
+        rewind.frame_back(&mut ch8).unwrap();
+        ch8.cpu.press(0x5).unwrap();
+        let pressed = ch8.save_state();
+        rewind.record(&ch8);
+        ch8.cpu.run_frame(&mut ch8.bus).unwrap();
+        assert_eq!(4, rewind.len());
+        assert!(rewind.frame_back(&mut ch8).unwrap());
+        assert_eq!(pressed, ch8.save_state());
+    }
+
+    #[test]
+    fn budget() {
+        let mut ch8 = counter();
+        let mut frames = Rewind::new(Budget::Frames(3));
+        run(&mut ch8, &mut frames, 10);
+        assert_eq!(3, frames.len());
+
+        let size = ch8.save_state().len();
+        let mut bytes = Rewind::new(Budget::Bytes(size * 5 + 1));
+        run(&mut counter(), &mut bytes, 10);
+        assert_eq!(5, bytes.len());
+    }
+
+    /// Replaying doesn't stop at, or count hits on, breakpoints and watchpoints
+    #[test]
+    fn breakpoints() {
+        use chirp::cpu::breakpoint::*;
+        let (mut ch8, mut rewind) = (counter(), Rewind::default());
+        // Each of these fires on every loop iteration
+        ch8.cpu.set_break(0x200);
+        ch8.cpu.add_breakpoint(Breakpoint::new(0x202).ignoring(1));
+        ch8.cpu.add_breakpoint(Breakpoint::new(0x204).once());
+        ch8.cpu
+            .add_watchpoint(&mut ch8.bus, Watchpoint::new(0..5, Watch::Read));
+        rewind.record(&ch8);
+        let mut stops = 0;
+        while ch8.cpu.cycle() < 20 {
+            if let Err(Error::BreakpointHit { .. }) = ch8.cpu.multistep(&mut ch8.bus, 1) {
+                ch8.cpu.flags.pause = false;
+                stops += 1;
+            }
+        }
+        assert!(stops > 4);
+        assert!(rewind.step_back(&mut ch8).unwrap());
+        let (cpu, bus) = (ch8.cpu.clone(), ch8.bus.clone());
+        let mut reference = counter();
+        for cycle in (0..19).rev() {
+            assert!(rewind.step_back(&mut ch8).unwrap());
+            assert_eq!(cycle, ch8.cpu.cycle());
+            reference.load_state(&counter().save_state()).unwrap();
+            reference.cpu.multistep(&mut reference.bus, cycle).unwrap();
+            assert_eq!(reference.cpu.v(), ch8.cpu.v());
+            // Each replay starts over from the same recording, with the same hit counts
+            assert_eq!(cpu.breakpoints(), ch8.cpu.breakpoints());
+            assert_eq!(
+                cpu.conditional_breakpoints(),
+                ch8.cpu.conditional_breakpoints()
+            );
+            assert_eq!(bus.watchpoints(), ch8.bus.watchpoints());
+        }
+    }
+
+    /// Going back in time, like by loading an old save state, starts a new history
+    #[test]
+    fn load_state() {
+        let (mut ch8, mut rewind) = (counter(), Rewind::default());
+        let state = ch8.save_state();
+        run(&mut ch8, &mut rewind, 4);
+        ch8.load_state(&state).unwrap();
+        rewind.record(&ch8);
+        assert_eq!(1, rewind.len());
+    }
+}
+
+mod debugger {
+    use super::*;
+    use chirp::debugger::Debugger;
+
+    /// Calls a subroutine which counts in v0 and writes it to 300, then halts
+    pub(super) fn setup() -> Chip8 {
+        let mut cpu = CPU::default();
+        cpu.flags.debug = false;
+        cpu.flags.pause = true;
+        let bus = MemoryMap::classic()
+            .bus()
+            .load_region(Program, common::SUBROUTINE);
+        Chip8 { cpu, bus }
+    }
+
+    /// Runs a command, like a frontend would, and gets its output
+    fn run(debugger: &mut Debugger, ch8: &mut Chip8, line: &str) -> String {
+        let mut out = vec![];
+        assert!(debugger.run(line, ch8, &mut out).unwrap());
+        // Run the machine until something stops it
+        while !ch8.cpu.flags.pause {
+            if let Err(e) = ch8.cpu.run_frame(&mut ch8.bus) {
+                debugger.stopped(ch8, Some(e), &mut out).unwrap();
+            } else if ch8.cpu.flags.pause {
+                debugger.stopped(ch8, None, &mut out).unwrap();
+            }
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn step() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        assert_eq!("=> 202: call   20a\n", run(&mut debugger, &mut ch8, "step"));
+        assert_eq!(
+            "=> 20c: mov    $300, I\n",
+            run(&mut debugger, &mut ch8, "s 2")
+        );
+        assert_eq!(3, ch8.cpu.cycle());
+    }
+
+    #[test]
+    fn next() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        run(&mut debugger, &mut ch8, "step");
+        assert_eq!(
+            "=> 204: add    #01, v1\n",
+            run(&mut debugger, &mut ch8, "next")
+        );
+        assert_eq!(6, ch8.cpu.v()[0]);
+        // The temporary breakpoint is gone
+        assert!(ch8.cpu.conditional_breakpoints().is_empty());
+        assert_eq!(
+            "=> 206: mov    $300, I\n",
+            run(&mut debugger, &mut ch8, "next")
+        );
+    }
+
+    #[test]
+    fn finish() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        run(&mut debugger, &mut ch8, "step 3");
+        assert_eq!(
+            "#0  => 20c: mov    $300, I\n#1  202: call   20a\n",
+            run(&mut debugger, &mut ch8, "backtrace")
+        );
+        assert_eq!(
+            "=> 204: add    #01, v1\n",
+            run(&mut debugger, &mut ch8, "finish")
+        );
+        assert_eq!(
+            "Not in a subroutine\n",
+            run(&mut debugger, &mut ch8, "finish")
+        );
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        let out = run(&mut debugger, &mut ch8, "break 0x204 if v0 == 6");
+        assert_eq!("Breakpoint 1 at 204\n", out);
+        let out = run(&mut debugger, &mut ch8, "continue");
+        assert_eq!(
+            "Continuing\nHit breakpoint 1: 204 (7101)\n=> 204: add    #01, v1\n",
+            out
+        );
+        run(&mut debugger, &mut ch8, "delete 1");
+        let out = run(&mut debugger, &mut ch8, "c");
+        assert_eq!("Continuing\n=> 212: jmp    212\n", out);
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        assert_eq!(
+            "Watchpoint 1 on change at 300..301\n",
+            run(&mut debugger, &mut ch8, "watch 0x300")
+        );
+        let out = run(&mut debugger, &mut ch8, "continue");
+        assert!(
+            out.contains("watchpoint 1 (change at 300: 00 -> 06)"),
+            "{out}"
+        );
+        assert!(out.ends_with("=> 210: ret\n"), "{out}");
+    }
+
+    #[test]
+    fn interrupt() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        let mut out = vec![];
+        debugger.run("continue", &mut ch8, &mut out).unwrap();
+        ch8.cpu.multistep(&mut ch8.bus, 3).unwrap();
+        assert_eq!(
+            "Interrupted\n=> 20c: mov    $300, I\n",
+            run(&mut debugger, &mut ch8, "interrupt")
+        );
+        assert!(ch8.cpu.flags.pause);
+        assert_eq!(
+            "The CPU isn't running\n",
+            run(&mut debugger, &mut ch8, "interrupt")
+        );
+    }
+
+    /// Breakpoints and watchpoints are numbered together, and deleted the same way
+    #[test]
+    fn delete() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        run(&mut debugger, &mut ch8, "break 0x204");
+        assert_eq!(
+            "Watchpoint 2 on change at 300..301\n",
+            run(&mut debugger, &mut ch8, "watch 0x300")
+        );
+        assert_eq!(
+            "Deleted watchpoint 2\n",
+            run(&mut debugger, &mut ch8, "delete 2")
+        );
+        assert_eq!(
+            "Deleted breakpoint 1\n",
+            run(&mut debugger, &mut ch8, "delete 1")
+        );
+        assert_eq!(
+            "No breakpoint or watchpoint 1\n",
+            run(&mut debugger, &mut ch8, "delete 1")
+        );
+    }
+
+    #[test]
+    fn memory() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        assert_eq!(
+            "200: 60 05 22 0a\n",
+            run(&mut debugger, &mut ch8, "x/4b 0x200")
+        );
+        assert_eq!(
+            "200: 6005 220a\n",
+            run(&mut debugger, &mut ch8, "x/2h $200")
+        );
+        assert_eq!(
+            "=> 200: mov    #05, v0\n   202: call   20a\n",
+            run(&mut debugger, &mut ch8, "x/2i 0x200")
+        );
+        run(&mut debugger, &mut ch8, "poke 0x300 1 #2 0x3");
+        assert_eq!(Some(&[1, 2, 3][..]), ch8.bus.get(0x300..0x303));
+        assert_eq!(
+            "300: 01 02 03 00 00 00 00 00\n308: 00 00\n",
+            run(&mut debugger, &mut ch8, "x/10b 0x300")
+        );
+    }
+
+    #[test]
+    fn registers() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        for command in ["set v3 0x10", "set I 0x401", "set pc 0x20a", "set dt 5"] {
+            run(&mut debugger, &mut ch8, command);
+        }
+        let out = run(&mut debugger, &mut ch8, "regs");
+        assert!(out.starts_with("pc: 20a  i: 401  sp: efe\n"), "{out}");
+        assert!(out.contains("v3: 10"), "{out}");
+        assert!(out.ends_with("dt: 5  st: 0  cycle: 0\n"), "{out}");
+    }
+
+    #[test]
+    fn frame() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        run(&mut debugger, &mut ch8, "frame");
+        assert_eq!(ch8.cpu.flags.speed, ch8.cpu.cycle());
+        assert!(ch8.cpu.flags.pause);
+    }
+
+    #[test]
+    fn invalid() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        for command in [
+            "jump",
+            "step x",
+            "set v3",
+            "set q 1",
+            "x/4z 0x200",
+            "break 0x200 v0",
+            // Ranges which overflow
+            "watch 0xffffffffffffffff 2",
+            "x/18446744073709551615h 0",
+            "poke 0xffffffffffffffff 1",
+        ] {
+            let result = debugger.run(command, &mut ch8, &mut vec![]);
+            assert!(
+                matches!(result, Err(Error::InvalidCommand { .. })),
+                "{command}"
+            );
+        }
+    }
+
+    /// Scripted commands wait for the machine to stop
+    #[test]
+    fn source() {
+        let (mut ch8, mut debugger) = (setup(), Debugger::new());
+        let path = std::env::temp_dir().join("chirp-debugger-source.txt");
+        std::fs::write(&path, "# Stop in the subroutine\nbreak 0x20e\n\ncontinue\n").unwrap();
+        debugger.source(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        while let Some(line) = debugger.next_scripted(&ch8) {
+            run(&mut debugger, &mut ch8, &line);
+        }
+        assert_eq!(0x20e, ch8.cpu.pc());
+        assert_eq!(&["break 0x20e", "continue"], debugger.history());
+    }
+}
+
+mod gdb {
+    use super::*;
+    use chirp::gdb::GdbStub;
+    use std::{
+        io::{Read, Write},
+        net::TcpStream,
+    };
+
+    /// Connects a client to a stub, debugging the same program as the debugger tests
+    fn setup() -> (Chip8, GdbStub, TcpStream) {
+        let mut ch8 = super::debugger::setup();
+        ch8.cpu.flags.pause = false;
+        let mut stub = GdbStub::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(stub.local_addr().unwrap()).unwrap();
+        client.set_nonblocking(true).unwrap();
+        while !stub.is_connected() {
+            assert!(stub.poll(&mut ch8).unwrap());
+        }
+        // Connecting stops the machine
+        assert!(ch8.cpu.flags.pause);
+        (ch8, stub, client)
+    }
+
+    /// Sends a packet, then waits for the reply
+    fn request(stub: &mut GdbStub, ch8: &mut Chip8, client: &mut TcpStream, data: &str) -> String {
+        let sum = data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte));
+        client
+            .write_all(format!("${data}#{sum:02x}").as_bytes())
+            .unwrap();
+        reply(stub, ch8, client)
+    }
+
+    /// Drives the stub and the machine, like a frontend would, until a reply arrives
+    fn reply(stub: &mut GdbStub, ch8: &mut Chip8, client: &mut TcpStream) -> String {
+        let mut received = vec![];
+        loop {
+            assert!(stub.poll(ch8).unwrap());
+            if !ch8.cpu.flags.pause {
+                match ch8.cpu.run_frame(&mut ch8.bus).map(|_| ()) {
+                    Err(e) => stub.stopped(ch8, Some(&e)).unwrap(),
+                    Ok(()) if ch8.cpu.flags.pause => stub.stopped(ch8, None).unwrap(),
+                    Ok(()) => {}
+                }
+            }
+            let mut chunk = [0; 1024];
+            if let Ok(len) = client.read(&mut chunk) {
+                received.extend_from_slice(&chunk[..len]);
+            }
+            let received = String::from_utf8_lossy(&received);
+            if let Some((_, packet)) = received.split_once('$') {
+                if let Some((data, sum)) = packet.split_once('#') {
+                    if sum.len() == 2 {
+                        return data.to_string();
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn registers() {
+        let (mut ch8, mut stub, mut client) = setup();
+        assert_eq!("S05", request(&mut stub, &mut ch8, &mut client, "?"));
+        // v0-vF, I, pc, sp, dt, st
+        let registers = request(&mut stub, &mut ch8, &mut client, "g");
+        assert_eq!(format!("{}00000002fe0e0000", "00".repeat(16)), registers);
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "P11=0a02"));
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "P3=2a"));
+        assert_eq!("0a02", request(&mut stub, &mut ch8, &mut client, "p11"));
+        assert_eq!((0x20a, 0x2a), (ch8.cpu.pc(), ch8.cpu.v()[3]));
+        // The stack pointer is read only
+        assert_eq!("E01", request(&mut stub, &mut ch8, &mut client, "P12=0000"));
+    }
+
+    #[test]
+    fn memory() {
+        let (mut ch8, mut stub, mut client) = setup();
+        assert_eq!(
+            "6005220a",
+            request(&mut stub, &mut ch8, &mut client, "m200,4")
+        );
+        assert_eq!(
+            "OK",
+            request(&mut stub, &mut ch8, &mut client, "M300,2:abcd")
+        );
+        assert_eq!(Some(&[0xab, 0xcd][..]), ch8.bus.get(0x300..0x302));
+        assert_eq!("E01", request(&mut stub, &mut ch8, &mut client, "m2000,4"));
+    }
+
+    #[test]
+    fn breakpoints() {
+        let (mut ch8, mut stub, mut client) = setup();
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "Z0,20e,2"));
+        assert_eq!("S05", request(&mut stub, &mut ch8, &mut client, "c"));
+        assert_eq!(0x20e, ch8.cpu.pc());
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "z0,20e,2"));
+        assert_eq!("S05", request(&mut stub, &mut ch8, &mut client, "s"));
+        assert_eq!(0x210, ch8.cpu.pc());
+        // The program halts on a jump to itself
+        assert_eq!("S05", request(&mut stub, &mut ch8, &mut client, "c"));
+        assert_eq!(0x212, ch8.cpu.pc());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let (mut ch8, mut stub, mut client) = setup();
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "Z2,300,1"));
+        assert_eq!(
+            "T05watch:300;",
+            request(&mut stub, &mut ch8, &mut client, "c")
+        );
+        assert_eq!(Some(&6), ch8.bus.get(0x300));
+        assert_eq!("OK", request(&mut stub, &mut ch8, &mut client, "z2,300,1"));
+        assert!(ch8.bus.watchpoints().is_empty());
+    }
+
+    /// Addresses and lengths which add up past the end of a usize are errors
+    #[test]
+    fn overflow() {
+        let (mut ch8, mut stub, mut client) = setup();
+        for packet in [
+            "mffffffffffffffff,2",
+            "Mffffffffffffffff,2:abcd",
+            "Z2,ffffffffffffffff,2",
+            "qXfer:features:read:target.xml:1,ffffffffffffffff",
+        ] {
+            assert_eq!("E01", request(&mut stub, &mut ch8, &mut client, packet));
+        }
+        assert!(ch8.bus.watchpoints().is_empty());
+    }
+
+    #[test]
+    fn not_ascii() {
+        let (mut ch8, mut stub, mut client) = setup();
+        client.write_all(b"$\xff#ff").unwrap();
+        assert_eq!("E01", reply(&mut stub, &mut ch8, &mut client));
+    }
+
+    #[test]
+    fn interrupt() {
+        let (mut ch8, mut stub, mut client) = setup();
+        // Ctrl-C, right after continuing
+        client.write_all(b"$c#63\x03").unwrap();
+        assert_eq!("S02", reply(&mut stub, &mut ch8, &mut client));
+        assert!(ch8.cpu.flags.pause);
+    }
+
+    #[test]
+    fn target() {
+        let (mut ch8, mut stub, mut client) = setup();
+        let supported = request(&mut stub, &mut ch8, &mut client, "qSupported:swbreak+");
+        assert!(supported.contains("qXfer:features:read+"));
+        let xml = request(
+            &mut stub,
+            &mut ch8,
+            &mut client,
+            "qXfer:features:read:target.xml:0,1000",
+        );
+        assert!(xml.starts_with("l<?xml"));
+        assert!(xml.contains(r#"<reg name="pc" bitsize="16" type="code_ptr"/>"#));
+        // Unsupported packets get an empty reply
+        assert_eq!(
+            "",
+            request(&mut stub, &mut ch8, &mut client, "vMustReplyEmpty")
+        );
+    }
+
+    #[test]
+    fn kill() {
+        let (mut ch8, mut stub, mut client) = setup();
+        client.write_all(b"$k#6b").unwrap();
+        while stub.poll(&mut ch8).unwrap() {}
+    }
+}
+
+mod trace {
+    use super::*;
+    use chirp::trace::{Format, Tracer};
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    /// Collects what a [Tracer] writes
+    #[derive(Clone, Debug, Default)]
+    struct Log(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Log {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Log {
+        fn lines(&self) -> Vec<String> {
+            let log = self.0.lock().unwrap();
+            String::from_utf8_lossy(&log)
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    /// Runs the debugger's test program to the end, while tracing
+    fn run(tracer: impl FnOnce(Log) -> Tracer) -> (Chip8, Log) {
+        let mut ch8 = super::debugger::setup();
+        let log = Log::default();
+        ch8.cpu.set_tracer(Some(tracer(log.clone())));
+        ch8.cpu.flags.pause = false;
+        while !ch8.cpu.flags.pause {
+            ch8.cpu.run_frame(&mut ch8.bus).unwrap();
+        }
+        (ch8, log)
+    }
+
+    /// Gets one of the fields of a text record
+    fn field(line: &str, index: usize) -> String {
+        line.split(' ').nth(index).unwrap().to_string()
+    }
+
+    #[test]
+    fn text() {
+        let (_, log) = run(|log| Tracer::new(log, Format::Text));
+        let lines = log.lines();
+        // Every instruction, up to the jump which halts
+        assert_eq!(10, lines.len());
+        assert_eq!(
+            "2 0202 220a v:05000000000000000000000000000000 i:0000 sp:0efe dt:00 st:00",
+            lines[1]
+        );
+    }
+
+    #[test]
+    fn json() {
+        let (_, log) = run(|log| Tracer::new(log, Format::Json));
+        // `dma v0, I` in the subroutine
+        assert_eq!(
+            r#"{"cycle":5,"pc":526,"opcode":"f055","v":[6,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0],"i":768,"sp":3836,"dt":0,"st":0}"#,
+            log.lines()[4]
+        );
+    }
+
+    #[test]
+    fn filters() {
+        // Only the subroutine
+        let (_, log) = run(|log| Tracer::new(log, Format::Text).within(0x20a..0x212));
+        let pcs: Vec<String> = log.lines().iter().map(|line| field(line, 1)).collect();
+        assert_eq!(vec!["020a", "020c", "020e", "0210"], pcs);
+        // Only the third and fourth cycles
+        let (_, log) = run(|log| Tracer::new(log, Format::Text).during(3..5));
+        let cycles: Vec<String> = log.lines().iter().map(|line| field(line, 0)).collect();
+        assert_eq!(vec!["3", "4"], cycles);
+    }
+
+    #[test]
+    fn flight_recorder() {
+        let mut ch8 = super::debugger::setup();
+        // Replace the halt with an invalid instruction
+        chirp::Write::write(&mut ch8.bus, 0x212u16, 0xffffu16);
+        let log = Log::default();
+        let tracer = Tracer::new(log.clone(), Format::Text).flight_recorder(3);
+        ch8.cpu.set_tracer(Some(tracer));
+        ch8.cpu.flags.pause = false;
+        ch8.cpu.multistep(&mut ch8.bus, 9).unwrap();
+        // Nothing's written until something goes wrong
+        assert!(log.lines().is_empty());
+        assert!(ch8.cpu.multistep(&mut ch8.bus, 1).is_err());
+        let pcs: Vec<String> = log.lines().iter().map(|line| field(line, 1)).collect();
+        assert_eq!(vec!["0206", "0208", "0212"], pcs);
+    }
+
+    /// Breakpoints only pause the CPU, so they don't dump the flight recorder
+    #[test]
+    fn flight_recorder_breakpoint() {
+        let mut ch8 = super::debugger::setup();
+        let log = Log::default();
+        let tracer = Tracer::new(log.clone(), Format::Text).flight_recorder(3);
+        ch8.cpu.set_tracer(Some(tracer));
+        ch8.cpu.set_break(0x20e);
+        ch8.cpu.flags.pause = false;
+        assert!(ch8.cpu.multistep(&mut ch8.bus, 9).is_err());
+        assert_eq!(0x20e, ch8.cpu.pc());
+        assert!(log.lines().is_empty());
+    }
 }
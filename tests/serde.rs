@@ -0,0 +1,77 @@
+//! Testing serde support, with `--features serde`
+#![cfg(feature = "serde")]
+use chirp::{bus::Region, cpu::disassembler::Insn, *};
+
+mod common;
+use common::counter;
+
+/// A deserialized machine runs exactly like the original
+#[test]
+fn same_future() {
+    let mut ch8 = counter();
+    ch8.cpu.multistep(&mut ch8.bus, 37).unwrap();
+    let json = serde_json::to_string(&ch8).unwrap();
+    let mut other: Chip8 = serde_json::from_str(&json).unwrap();
+    assert_eq!(ch8.save_state(), other.save_state());
+    ch8.cpu.multistep(&mut ch8.bus, 100).unwrap();
+    other.cpu.multistep(&mut other.bus, 100).unwrap();
+    assert_eq!(ch8.save_state(), other.save_state());
+}
+
+#[test]
+fn field_names() {
+    let json = serde_json::to_value(counter()).unwrap();
+    assert_eq!(1234, json["cpu"]["rng"]);
+    assert_eq!(8, json["cpu"]["flags"]["speed"]);
+    assert_eq!("Chip8", json["cpu"]["flags"]["mode"]);
+    assert_eq!(true, json["bus"]["strict"]);
+    assert_eq!(
+        serde_json::json!({ "jmp": { "A": 0x200 } }),
+        serde_json::to_value(Insn::jmp { A: 0x200 }).unwrap()
+    );
+}
+
+/// Breakpoints and watchpoints are serialized, with conditions written out as text
+#[test]
+fn breakpoints() {
+    use chirp::cpu::breakpoint::*;
+    let mut ch8 = counter();
+    let condition = "v0 >= 2 || cycle > 100".parse().unwrap();
+    ch8.cpu
+        .add_breakpoint(Breakpoint::new(0x202).when(condition));
+    ch8.cpu
+        .add_watchpoint(&mut ch8.bus, Watchpoint::new(0x300..0x400, Watch::Change));
+    let json = serde_json::to_value(&ch8).unwrap();
+    assert_eq!(
+        "v0 >= 0x2 || cycle > 0x64",
+        json["cpu"]["conditional"][0]["condition"]
+    );
+    assert_eq!("Change", json["bus"]["watchpoints"][0]["watch"]);
+    let other: Chip8 = serde_json::from_value(json).unwrap();
+    assert_eq!(
+        ch8.cpu.conditional_breakpoints(),
+        other.cpu.conditional_breakpoints()
+    );
+    assert_eq!(ch8.save_state(), other.save_state());
+}
+
+/// Flags can be read from a config file
+#[test]
+fn flags_from_config() {
+    let flags = Flags {
+        mode: Mode::SChip,
+        quirks: Mode::SChip.into(),
+        speed: 20,
+        ..Default::default()
+    };
+    let config = serde_json::to_string_pretty(&flags).unwrap();
+    assert_eq!(flags, serde_json::from_str(&config).unwrap());
+}
+
+#[test]
+fn region() {
+    for region in Region::ALL {
+        let json = serde_json::to_string(&region).unwrap();
+        assert_eq!(region, serde_json::from_str(&json).unwrap());
+    }
+}